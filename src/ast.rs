@@ -9,8 +9,8 @@ mod macros;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Filter {
-    stmts: Stmts,
-    expr: Expr,
+    pub(crate) stmts: Stmts,
+    pub(crate) expr: Expr,
 }
 
 impl Filter {
@@ -27,8 +27,8 @@ impl Display for Filter {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Module {
-    stmts: Stmts,
-    decls: Vec<ExprFnDecl>,
+    pub(crate) stmts: Stmts,
+    pub(crate) decls: Vec<ExprFnDecl>,
 }
 
 impl Module {
@@ -47,8 +47,8 @@ impl Display for Module {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Stmts {
-    module: Option<Expr>,
-    stmts: Vec<Stmt>,
+    pub(crate) module: Option<Expr>,
+    pub(crate) stmts: Vec<Stmt>,
 }
 
 impl Stmts {
@@ -93,9 +93,9 @@ impl Display for Stmt {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct StmtImportMod {
-    file: PathBuf,
-    path: IdentPath,
-    metadata: Option<Expr>,
+    pub(crate) file: PathBuf,
+    pub(crate) path: IdentPath,
+    pub(crate) metadata: Option<Expr>,
 }
 
 impl StmtImportMod {
@@ -121,9 +121,9 @@ impl Display for StmtImportMod {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct StmtImportToml {
-    file: PathBuf,
-    variable: Variable,
-    metadata: Option<Expr>,
+    pub(crate) file: PathBuf,
+    pub(crate) variable: Variable,
+    pub(crate) metadata: Option<Expr>,
 }
 
 impl StmtImportToml {
@@ -149,8 +149,8 @@ impl Display for StmtImportToml {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct StmtInclude {
-    file: PathBuf,
-    metadata: Option<Expr>,
+    pub(crate) file: PathBuf,
+    pub(crate) metadata: Option<Expr>,
 }
 
 impl StmtInclude {
@@ -186,7 +186,8 @@ pub enum Expr {
     /// `[1, 2, 3, 4]`, `[map(. + 1)]`
     Array(Option<Box<Expr>>),
     /// `{ foo = "bar", baz = 5 }`
-    Table(Vec<(TableKey, Expr)>),
+    /// `{ name, version, $x, "a b", (.k) }`
+    Table(Vec<(TableKey, Option<Expr>)>),
 
     /// `-12`
     /// `!15.0`
@@ -253,7 +254,10 @@ impl Display for Expr {
             Expr::Table(ref table) => {
                 let table: Vec<_> = table
                     .iter()
-                    .map(|(k, v)| format!("{} = {}", k, v))
+                    .map(|(k, v)| match v {
+                        Some(v) => format!("{} = {}", k, v),
+                        None => k.to_string(),
+                    })
                     .collect();
                 write!(fmt, "{{{}}}", table.join(", "))
             }
@@ -311,6 +315,7 @@ pub enum ExprFilter {
     Recurse,
 
     /// `foo`
+    /// `"docs.rs"`
     Field(Ident),
     /// `$blah`
     Variable(Variable),
@@ -327,7 +332,8 @@ impl Display for ExprFilter {
         match *self {
             ExprFilter::Identity => fmt.write_str("."),
             ExprFilter::Recurse => fmt.write_str(".."),
-            ExprFilter::Field(ref ident) => write!(fmt, ".{}", ident),
+            ExprFilter::Field(ref ident) if ident.is_bare() => write!(fmt, ".{}", ident),
+            ExprFilter::Field(ref ident) => write!(fmt, ".{}", Literal::from(ident.to_string())),
             ExprFilter::Variable(ref var) => write!(fmt, "{}", var),
             ExprFilter::Index(ref index) => write!(fmt, ".{}", index),
             ExprFilter::Path(ref lhs, ref rhs) => match **rhs {
@@ -416,8 +422,8 @@ impl Display for BinaryOp {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExprBinding {
-    expr: Expr,
-    pattern: ExprPattern,
+    pub(crate) expr: Expr,
+    pub(crate) pattern: ExprPattern,
 }
 
 impl ExprBinding {
@@ -451,17 +457,20 @@ impl Display for ExprIndex {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ExprSlice {
+    /// `[2:]`
     Lower(Expr),
+    /// `[:3]`
     Upper(Expr),
+    /// `[1:4]`
     Range(Expr, Expr),
 }
 
 impl Display for ExprSlice {
     fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
         match *self {
-            ExprSlice::Lower(ref bound) => write!(fmt, ":{}", bound),
-            ExprSlice::Upper(ref bound) => write!(fmt, "{}:", bound),
-            ExprSlice::Range(ref upper, ref lower) => write!(fmt, "{}:{}", upper, lower),
+            ExprSlice::Lower(ref bound) => write!(fmt, "{}:", bound),
+            ExprSlice::Upper(ref bound) => write!(fmt, ":{}", bound),
+            ExprSlice::Range(ref lower, ref upper) => write!(fmt, "{}:{}", lower, upper),
         }
     }
 }
@@ -494,8 +503,8 @@ impl Display for ExprPattern {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExprFnCall {
-    path: IdentPath,
-    args: Vec<Expr>,
+    pub(crate) path: IdentPath,
+    pub(crate) args: Vec<Expr>,
 }
 
 impl ExprFnCall {
@@ -517,9 +526,9 @@ impl Display for ExprFnCall {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExprFnDecl {
-    name: IdentPath,
-    params: Vec<FnParam>,
-    body: Expr,
+    pub(crate) name: IdentPath,
+    pub(crate) params: Vec<FnParam>,
+    pub(crate) body: Expr,
 }
 
 impl ExprFnDecl {
@@ -543,9 +552,9 @@ impl Display for ExprFnDecl {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExprIfElse {
-    main_clause: (Expr, Expr),
-    alt_clauses: Vec<(Expr, Expr)>,
-    fallback: Expr,
+    pub(crate) main_clause: (Expr, Expr),
+    pub(crate) alt_clauses: Vec<(Expr, Expr)>,
+    pub(crate) fallback: Expr,
 }
 
 impl ExprIfElse {
@@ -577,9 +586,9 @@ impl Display for ExprIfElse {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExprReduce {
-    binding: ExprBinding,
-    acc: Expr,
-    eval: Expr,
+    pub(crate) binding: ExprBinding,
+    pub(crate) acc: Expr,
+    pub(crate) eval: Expr,
 }
 
 impl ExprReduce {
@@ -596,10 +605,10 @@ impl Display for ExprReduce {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExprForeach {
-    binding: ExprBinding,
-    init: Expr,
-    update: Expr,
-    extract: Expr,
+    pub(crate) binding: ExprBinding,
+    pub(crate) init: Expr,
+    pub(crate) update: Expr,
+    pub(crate) extract: Expr,
 }
 
 impl ExprForeach {
@@ -625,8 +634,8 @@ impl Display for ExprForeach {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExprTry {
-    expr: Expr,
-    fallback: Option<Expr>,
+    pub(crate) expr: Expr,
+    pub(crate) fallback: Option<Expr>,
 }
 
 impl ExprTry {
@@ -267,29 +267,47 @@ macro_rules! tq_construct {
     };
 
     (@member ($dollar:tt $var:ident = $($value:tt)+) , $($rest:tt)+) => {{
-        let member = ($crate::tq_table_key!($dollar$var), $crate::tq_table_value!($($value)+));
+        let member = ($crate::tq_table_key!($dollar$var), Some($crate::tq_table_value!($($value)+)));
         ::std::iter::once(member).chain($crate::tq_construct!(@table $($rest)+))
     }};
 
     (@member ($key:tt = $($value:tt)+) , $($rest:tt)+) => {{
-        let member = ($crate::tq_table_key!($key), $crate::tq_table_value!($($value)+));
+        let member = ($crate::tq_table_key!($key), Some($crate::tq_table_value!($($value)+)));
         ::std::iter::once(member).chain($crate::tq_construct!(@table $($rest)+))
     }};
 
     (@member ($dollar:tt $var:ident = $($value:tt)+)) => {
         ::std::iter::once((
             $crate::tq_table_key!($dollar$var),
-            $crate::tq_table_value!($($value)+),
+            Some($crate::tq_table_value!($($value)+)),
         ))
     };
 
     (@member ($key:tt = $($value:tt)+)) => {
         ::std::iter::once((
             $crate::tq_table_key!($key),
-            $crate::tq_table_value!($($value)+),
+            Some($crate::tq_table_value!($($value)+)),
         ))
     };
 
+    (@member ($dollar:tt $var:ident) , $($rest:tt)+) => {{
+        let member = ($crate::tq_table_key!($dollar$var), None);
+        ::std::iter::once(member).chain($crate::tq_construct!(@table $($rest)+))
+    }};
+
+    (@member ($key:tt) , $($rest:tt)+) => {{
+        let member = ($crate::tq_table_key!($key), None);
+        ::std::iter::once(member).chain($crate::tq_construct!(@table $($rest)+))
+    }};
+
+    (@member ($dollar:tt $var:ident)) => {
+        ::std::iter::once(($crate::tq_table_key!($dollar$var), None))
+    };
+
+    (@member ($key:tt)) => {
+        ::std::iter::once(($crate::tq_table_key!($key), None))
+    };
+
     (@member ($($members:tt)+) $next:tt $($rest:tt)*) => {
         $crate::tq_construct!(@member ($($members)+ $next) $($rest)*)
     };
@@ -368,8 +386,8 @@ macro_rules! tq_index {
         ExprIndex::Iter
     };
 
-    ( $upper:tt:$($lower:tt)+ ) => {
-        ExprIndex::Slice(ExprSlice::Range($crate::tq_expr!($($upper)+), $crate::tq_expr!($($lower)+)))
+    ( $lower:tt:$($upper:tt)+ ) => {
+        ExprIndex::Slice(ExprSlice::Range($crate::tq_expr!($lower), $crate::tq_expr!($($upper)+)))
     };
 
     ( $expr:tt: ) => {
@@ -398,6 +416,16 @@ macro_rules! tq_filter {
         $crate::tq_filter!(@path .$field).chain($crate::tq_filter!(@path $($rest)+))
     };
 
+    // Final path segment is a quoted field.
+    (@path .$key:literal) => {
+        ::std::iter::once($crate::tq_filter!(.$key))
+    };
+
+    // Next path segment is a quoted field.
+    (@path .$key:literal $($rest:tt)+) => {
+        $crate::tq_filter!(@path .$key).chain($crate::tq_filter!(@path $($rest)+))
+    };
+
     // Final path segment is a slice-style field.
     (@path [$($expr:tt)*]) => {
         ::std::iter::once($crate::tq_filter!(.[$($expr)*]))
@@ -423,6 +451,11 @@ macro_rules! tq_filter {
         ExprFilter::Field($crate::tq_token!($field))
     };
 
+    // Single quoted field path.
+    ( .$key:literal ) => {
+        ExprFilter::Field($crate::ast::tokens::Ident::from($key))
+    };
+
     // Single slice path.
     ( .[$($expr:tt)*] ) => {
         ExprFilter::Index(Box::new($crate::tq_index!($($expr)*)))
@@ -436,6 +469,14 @@ macro_rules! tq_filter {
             })
     };
 
+    // Nested path beginning with a quoted field access.
+    ( .$key:literal $($rest:tt)+ ) => {
+        $crate::tq_filter!(@path $($rest)+)
+            .fold($crate::tq_filter!(.$key), |seq, next| {
+                ExprFilter::Path(Box::new(seq), Box::new(next))
+            })
+    };
+
     // Nested path beginning with a slice-style field access.
     ( .[$($expr:tt)*] $($rest:tt)+ ) => {
         $crate::tq_filter!(@path $($rest)+)
@@ -690,7 +731,7 @@ macro_rules! fn_decl {
     };
 
     (@rule ($($expr:tt)+)) => {
-        $crate::assign!($($expr)+)
+        $crate::alternative!($($expr)+)
     };
 
     ( $first:tt $($rest:tt)* ) => {{
@@ -702,6 +743,35 @@ macro_rules! fn_decl {
     }};
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! alternative {
+    // Note that the alt (`//`) operator is separated by a space in this macro because it also
+    // happens to be the comment token in Rust. The `tq_expr_and_str!()` macro will replace these
+    // occurrences with the correct `//` form in the output string.
+    (@rule ($($lhs:tt)+) / / $($rhs:tt)+) => {{
+        let lhs = $crate::assign!($($lhs)+);
+        let rhs = $crate::alternative!($($rhs)+);
+        Expr::Binary(BinaryOp::Alt, Box::new(lhs), Box::new(rhs))
+    }};
+
+    (@rule ($($prev:tt)*) $next:tt $($rest:tt)*) => {
+        $crate::alternative!(@rule ($($prev)* $next) $($rest)*)
+    };
+
+    (@rule ($($expr:tt)+)) => {
+        $crate::assign!($($expr)+)
+    };
+
+    ( $first:tt $($rest:tt)* ) => {{
+        #[allow(unused_imports)]
+        use $crate::ast::*;
+        #[allow(unused_imports)]
+        use $crate::ast::tokens::*;
+        $crate::alternative!(@rule ($first) $($rest)*)
+    }};
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! assign {
@@ -840,15 +910,6 @@ macro_rules! sum {
 #[doc(hidden)]
 #[macro_export]
 macro_rules! product {
-    // Note that the alt (`//`) operator is separated by a space in this macro because it also
-    // happens to be the comment token in Rust. The `tq_expr_and_str!()` macro will replace these
-    // occurrences with the correct `//` form in the output string.
-    (@rule ($($lhs:tt)+) / / $($rhs:tt)+) => {{
-        let lhs = $crate::try_postfix!($($lhs)+);
-        let rhs = $crate::product!($($rhs)+);
-        Expr::Binary(BinaryOp::Alt, Box::new(lhs), Box::new(rhs))
-    }};
-
     (@rule ($($lhs:tt)+) * $($rhs:tt)+) => {{
         let lhs = $crate::try_postfix!($($lhs)+);
         let rhs = $crate::product!($($rhs)+);
@@ -3,7 +3,22 @@ use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use toml::value::Datetime;
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
-pub struct Ident(String);
+pub struct Ident(pub(crate) String);
+
+impl Ident {
+    /// Returns `true` if this identifier can be written as a bare key without quotes.
+    ///
+    /// Keys such as `docs.rs`, `a b`, or `2015` must be quoted when used in a field access, e.g.
+    /// `."docs.rs"`.
+    pub fn is_bare(&self) -> bool {
+        let mut chars = self.0.chars();
+        let is_valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        match chars.next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(is_valid),
+            _ => false,
+        }
+    }
+}
 
 impl<'a> From<&'a str> for Ident {
     fn from(s: &'a str) -> Self {
@@ -24,7 +39,7 @@ impl Display for Ident {
 }
 
 #[derive(Clone, PartialEq, PartialOrd)]
-pub struct IdentPath(Vec<Ident>);
+pub struct IdentPath(pub(crate) Vec<Ident>);
 
 impl<T, U> From<U> for IdentPath
 where
@@ -52,7 +67,7 @@ impl Display for IdentPath {
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
-pub struct Label(Variable);
+pub struct Label(pub(crate) Variable);
 
 impl<T: Into<Variable>> From<T> for Label {
     fn from(variable: T) -> Self {
@@ -67,7 +82,7 @@ impl Display for Label {
 }
 
 #[derive(Clone, PartialEq, PartialOrd)]
-pub struct Variable(Ident);
+pub struct Variable(pub(crate) Ident);
 
 impl<T: Into<Ident>> From<T> for Variable {
     fn from(ident: T) -> Self {
@@ -143,9 +158,28 @@ impl Display for Literal {
             Literal::Datetime(ref dt) => write!(fmt, "{}", dt),
             Literal::Float(ref f) => write!(fmt, "{}", f),
             Literal::Integer(ref i) => write!(fmt, "{}", i),
-            Literal::String(ref s) => write!(fmt, "\"{}\"", s),
+            Literal::String(ref s) => write!(fmt, "\"{}\"", escape(s)),
+        }
+    }
+}
+
+/// Escapes a string so it may be safely written between double quotes.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\x08' => escaped.push_str("\\b"),
+            '\x0C' => escaped.push_str("\\f"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+            c => escaped.push(c),
         }
     }
+    escaped
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
@@ -0,0 +1,1256 @@
+//! Format-preserving TOML documents.
+//!
+//! A [`Document`](./struct.Document.html) remembers the exact text it was parsed from. Filters
+//! which update their input, e.g. `.package.version = "1.2.3"` or `del(.dependencies.log)`, are
+//! written back by applying only the changes they made to that text, so that comments, blank
+//! lines, key order and number formats outside of the changed values are kept byte-for-byte.
+//!
+//! Comments are exposed to filters as [`Comments`](./struct.Comments.html), keyed by the paths of
+//! the values they are attached to, and changes to them are written back the same way. Tables and
+//! arrays keep the style they were written in, unless a filter sets another one with
+//! [`Styles`](../output/toml/struct.Styles.html).
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+
+use toml_edit::{Decor, DocumentMut, ImDocument, Item, RawString, TableLike, TomlError};
+
+use crate::output::toml::{Style, Styles};
+use crate::value::{path, Table, Value};
+
+/// A TOML document which can be written back with its formatting intact.
+#[derive(Clone, Debug)]
+pub struct Document {
+    doc: DocumentMut,
+    value: Value,
+    locations: Locations,
+}
+
+impl Document {
+    /// Returns the contents of the document, as evaluated by filters.
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Returns where each value of the document was written in the text it was parsed from.
+    pub fn locations(&self) -> &Locations {
+        &self.locations
+    }
+
+    /// Returns the text of the document after its contents were replaced with `value`.
+    ///
+    /// Only the parts of the document which differ from `value` are rewritten.
+    pub fn update(&self, value: &Value) -> Result<String, String> {
+        self.update_with(value, &Comments::default(), &Styles::default())
+    }
+
+    /// Like [`update`](#method.update), but also rewrites the comments changed in `comments`, and
+    /// the tables and arrays given another style in `styles`.
+    pub fn update_with(
+        &self,
+        value: &Value,
+        comments: &Comments,
+        styles: &Styles,
+    ) -> Result<String, String> {
+        let (old, new) = match (&self.value, value) {
+            (Value::Table(ref old), Value::Table(ref new)) => (old, new),
+            (_, value) => return Err(format!("Cannot write {} as a document", value.type_name())),
+        };
+
+        let mut doc = self.doc.clone();
+        patch_table(doc.as_table_mut(), old, new, 0)?;
+        for (path, style) in styles.iter() {
+            if path::get(value, path).is_ok_and(|v| style.fits(&v)) {
+                restyle(doc.as_table_mut(), path, style)
+                    .map_err(|msg| format!("{} at {}", msg, Value::Array(path.to_vec())))?;
+            }
+        }
+        for (path, comment) in &comments.changes {
+            let mut set = |mut line: Line| line.set_comment(comment.as_deref());
+            if with_line(doc.as_table_mut(), path, None, &mut set).is_none() {
+                let path = Value::Array(path.clone());
+                return Err(format!("Cannot attach a comment to the value at {}", path));
+            }
+        }
+        Ok(doc.to_string())
+    }
+
+    /// Returns the comments attached to the values of the document.
+    pub fn comments(&self) -> Comments {
+        fn collect(
+            doc: &mut DocumentMut,
+            path: &mut Vec<Value>,
+            value: &Value,
+            comments: &mut Comments,
+        ) {
+            let children: Vec<(Value, &Value)> = match *value {
+                Value::Array(ref array) => array
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (Value::Integer(i as i64), v))
+                    .collect(),
+                Value::Table(ref table) => table
+                    .iter()
+                    .map(|(k, v)| (Value::String(k.clone()), v))
+                    .collect(),
+                _ => return,
+            };
+
+            for (key, child) in children {
+                path.push(key);
+                let comment = with_line(doc.as_table_mut(), path, None, &mut |line| line.comment());
+                if let Some(comment) = comment.flatten() {
+                    comments.comments.insert(Comments::key(path), comment);
+                }
+                collect(doc, path, child, comments);
+                path.pop();
+            }
+        }
+
+        let mut doc = self.doc.clone();
+        let mut comments = Comments::default();
+        collect(&mut doc, &mut Vec::new(), &self.value, &mut comments);
+        comments
+    }
+
+    /// Sorts the keys of every table in the document, moving `[table]` sections to match.
+    pub fn sort_keys(&mut self) {
+        let root = self.doc.as_table_mut();
+        sort_table(root, &mut 0);
+        self.value.sort_keys();
+
+        // The section now written first may have been preceded by a blank line.
+        if !root.iter().any(|(_, item)| item.is_value()) {
+            if let Some(decor) = first_section(root).map(|t| t.decor_mut()) {
+                let prefix = decor.prefix().and_then(|p| p.as_str()).unwrap_or("");
+                let prefix = prefix.trim_start_matches(['\r', '\n']).to_owned();
+                decor.set_prefix(prefix);
+            }
+        }
+    }
+}
+
+/// The comments attached to the values of a document, which filters can read and change.
+///
+/// The comments of a value are the one at the end of its line, and those on the lines directly
+/// above it. Only key/value pairs, `[table]` headers and `[[array]]` elements have lines of their
+/// own, so other values cannot have comments.
+#[derive(Clone, Debug, Default)]
+pub struct Comments {
+    /// The comments of each value, keyed by its path written as an array.
+    comments: HashMap<String, String>,
+    /// The comments set or deleted since the document was read, in order.
+    changes: Vec<(Vec<Value>, Option<String>)>,
+}
+
+impl Comments {
+    /// Returns the comments of the value at `path`, one per line, if it has any.
+    pub fn get(&self, path: &[Value]) -> Option<&str> {
+        self.comments.get(&Comments::key(path)).map(String::as_str)
+    }
+
+    /// Replaces the comments of the value at `path`, or deletes them if `comment` is `None`.
+    pub fn set(&mut self, path: &[Value], comment: Option<String>) {
+        match comment {
+            Some(ref comment) => self.comments.insert(Comments::key(path), comment.clone()),
+            None => self.comments.remove(&Comments::key(path)),
+        };
+        self.changes.push((path.to_vec(), comment));
+    }
+
+    fn key(path: &[Value]) -> String {
+        Value::Array(path.to_vec()).to_string()
+    }
+}
+
+/// The line and column at which a value was written, both counted from 1.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where the values of a document were written, keyed by their paths.
+///
+/// Key/value pairs are located by their key, tables by their header if they have one, and
+/// elements of arrays by their first character.
+#[derive(Clone, Debug, Default)]
+pub struct Locations {
+    locations: HashMap<String, Location>,
+}
+
+impl Locations {
+    /// Returns where the value at `path` was written, if it was read from the document.
+    pub fn get(&self, path: &[Value]) -> Option<Location> {
+        self.locations.get(&Comments::key(path)).copied()
+    }
+
+    /// Moves every location down by `lines`, for documents which start further down their file.
+    fn offset(&mut self, lines: usize) {
+        for location in self.locations.values_mut() {
+            location.line += lines;
+        }
+    }
+
+    /// Collects the locations of the values of the document parsed from `text`.
+    fn collect(doc: &ImDocument<&str>, text: &str) -> Locations {
+        let lines = Lines::new(text);
+        let mut locations = Locations::default();
+        locations.visit_table(doc.as_table(), &mut Vec::new(), &lines);
+        locations
+    }
+
+    fn visit_table(&mut self, table: &toml_edit::Table, path: &mut Vec<Value>, lines: &Lines) {
+        for (key, item) in table.iter() {
+            path.push(Value::from(key));
+            let span = match *item {
+                Item::Table(ref inner) if !inner.is_implicit() && !inner.is_dotted() => {
+                    inner.span()
+                }
+                Item::ArrayOfTables(ref array) => array.get(0).and_then(|t| t.span()),
+                _ => table.key(key).and_then(|k| k.span()),
+            };
+            self.insert(path, span, lines);
+
+            match *item {
+                Item::Table(ref inner) => self.visit_table(inner, path, lines),
+                Item::Value(ref value) => self.visit_value(value, path, lines),
+                Item::ArrayOfTables(ref array) => {
+                    for (i, inner) in array.iter().enumerate() {
+                        path.push(Value::Integer(i as i64));
+                        self.insert(path, inner.span(), lines);
+                        self.visit_table(inner, path, lines);
+                        path.pop();
+                    }
+                }
+                Item::None => {}
+            }
+            path.pop();
+        }
+    }
+
+    fn visit_value(&mut self, value: &toml_edit::Value, path: &mut Vec<Value>, lines: &Lines) {
+        match *value {
+            toml_edit::Value::InlineTable(ref table) => {
+                for (key, value) in table.iter() {
+                    path.push(Value::from(key));
+                    self.insert(path, table.key(key).and_then(|k| k.span()), lines);
+                    self.visit_value(value, path, lines);
+                    path.pop();
+                }
+            }
+            toml_edit::Value::Array(ref array) => {
+                for (i, value) in array.iter().enumerate() {
+                    path.push(Value::Integer(i as i64));
+                    self.insert(path, value.span(), lines);
+                    self.visit_value(value, path, lines);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Records the location of the value at `path`, if it was parsed from the text.
+    fn insert(&mut self, path: &[Value], span: Option<Range<usize>>, lines: &Lines) {
+        if let Some(span) = span {
+            let location = lines.locate(span.start);
+            self.locations.insert(Comments::key(path), location);
+        }
+    }
+}
+
+/// The lines of a text, to find the line and column of an offset in it.
+pub(crate) struct Lines<'a> {
+    text: &'a str,
+    /// The offset at which each line starts.
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        let starts = Some(0)
+            .into_iter()
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Lines { text, starts }
+    }
+
+    /// Returns the line and column of the character at `offset`.
+    pub(crate) fn locate(&self, offset: usize) -> Location {
+        let line = self.starts.partition_point(|&start| start <= offset);
+        let start = self.starts[line - 1];
+        let column = self.text[start..offset].chars().count() + 1;
+        Location { line, column }
+    }
+}
+
+impl Document {
+    /// Parses a document read from `file`, starting on the line `first_line` of the file.
+    ///
+    /// Errors point at the line and column at which they occur, and underline the offending key
+    /// of duplicate keys and tables, e.g.
+    ///
+    /// ```text
+    /// duplicate key `x` in document root
+    ///  --> Cargo.toml:4:2
+    ///   |
+    /// 4 | [x]
+    ///   |  ^
+    /// ```
+    pub fn parse_file(text: &str, file: &str, first_line: usize) -> Result<Document, String> {
+        let doc = ImDocument::parse(text).map_err(|e| diagnostic(text, file, first_line, &e))?;
+        let mut doc = Document::new(doc, text)?;
+        doc.locations.offset(first_line - 1);
+        Ok(doc)
+    }
+
+    fn new(doc: ImDocument<&str>, text: &str) -> Result<Document, String> {
+        let value = from_item(doc.as_item())?;
+        let locations = Locations::collect(&doc, text);
+        Ok(Document {
+            doc: doc.into_mut(),
+            value,
+            locations,
+        })
+    }
+}
+
+impl FromStr for Document {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let doc = ImDocument::parse(s).map_err(|e| e.to_string())?;
+        Document::new(doc, s)
+    }
+}
+
+/// Describes a parse error in the document `text`, read from `file`, with the offending line.
+pub(crate) fn diagnostic(text: &str, file: &str, first_line: usize, error: &TomlError) -> String {
+    let message = error.message().trim_end().replace('\n', ": ");
+    let span = match error.span() {
+        Some(span) => offending_key(text, &span, error.message()).unwrap_or(span),
+        None => return message,
+    };
+    annotate(&message, text, file, first_line, span)
+}
+
+/// Describes an error at `span` of the document `text`, read from `file` from its line
+/// `first_line` on, with the offending line.
+pub(crate) fn annotate(
+    message: &str,
+    text: &str,
+    file: &str,
+    first_line: usize,
+    span: Range<usize>,
+) -> String {
+    let lines = Lines::new(text);
+    let location = lines.locate(span.start);
+    let start = lines.starts[location.line - 1];
+    let content = text[start..].lines().next().unwrap_or("");
+    let before = &text[start..span.start];
+    let len = text[span.start..span.end.min(start + content.len())]
+        .chars()
+        .count();
+
+    let line = location.line + first_line - 1;
+    let gutter = " ".repeat(line.to_string().len());
+    // Tabs are kept so that the carets line up with the line above them.
+    let padding: String = before
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!(
+        "{}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+        message,
+        gutter,
+        file,
+        line,
+        location.column,
+        gutter,
+        line,
+        content,
+        gutter,
+        padding,
+        "^".repeat(len.max(1)),
+    )
+}
+
+/// Returns the span of the key named by a duplicate key error, on the line at which it occurs.
+fn offending_key(text: &str, span: &Range<usize>, message: &str) -> Option<Range<usize>> {
+    let (key, _) = message.split("duplicate key `").nth(1)?.rsplit_once('`')?;
+    let end = text[span.start..]
+        .find('\n')
+        .map_or(text.len(), |i| span.start + i);
+    let line = &text[span.start..end];
+
+    let is_bare =
+        |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric() || "-_".contains(c));
+    let (i, _) = line
+        .match_indices(key)
+        .filter(|&(i, _)| {
+            !is_bare(line[..i].chars().next_back())
+                && !is_bare(line[i + key.len()..].chars().next())
+        })
+        .last()?;
+    Some(span.start + i..span.start + i + key.len())
+}
+
+fn from_item(item: &Item) -> Result<Value, String> {
+    match *item {
+        Item::None => Ok(Value::Null),
+        Item::Value(ref value) => from_value(value),
+        Item::Table(ref table) => from_table(table),
+        Item::ArrayOfTables(ref array) => array
+            .iter()
+            .map(|t| from_table(t))
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+    }
+}
+
+pub(crate) fn from_table(table: &dyn TableLike) -> Result<Value, String> {
+    table
+        .iter()
+        .map(|(k, v)| from_item(v).map(|v| (k.to_owned(), v)))
+        .collect::<Result<_, _>>()
+        .map(Value::Table)
+}
+
+fn from_value(value: &toml_edit::Value) -> Result<Value, String> {
+    use toml_edit::Value as V;
+    match *value {
+        V::String(ref s) => Ok(Value::String(s.value().clone())),
+        V::Integer(ref i) => Ok(Value::Integer(*i.value())),
+        V::Float(ref f) => Ok(Value::Float(*f.value())),
+        V::Boolean(ref b) => Ok(Value::Boolean(*b.value())),
+        V::Datetime(ref dt) => dt
+            .value()
+            .to_string()
+            .parse()
+            .map(Value::Datetime)
+            .map_err(|_| format!("Unsupported datetime {}", dt.value())),
+        V::Array(ref array) => array
+            .iter()
+            .map(from_value)
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        V::InlineTable(ref table) => from_table(table),
+    }
+}
+
+/// The decorations of the line a value is written on.
+enum Line<'a> {
+    /// A `[table]` header or `[[array]]` element, decorated before and after its brackets.
+    Header(&'a mut Decor),
+    /// A key/value pair, whose key is decorated with the lines above it and its value with the
+    /// rest of the line after it.
+    KeyValue(&'a mut Decor, &'a mut Decor),
+}
+
+impl<'a> Line<'a> {
+    /// Returns the comments above this line and at the end of it, if there are any.
+    fn comment(&self) -> Option<String> {
+        let (above, end) = self.text();
+        let (_, run, _) = split_comments(&above);
+        let mut lines: Vec<_> = run.lines().map(comment_text).collect();
+        if end.trim_start().starts_with('#') {
+            lines.push(comment_text(&end));
+        }
+
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+
+    /// Replaces the comments above this line and at the end of it.
+    ///
+    /// A comment of a single line is written at the end of this line, and a longer one above it.
+    fn set_comment(&mut self, comment: Option<&str>) {
+        let (above, suffix) = self.text();
+        let (head, _, indent) = split_comments(&above);
+        // An existing comment at the end of the line keeps its alignment.
+        let gap = match suffix.find('#') {
+            Some(i) if i > 0 => &suffix[..i],
+            _ => " ",
+        };
+        let mut above = head.to_string();
+        let mut end = String::new();
+        match comment {
+            Some(comment) if comment.contains('\n') => {
+                for line in comment.lines() {
+                    let line = format!("# {}", line);
+                    above.push_str(&format!("{}{}\n", indent, line.trim_end()));
+                }
+            }
+            Some(comment) => end = format!("{}# {}", gap, comment).trim_end().to_string(),
+            None => {}
+        }
+        above.push_str(indent);
+
+        match *self {
+            Line::Header(ref mut decor) => {
+                decor.set_prefix(above);
+                decor.set_suffix(end);
+            }
+            Line::KeyValue(ref mut key, ref mut value) => {
+                key.set_prefix(above);
+                value.set_suffix(end);
+            }
+        }
+    }
+
+    /// Returns the text before this line, and the text at the end of it.
+    fn text(&self) -> (String, String) {
+        let text = |raw: Option<&RawString>| raw.and_then(|r| r.as_str()).unwrap_or("").to_string();
+        match *self {
+            Line::Header(ref decor) => (text(decor.prefix()), text(decor.suffix())),
+            Line::KeyValue(ref key, ref value) => (text(key.prefix()), text(value.suffix())),
+        }
+    }
+}
+
+/// Splits the text before a line into the text before the comments directly above the line,
+/// those comments, and the indentation of the line.
+fn split_comments(above: &str) -> (&str, &str, &str) {
+    let indent_start = above.rfind('\n').map_or(0, |i| i + 1);
+    let mut start = indent_start;
+    while start > 0 {
+        let line_start = above[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+        if !above[line_start..start].trim_start().starts_with('#') {
+            break;
+        }
+        start = line_start;
+    }
+    (
+        &above[..start],
+        &above[start..indent_start],
+        &above[indent_start..],
+    )
+}
+
+/// Returns the text of a comment, without the `#` and the spaces around it.
+fn comment_text(comment: &str) -> String {
+    let comment = comment.trim().trim_start_matches('#');
+    comment
+        .strip_prefix(' ')
+        .unwrap_or(comment)
+        .trim_end()
+        .to_string()
+}
+
+/// Calls `f` with the line the value at `path` within `table` is written on, if it has one.
+///
+/// `above` is the decoration of the first key of a dotted key, e.g. `a` in `a.b = 1`, which
+/// holds the comments above the line rather than the key of the value itself.
+fn with_line<R>(
+    table: &mut dyn TableLike,
+    path: &[Value],
+    above: Option<&mut Decor>,
+    f: &mut dyn FnMut(Line) -> R,
+) -> Option<R> {
+    let (key, rest) = path.split_first()?;
+    let key = match *key {
+        Value::String(ref key) => key,
+        _ => return None,
+    };
+
+    let (mut key, item) = table.get_key_value_mut(key)?;
+    let above = match above {
+        Some(above) => above,
+        None => key.leaf_decor_mut(),
+    };
+
+    match *item {
+        Item::Value(ref mut value) if rest.is_empty() => {
+            Some(f(Line::KeyValue(above, value.decor_mut())))
+        }
+        Item::Table(ref mut table) if rest.is_empty() => {
+            if table.is_implicit() || table.is_dotted() {
+                None
+            } else {
+                Some(f(Line::Header(table.decor_mut())))
+            }
+        }
+        Item::Table(ref mut table) if table.is_dotted() => with_line(table, rest, Some(above), f),
+        Item::Table(ref mut table) => with_line(table, rest, None, f),
+        Item::ArrayOfTables(ref mut array) => {
+            let (index, rest) = rest.split_first()?;
+            let table = match *index {
+                Value::Integer(i) if i >= 0 => array.get_mut(i as usize)?,
+                _ => return None,
+            };
+            if rest.is_empty() {
+                Some(f(Line::Header(table.decor_mut())))
+            } else {
+                with_line(table, rest, None, f)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Rewrites the table or array at `path` within `table` in the given style, if it is not already
+/// written that way.
+fn restyle(table: &mut toml_edit::Table, path: &[Value], style: Style) -> Result<(), String> {
+    let nested = "Cannot change the style of a value nested in an inline table or array";
+    let (key, rest) = match path.split_first() {
+        Some((Value::String(ref key), rest)) => (key.as_str(), rest),
+        _ => return Err(nested.to_string()),
+    };
+
+    if !rest.is_empty() {
+        return match *table.get_mut(key).ok_or(nested)? {
+            Item::Table(ref mut table) => restyle(table, rest, style),
+            Item::ArrayOfTables(ref mut array) => match rest.split_first() {
+                Some((&Value::Integer(i), rest)) if i >= 0 && !rest.is_empty() => {
+                    restyle(array.get_mut(i as usize).ok_or(nested)?, rest, style)
+                }
+                _ => Err(nested.to_string()),
+            },
+            // Everything nested in an inline value is already inline.
+            _ if style == Style::Inline => Ok(()),
+            _ => Err(nested.to_string()),
+        };
+    }
+
+    // Comments above the line of the value, or above its header, move to its new line.
+    let was_value = table.get(key).is_some_and(Item::is_value);
+    let above = match table.get(key) {
+        Some(Item::Table(ref inner)) if !inner.is_dotted() => inner.decor().prefix(),
+        Some(Item::Value(_)) => table.key(key).and_then(|k| k.leaf_decor().prefix()),
+        _ => None,
+    };
+    let above = above.and_then(|p| p.as_str()).unwrap_or("");
+    let above = above.trim_start_matches(['\r', '\n']).to_string();
+
+    let item = table.get_mut(key).ok_or(nested)?;
+    match (style, std::mem::take(item)) {
+        (Style::Inline, Item::Table(mut inner)) => {
+            inner.set_dotted(false);
+            *item = Item::Table(inner);
+            item.make_value();
+        }
+        (Style::Inline, old @ Item::ArrayOfTables(_)) => {
+            *item = old;
+            item.make_value();
+        }
+        (Style::Standard, Item::Table(mut inner)) => {
+            inner.set_dotted(false);
+            inner.set_implicit(false);
+            *item = Item::Table(inner);
+        }
+        (Style::Dotted, Item::Table(mut inner)) => {
+            dot(&mut inner);
+            *item = Item::Table(inner);
+        }
+        (style @ (Style::Standard | Style::Dotted), old @ Item::Value(_)) => {
+            let mut inner = old
+                .into_table()
+                .map_err(|_| "Expected a table".to_string())?;
+            inner.decor_mut().set_prefix(format!("\n{}", above));
+            if style == Style::Dotted {
+                dot(&mut inner);
+            }
+            *item = Item::Table(inner);
+        }
+        (Style::ArrayOfTables, old @ Item::Value(_)) => {
+            let mut array = old
+                .into_array_of_tables()
+                .map_err(|_| "Expected an array of tables".to_string())?;
+            if let Some(first) = array.get_mut(0) {
+                first.decor_mut().set_prefix(format!("\n{}", above));
+            }
+            *item = Item::ArrayOfTables(array);
+        }
+        (_, old) => *item = old,
+    }
+
+    let is_value = item.is_value();
+    let is_dotted = item.as_table().is_some_and(|t| t.is_dotted());
+    if is_value != was_value {
+        let mut key = table.key_mut(key).unwrap();
+        let decor = key.leaf_decor_mut();
+        decor.clear();
+        if is_value {
+            decor.set_prefix(above);
+        }
+    }
+    // The values of a table with no header would otherwise be written under the previous one.
+    if is_value || is_dotted {
+        table.set_implicit(false);
+    }
+    Ok(())
+}
+
+/// Writes the members of `table`, and those of the tables nested in it, as dotted keys.
+fn dot(table: &mut toml_edit::Table) {
+    table.set_dotted(true);
+    for (_, item) in table.iter_mut() {
+        if item.as_inline_table().is_some_and(|t| !t.is_empty()) {
+            if let Ok(inner) = std::mem::take(item).into_table() {
+                *item = Item::Table(inner);
+            }
+        }
+        if let Item::Table(ref mut inner) = *item {
+            dot(inner);
+        }
+    }
+}
+
+/// Sorts the members of `table` and the tables nested in it, numbering their sections in order.
+fn sort_table(table: &mut toml_edit::Table, position: &mut usize) {
+    table.sort_values();
+    table.set_position(*position);
+    *position += 1;
+    for (_, item) in table.iter_mut() {
+        match *item {
+            Item::None => {}
+            Item::Value(ref mut value) => sort_value(value),
+            Item::Table(ref mut table) => sort_table(table, position),
+            Item::ArrayOfTables(ref mut array) => {
+                for table in array.iter_mut() {
+                    sort_table(table, position);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the first table within `table` which is written with a header.
+fn first_section(table: &mut toml_edit::Table) -> Option<&mut toml_edit::Table> {
+    for (_, item) in table.iter_mut() {
+        match *item {
+            Item::Table(ref mut table) => {
+                if !table.is_implicit() && !table.is_dotted() {
+                    return Some(table);
+                } else if let Some(table) = first_section(table) {
+                    return Some(table);
+                }
+            }
+            Item::ArrayOfTables(ref mut array) => return array.iter_mut().next(),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn sort_value(value: &mut toml_edit::Value) {
+    match *value {
+        toml_edit::Value::Array(ref mut array) => array.iter_mut().for_each(sort_value),
+        toml_edit::Value::InlineTable(ref mut table) => {
+            // The spacing around members moves with them, so it is reset when they are reordered.
+            if !table.iter().map(|(k, _)| k).is_sorted() {
+                table.sort_values();
+                table.fmt();
+            }
+            table.iter_mut().for_each(|(_, value)| sort_value(value));
+        }
+        _ => {}
+    }
+}
+
+/// Rewrites the members of `table` which differ between `old` and `new`.
+///
+/// `depth` is the number of tables enclosing `table`, which decides how new tables are written.
+fn patch_table(
+    table: &mut dyn TableLike,
+    old: &Table,
+    new: &Table,
+    depth: usize,
+) -> Result<(), String> {
+    for key in old.keys().filter(|k| !new.contains_key(*k)) {
+        table.remove(key);
+    }
+
+    for (key, value) in new {
+        match (old.get(key), table.get_mut(key)) {
+            (Some(old), Some(item)) => patch_item(item, old, value, depth)?,
+            _ => {
+                table.insert(key, to_item(value, depth)?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn patch_item(item: &mut Item, old: &Value, new: &Value, depth: usize) -> Result<(), String> {
+    if old == new {
+        return Ok(());
+    }
+
+    match (old, new) {
+        (Value::Table(ref old), Value::Table(ref new)) => {
+            if let Some(table) = item.as_table_like_mut() {
+                return patch_table(table, old, new, depth + 1);
+            }
+        }
+        (Value::Array(ref old), Value::Array(ref new)) => match *item {
+            Item::Value(toml_edit::Value::Array(ref mut array)) => {
+                return patch_array(array, old, new);
+            }
+            Item::ArrayOfTables(ref mut array) if new.iter().all(is_table) => {
+                return patch_array_of_tables(array, old, new, depth);
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+
+    match item.as_value_mut() {
+        Some(value) => {
+            let decor = value.decor().clone();
+            *value = to_value(new)?;
+            *value.decor_mut() = decor;
+        }
+        None => *item = to_item(new, depth)?,
+    }
+
+    Ok(())
+}
+
+fn patch_array(array: &mut toml_edit::Array, old: &[Value], new: &[Value]) -> Result<(), String> {
+    while array.len() > new.len() {
+        array.remove(array.len() - 1);
+    }
+    if array.is_empty() {
+        // Comments after the last element went away with it.
+        array.set_trailing("");
+        array.set_trailing_comma(false);
+    }
+
+    for (i, new) in new.iter().enumerate() {
+        match (old.get(i), array.get_mut(i)) {
+            (Some(old), Some(value)) => {
+                let mut item = Item::Value(value.clone());
+                patch_item(&mut item, old, new, 1)?;
+                if let Ok(patched) = item.into_value() {
+                    *value = patched;
+                }
+            }
+            _ => array.push(to_value(new)?),
+        }
+    }
+
+    Ok(())
+}
+
+fn patch_array_of_tables(
+    array: &mut toml_edit::ArrayOfTables,
+    old: &[Value],
+    new: &[Value],
+    depth: usize,
+) -> Result<(), String> {
+    while array.len() > new.len() {
+        array.remove(array.len() - 1);
+    }
+
+    for (i, new) in new.iter().enumerate() {
+        let new = match *new {
+            Value::Table(ref new) => new,
+            _ => continue,
+        };
+        match (old.get(i), array.get_mut(i)) {
+            (Some(Value::Table(ref old)), Some(table)) => patch_table(table, old, new, depth + 1)?,
+            _ => match to_item(&Value::Table(new.clone()), 0)? {
+                Item::Table(table) => array.push(table),
+                _ => unreachable!("tables at the top level are always standard tables"),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn is_table(value: &Value) -> bool {
+    matches!(*value, Value::Table(_))
+}
+
+/// Converts a value added to a table nested `depth` tables deep into a TOML item.
+///
+/// Tables added to the document root are written as `[table]` sections, and everything else is
+/// written inline.
+fn to_item(value: &Value, depth: usize) -> Result<Item, String> {
+    match *value {
+        Value::Table(ref members) if depth == 0 => {
+            let mut table = toml_edit::Table::new();
+            for (key, value) in members {
+                table.insert(key, to_item(value, depth + 1)?);
+            }
+            Ok(Item::Table(table))
+        }
+        Value::Array(ref array)
+            if depth == 0 && !array.is_empty() && array.iter().all(is_table) =>
+        {
+            let mut tables = toml_edit::ArrayOfTables::new();
+            for table in array {
+                if let Item::Table(table) = to_item(table, depth)? {
+                    tables.push(table);
+                }
+            }
+            Ok(Item::ArrayOfTables(tables))
+        }
+        ref value => to_value(value).map(Item::Value),
+    }
+}
+
+fn to_value(value: &Value) -> Result<toml_edit::Value, String> {
+    match *value {
+        Value::Null => Err("null cannot be represented in TOML".to_string()),
+        Value::Boolean(b) => Ok(b.into()),
+        Value::Integer(i) => Ok(i.into()),
+        Value::Float(f) => Ok(f.into()),
+        Value::String(ref s) => Ok(s.as_str().into()),
+        Value::Datetime(ref dt) => dt
+            .to_string()
+            .parse::<toml_edit::Datetime>()
+            .map(Into::into)
+            .map_err(|e| e.to_string()),
+        Value::Array(ref array) => {
+            let mut values = toml_edit::Array::new();
+            for value in array {
+                values.push(to_value(value)?);
+            }
+            Ok(values.into())
+        }
+        Value::Table(ref members) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (key, value) in members {
+                table.insert(key, to_value(value)?);
+            }
+            Ok(table.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Filter;
+    use crate::eval::Interpreter;
+
+    const MANIFEST: &str = r#"# The package itself.
+[package]
+name = "tq"   # not `jq`
+version = "0.1.0"
+authors = [
+    "Eyal Kalderon <ebkalderon@gmail.com>",  # original author
+]
+
+[dependencies]
+nom = "5.0.0"
+log = { version = "0.4.6", features = ["std"] }
+
+[limits]
+mask = 0xff_ff
+big = 1_000_000
+"#;
+
+    fn update(filter: &str, text: &str) -> Result<String, String> {
+        update_all(filter, text).map(|mut docs| docs.remove(0))
+    }
+
+    /// Updates `text` with each output of `filter`.
+    fn update_all(filter: &str, text: &str) -> Result<Vec<String>, String> {
+        let filter: Filter = filter.parse()?;
+        let doc: Document = text.parse()?;
+        let interpreter = Interpreter::new();
+        interpreter.set_comments(doc.comments());
+        let mut outputs = Vec::new();
+        interpreter
+            .run(&filter, doc.value().clone(), |value| {
+                let update = interpreter.is_update();
+                outputs.push((value, update, interpreter.comments(), interpreter.styles()));
+                Ok(())
+            })
+            .map_err(|e| e.to_string())?;
+        outputs
+            .iter()
+            .map(|(output, update, comments, styles)| {
+                assert!(update, "{} is not an update", filter);
+                doc.update_with(output, comments, styles)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unchanged() {
+        assert_eq!(update(".", MANIFEST), Ok(MANIFEST.to_string()));
+        assert_eq!(
+            update(".limits.big = 1000000", MANIFEST),
+            Ok(MANIFEST.to_string())
+        );
+    }
+
+    #[test]
+    fn assign() {
+        let expected = MANIFEST.replace("\"0.1.0\"", "\"1.2.3\"");
+        assert_eq!(update(".package.version = '1.2.3'", MANIFEST), Ok(expected));
+
+        let expected = MANIFEST.replace("\"tq\"", "\"tq2\"");
+        assert_eq!(update(".package.name += '2'", MANIFEST), Ok(expected));
+
+        let expected = MANIFEST.replace("[\"std\"]", "[\"std\", \"serde\"]");
+        let actual = update(".dependencies.log.features += ['serde']", MANIFEST);
+        assert_eq!(actual, Ok(expected));
+    }
+
+    #[test]
+    fn insert() {
+        let expected = MANIFEST.replace(
+            "features = [\"std\"] }\n",
+            "features = [\"std\"] }\nserde = { version = \"1\" }\n",
+        );
+        let actual = update(".dependencies.serde = { version = '1' }", MANIFEST);
+        assert_eq!(actual, Ok(expected));
+
+        let expected = format!("{}\n[features]\ndefault = []\n", MANIFEST);
+        assert_eq!(update(".features.default = []", MANIFEST), Ok(expected));
+    }
+
+    #[test]
+    fn delete() {
+        let expected = MANIFEST.replace("nom = \"5.0.0\"\n", "");
+        assert_eq!(update("del(.dependencies.nom)", MANIFEST), Ok(expected));
+
+        let expected = MANIFEST.replace("\n[limits]\nmask = 0xff_ff\nbig = 1_000_000\n", "");
+        assert_eq!(update("delpaths([['limits']])", MANIFEST), Ok(expected));
+
+        let expected = MANIFEST.replace(
+            "\n    \"Eyal Kalderon <ebkalderon@gmail.com>\",  # original author\n",
+            "",
+        );
+        assert_eq!(
+            update("setpath(['package', 'authors']; [])", MANIFEST),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn key_order() {
+        let doc: Document = MANIFEST.parse().unwrap();
+        let keys = match *doc.value() {
+            Value::Table(ref table) => table.keys().cloned().collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        assert_eq!(keys, ["package", "dependencies", "limits"]);
+    }
+
+    #[test]
+    fn sort_keys() {
+        let mut doc: Document = MANIFEST.parse().unwrap();
+        doc.sort_keys();
+        let expected = r#"[dependencies]
+log = { features = ["std"], version = "0.4.6" }
+nom = "5.0.0"
+
+[limits]
+big = 1_000_000
+mask = 0xff_ff
+# The package itself.
+[package]
+authors = [
+    "Eyal Kalderon <ebkalderon@gmail.com>",  # original author
+]
+name = "tq"   # not `jq`
+version = "0.1.0"
+"#;
+        assert_eq!(doc.update(&doc.value().clone()), Ok(expected.to_string()));
+    }
+
+    #[test]
+    fn comments() {
+        let doc: Document = MANIFEST.parse().unwrap();
+        let comments = doc.comments();
+        let path = |keys: &[&str]| keys.iter().map(|&k| Value::from(k)).collect::<Vec<_>>();
+        assert_eq!(
+            comments.get(&path(&["package"])),
+            Some("The package itself.")
+        );
+        assert_eq!(comments.get(&path(&["package", "name"])), Some("not `jq`"));
+        assert_eq!(comments.get(&path(&["dependencies", "nom"])), None);
+
+        let expected = MANIFEST.replace("nom = \"5.0.0\"", "nom = \"5.0.0\" # pinned");
+        let actual = update(".dependencies.nom |= setcomment(\"pinned\")", MANIFEST);
+        assert_eq!(actual, Ok(expected));
+
+        let expected = MANIFEST.replace("\"tq\"   # not `jq`", "\"tq\"");
+        assert_eq!(
+            update(".package.name |= delcomment", MANIFEST),
+            Ok(expected)
+        );
+
+        let expected = MANIFEST.replace("# The package itself.\n", "# a\n# b\n");
+        let actual = update(".package |= setcomment(\"a\\nb\")", MANIFEST);
+        assert_eq!(actual, Ok(expected));
+
+        let actual = update(".package.name |= setcomment(getcomment + \"!\")", MANIFEST);
+        assert_eq!(actual, Ok(MANIFEST.replace("`jq`", "`jq`!")));
+        assert!(update(".dependencies.log.version |= setcomment(\"x\")", MANIFEST).is_err());
+        assert!(update(".package.name |= setcomment(1)", MANIFEST).is_err());
+    }
+
+    #[test]
+    fn comments_per_output() {
+        let pinned = MANIFEST.replace("nom = \"5.0.0\"", "nom = \"5.0.0\" # pinned");
+        let actual = update_all("(.dependencies.nom |= setcomment(\"pinned\")), .", MANIFEST);
+        assert_eq!(actual, Ok(vec![pinned.clone(), MANIFEST.to_string()]));
+
+        let filter = "(.dependencies.nom | setcomment(\"x\")) as $x | .limits.mask = 0";
+        let expected = MANIFEST.replace("0xff_ff", "0");
+        assert_eq!(update(filter, MANIFEST), Ok(expected));
+        let filter = "([.dependencies.nom | setcomment(\"x\")] | length) as $n | .";
+        assert_eq!(update(filter, MANIFEST), Ok(MANIFEST.to_string()));
+
+        let filter = ".dependencies.nom |= setcomment(\"pinned\") | .package.name |= getcomment";
+        let expected = pinned.replace("\"tq\"   # not `jq`", "\"not `jq`\"   # not `jq`");
+        assert_eq!(update(filter, MANIFEST), Ok(expected));
+
+        let filter = "reduce ('nom', 'log') as $k (.; .dependencies[$k] |= setcomment($k))";
+        let expected = MANIFEST
+            .replace("nom = \"5.0.0\"", "nom = \"5.0.0\" # nom")
+            .replace("[\"std\"] }", "[\"std\"] } # log");
+        assert_eq!(update(filter, MANIFEST), Ok(expected));
+
+        let text = "[a]\nb = { c = 1 }\n";
+        let actual = update_all("(.a.b |= standard), .", text);
+        let standard = "[a]\n\n[a.b]\nc = 1\n".to_string();
+        assert_eq!(actual, Ok(vec![standard, text.to_string()]));
+    }
+
+    #[test]
+    fn styles() {
+        let text = "[a]\nx = 1\n\n# b\n[a.b]\nc = 1\n";
+        let expected = "[a]\nx = 1\n# b\nb = { c = 1 }\n";
+        assert_eq!(update(".a.b |= inline", text), Ok(expected.to_string()));
+
+        let expected = MANIFEST.replace(
+            "log = { version = \"0.4.6\", features = [\"std\"] }\n",
+            "\n[dependencies.log]\nversion = \"0.4.6\"\nfeatures = [\"std\"]\n",
+        );
+        assert_eq!(
+            update(".dependencies.log |= standard", MANIFEST),
+            Ok(expected)
+        );
+
+        let expected = MANIFEST.replace(
+            "log = { version = \"0.4.6\", features = [\"std\"] }",
+            "log.version = \"0.4.6\"\nlog.features = [\"std\"]",
+        );
+        assert_eq!(
+            update(".dependencies.log |= dotted", MANIFEST),
+            Ok(expected)
+        );
+
+        let text = "[a]\nb = [{ c = 1 }]\n";
+        let expected = "[a]\n\n[[a.b]]\nc = 1\n";
+        assert_eq!(update(".a.b |= aot", text), Ok(expected.to_string()));
+        assert_eq!(update(".a.b |= inline", text), Ok(text.to_string()));
+        assert!(update(".a.b[0] |= standard", text).is_err());
+        assert!(update(".a.b |= standard", text).is_err());
+    }
+
+    #[test]
+    fn locations() {
+        let doc: Document = MANIFEST.parse().unwrap();
+        let path = |keys: &[&str]| keys.iter().map(|&k| Value::from(k)).collect::<Vec<_>>();
+        let at = |line, column| Some(Location { line, column });
+        let locations = doc.locations();
+        assert_eq!(locations.get(&path(&["package"])), at(2, 1));
+        assert_eq!(locations.get(&path(&["package", "name"])), at(3, 1));
+        let mut authors = path(&["package", "authors"]);
+        authors.push(Value::Integer(0));
+        assert_eq!(locations.get(&authors), at(6, 5));
+        assert_eq!(
+            locations.get(&path(&["dependencies", "log", "features"])),
+            at(11, 28)
+        );
+        assert_eq!(locations.get(&path(&["dependencies", "serde"])), None);
+
+        let interpreter = Interpreter::new();
+        interpreter.set_locations("Cargo.toml", doc.locations().clone());
+        let location = |filter: &str| {
+            let filter = filter.parse().unwrap();
+            let outputs = interpreter.eval(&filter, doc.value().clone()).unwrap();
+            outputs[0].to_string()
+        };
+        let expected = "{ file = \"Cargo.toml\", line = 10, column = 1 }";
+        assert_eq!(location(".dependencies.nom | location"), expected);
+        assert_eq!(location(".limits.big + 1 | location"), "null");
+    }
+
+    #[test]
+    fn diagnostics() {
+        let err = Document::parse_file("a = 1\n[x]\n[x]\n", "Cargo.toml", 1).unwrap_err();
+        let expected = "invalid table header: duplicate key `x` in document root
+ --> Cargo.toml:3:2
+  |
+3 | [x]
+  |  ^";
+        assert_eq!(err, expected);
+
+        let err = Document::parse_file("x = { ab = 1, ab = 2 }", "a.md", 9).unwrap_err();
+        assert!(err.starts_with("duplicate key `ab`\n --> a.md:9:15\n"));
+        assert!(err.ends_with("\n  |               ^^"));
+
+        let err = Document::parse_file("\tb = \"x\n", "-", 1).unwrap_err();
+        assert!(err.ends_with("1 | \tb = \"x\n  | \t      ^"));
+        assert_eq!(
+            Document::parse_file("a = 1", "a.md", 3)
+                .unwrap()
+                .locations()
+                .get(&[Value::from("a")]),
+            Some(Location { line: 3, column: 1 })
+        );
+    }
+
+    #[test]
+    fn updates_only() {
+        let doc: Document = MANIFEST.parse().unwrap();
+        let updates = |filter: &str| {
+            let interpreter = Interpreter::new();
+            let mut update = false;
+            interpreter
+                .run(&filter.parse().unwrap(), doc.value().clone(), |_| {
+                    update = interpreter.is_update();
+                    Ok(())
+                })
+                .unwrap();
+            update
+        };
+        assert!(updates(".a = 1 | del(.package)"));
+        assert!(updates("del(.[])"));
+        assert!(!updates(".package"));
+        assert!(!updates("{ a = .package.name }"));
+        assert!(!updates("{ limits = .limits }"));
+        assert!(!updates("with_entries(.)"));
+        assert!(!updates(".a = 1 | .a"));
+        assert!(update(".a = null", MANIFEST).is_err());
+    }
+
+    #[test]
+    fn derived_updates() {
+        let expected = MANIFEST.replace("\"0.1.0\"", "\"2\"");
+        let actual = update("if true then .package.version = '2' else . end", MANIFEST);
+        assert_eq!(actual, Ok(expected));
+        assert_eq!(update("map_values(.)", MANIFEST), Ok(MANIFEST.to_string()));
+
+        let expected = MANIFEST.replace("0xff_ff", "0").replace("1_000_000", "0");
+        let actual = update(
+            "reduce ('mask', 'big') as $k (.; .limits[$k] = 0)",
+            MANIFEST,
+        );
+        assert_eq!(actual, Ok(expected));
+
+        let expected = MANIFEST.replace("\"5.0.0\"", "\"5.1.0\"");
+        let actual = update(
+            "def bump(f): f = '5.1.0'; bump(.dependencies.nom)",
+            MANIFEST,
+        );
+        assert_eq!(actual, Ok(expected));
+    }
+}
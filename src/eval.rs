@@ -0,0 +1,1222 @@
+//! Evaluation of `tq` filters against values.
+//!
+//! Filters are evaluated in the style of jq: every expression produces a stream of zero or more
+//! outputs for a single input, and each output is passed to a callback as soon as it is produced.
+//!
+//! Every output also carries its location within the input, if it was selected from the input
+//! rather than computed, which is how an updated input is told apart from values derived from it,
+//! and how `getcomment` knows which value it was given. Expressions used as paths, e.g. on the left
+//! side of `=` or inside `path(f)`, are evaluated with strict path tracking, which rejects
+//! expressions that compute new values rather than select existing ones.
+//!
+//! Comments and styles set by builtins such as `setcomment` and `inline` are carried along with
+//! the outputs they apply to, as `Note`s.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::rc::Rc;
+
+use self::env::{Callable, Env};
+use crate::ast::tokens::{FnParam, Label, Literal, Variable};
+use crate::ast::{
+    BinaryOp, Expr, ExprFilter, ExprFnCall, ExprFnDecl, ExprForeach, ExprIndex, ExprPattern,
+    ExprReduce, ExprSlice, Filter, TableKey,
+};
+use crate::document::{Comments, Locations};
+use crate::output::toml::{Style, Styles};
+use crate::parser::parse_module;
+use crate::value::ops::{self, describe};
+use crate::value::{path, Table, Value};
+
+mod builtins;
+mod env;
+
+/// A path into a value, as produced by `path(f)`.
+type Path = Vec<Value>;
+
+/// A change to the comments or styles of the input, made by a builtin such as `setcomment`.
+///
+/// A note applies to the outputs evaluated downstream of the builtin that set it. Values kept for
+/// later by `reduce`, `foreach`, `|=` and `[f]` keep the notes they were produced with, and take
+/// them back when they are emitted.
+#[derive(Clone, Debug)]
+enum Note {
+    Comment(Path, Option<String>),
+    Style(Path, Style),
+}
+
+/// The path of a value within the input, if it is known.
+#[derive(Clone, Debug)]
+struct Tracked {
+    path: Path,
+    /// Whether computed values are an error, rather than having no path.
+    strict: bool,
+}
+
+/// Receives each output of an expression, along with its path if it is known.
+type Output<'o> = &'o mut dyn FnMut(Option<Tracked>, Value) -> Result<(), Unwind>;
+
+/// An error raised while evaluating a filter.
+///
+/// Errors carry an arbitrary value, which is usually a message string but can be anything passed
+/// to `error/1`. This is the value received by the `catch` clause of a `try` expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error(Value);
+
+impl Error {
+    /// Returns the value carried by this error.
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self.0 {
+            Value::String(ref msg) => fmt.write_str(msg),
+            ref value => write!(fmt, "{} (not a string)", value),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Self {
+        Error(Value::String(msg))
+    }
+}
+
+/// Reasons for evaluation to stop before an expression has produced all of its outputs.
+enum Unwind {
+    /// An error was raised and has not been caught yet.
+    Error(Value),
+    /// `break $label` was evaluated for the label with the given unique identifier.
+    Break(usize),
+}
+
+impl From<String> for Unwind {
+    fn from(msg: String) -> Self {
+        Unwind::Error(Value::String(msg))
+    }
+}
+
+/// A source of the values read by the `input` builtin, which returns `None` once they run out.
+type Inputs = Box<dyn FnMut() -> Option<Result<Value, String>>>;
+
+/// Evaluates filters, with the builtin functions from `builtin.tq` in scope.
+pub struct Interpreter {
+    prelude: HashMap<String, Vec<ExprFnDecl>>,
+    labels: Cell<usize>,
+    inputs: RefCell<Option<Inputs>>,
+    comments: RefCell<Comments>,
+    styles: RefCell<Styles>,
+    /// Whether the output being passed to the callback given to `run` is its input, updated.
+    update: Cell<bool>,
+    /// The notes applying to the output being evaluated, oldest first.
+    notes: RefCell<Vec<Note>>,
+    /// The file of the value evaluated next, and where its values were written in it.
+    locations: RefCell<(String, Locations)>,
+}
+
+impl Interpreter {
+    /// Creates a new interpreter, loading the builtin function definitions.
+    pub fn new() -> Self {
+        let module = parse_module(include_str!("../builtin.tq")).expect("builtin.tq is invalid");
+        let mut prelude: HashMap<_, Vec<_>> = HashMap::new();
+        for decl in module.decls {
+            prelude.entry(decl.name.to_string()).or_default().push(decl);
+        }
+
+        Interpreter {
+            prelude,
+            labels: Cell::new(0),
+            inputs: RefCell::new(None),
+            comments: RefCell::new(Comments::default()),
+            styles: RefCell::new(Styles::default()),
+            update: Cell::new(false),
+            notes: RefCell::new(Vec::new()),
+            locations: RefCell::new((String::new(), Locations::default())),
+        }
+    }
+
+    /// Sets the source of the values read by `input` and `inputs`.
+    pub fn set_inputs<F>(&mut self, inputs: F)
+    where
+        F: FnMut() -> Option<Result<Value, String>> + 'static,
+    {
+        self.inputs = RefCell::new(Some(Box::new(inputs)));
+    }
+
+    /// Sets the comments read by `getcomment`, and changed by `setcomment` and `delcomment`.
+    ///
+    /// These are the comments of the document evaluated next, whose paths are relative to it.
+    pub fn set_comments(&self, comments: Comments) {
+        *self.comments.borrow_mut() = comments;
+    }
+
+    /// Returns the comments, with the changes made to them by the filter for the output it is
+    /// producing, while that output is being passed to the callback given to [`run`].
+    ///
+    /// [`run`]: #method.run
+    pub fn comments(&self) -> Comments {
+        let mut comments = self.comments.borrow().clone();
+        for note in self.notes.borrow().iter() {
+            if let Note::Comment(ref path, ref comment) = *note {
+                comments.set(path, comment.clone());
+            }
+        }
+        comments
+    }
+
+    /// Sets the styles of the tables and arrays of the value evaluated next, which are set by
+    /// `inline`, `standard`, `dotted` and `aot`.
+    pub fn set_styles(&self, styles: Styles) {
+        *self.styles.borrow_mut() = styles;
+    }
+
+    /// Returns the styles, with those set by the filter for the output it is producing, while that
+    /// output is being passed to the callback given to [`run`].
+    ///
+    /// [`run`]: #method.run
+    pub fn styles(&self) -> Styles {
+        let mut styles = self.styles.borrow().clone();
+        for note in self.notes.borrow().iter() {
+            if let Note::Style(ref path, style) = *note {
+                styles.set(path, style);
+            }
+        }
+        styles
+    }
+
+    /// Returns whether the output being passed to the callback given to [`run`] is its input with
+    /// some of its contents changed, through assignments or expressions keeping its path, rather
+    /// than a value derived from it, such as one of its sections.
+    ///
+    /// [`run`]: #method.run
+    pub fn is_update(&self) -> bool {
+        self.update.get()
+    }
+
+    /// Sets the name of the file holding the value evaluated next, and where its values were
+    /// written in it, which are reported by `location`.
+    pub fn set_locations(&self, file: &str, locations: Locations) {
+        *self.locations.borrow_mut() = (file.to_string(), locations);
+    }
+
+    /// Evaluates `filter` against `input`, passing each output to `out` as it is produced.
+    ///
+    /// Evaluation stops at the first error, whether it was raised by the filter or by `out`.
+    pub fn run<F>(&self, filter: &Filter, input: Value, mut out: F) -> Result<(), Error>
+    where
+        F: FnMut(Value) -> Result<(), Error>,
+    {
+        if filter.stmts.module.is_some() || !filter.stmts.stmts.is_empty() {
+            return Err(Error("modules and imports are not supported".into()));
+        }
+
+        let env = Rc::new(Env::Root);
+        let mut out = |path: Option<Tracked>, value| {
+            self.update
+                .set(path.is_some_and(|tracked| tracked.path.is_empty()));
+            out(value).map_err(|Error(e)| Unwind::Error(e))
+        };
+        let root = Some(Tracked {
+            path: Vec::new(),
+            strict: false,
+        });
+        match self.eval_expr(&filter.expr, &env, root, input, &mut out) {
+            Ok(()) => Ok(()),
+            Err(Unwind::Error(e)) => Err(Error(e)),
+            Err(Unwind::Break(_)) => Err(Error("break".into())),
+        }
+    }
+
+    /// Evaluates `filter` against `input`, collecting all of its outputs.
+    pub fn eval(&self, filter: &Filter, input: Value) -> Result<Vec<Value>, Error> {
+        let mut outputs = Vec::new();
+        self.run(filter, input, |value| {
+            outputs.push(value);
+            Ok(())
+        })?;
+        Ok(outputs)
+    }
+
+    fn eval_expr<'s>(
+        &'s self,
+        expr: &'s Expr,
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        match *expr {
+            Expr::Paren(ref expr) => self.eval_expr(expr, env, path, input, out),
+            Expr::Empty => Ok(()),
+            Expr::Literal(ref lit) => emit(&path, Value::from(lit.clone()), out),
+            Expr::Variable(ref var) => emit(&path, variable(env, var)?, out),
+            Expr::Array(ref inner) => {
+                let (mut array, mut notes) = (Vec::new(), Vec::new());
+                if let Some(ref inner) = *inner {
+                    let mark = self.notes.borrow().len();
+                    self.eval_expr(inner, env, lenient(&path), input, &mut |_, v| {
+                        array.push(v);
+                        notes.extend(self.notes_since(mark));
+                        Ok(())
+                    })?;
+                }
+                self.with_notes(notes, || emit(&path, Value::Array(array), out))
+            }
+            Expr::Table(ref members) => {
+                self.eval_table(members, env, &path, &input, Table::new(), &mut |t| {
+                    emit(&path, Value::Table(t), out)
+                })
+            }
+
+            Expr::Unary(ref op, ref expr) => {
+                self.eval_expr(expr, env, lenient(&path), input, &mut |_, v| {
+                    emit(&path, ops::unary(op, v)?, out)
+                })
+            }
+            Expr::Binary(BinaryOp::Pipe, ref lhs, ref rhs) => match **lhs {
+                Expr::Label(ref label) => self.eval_label(label, rhs, env, path, input, out),
+                ref lhs => self.eval_expr(lhs, env, path, input, &mut |p, v| {
+                    self.eval_expr(rhs, env, p, v, out)
+                }),
+            },
+            Expr::Binary(BinaryOp::Comma, ref lhs, ref rhs) => {
+                self.eval_expr(lhs, env, path.clone(), input.clone(), out)?;
+                self.eval_expr(rhs, env, path, input, out)
+            }
+            Expr::Binary(ref op @ BinaryOp::And, ref lhs, ref rhs)
+            | Expr::Binary(ref op @ BinaryOp::Or, ref lhs, ref rhs) => {
+                let short_circuit = *op == BinaryOp::Or;
+                self.eval_expr(lhs, env, lenient(&path), input.clone(), &mut |_, l| {
+                    if l.is_truthy() == short_circuit {
+                        return emit(&path, Value::Boolean(short_circuit), out);
+                    }
+                    self.eval_expr(rhs, env, lenient(&path), input.clone(), &mut |_, r| {
+                        emit(&path, Value::Boolean(r.is_truthy()), out)
+                    })
+                })
+            }
+            Expr::Binary(BinaryOp::Alt, ref lhs, ref rhs) => {
+                let mut truthy = false;
+                let mut downstream = None;
+                let result = self.eval_expr(lhs, env, path.clone(), input.clone(), &mut |p, v| {
+                    if !v.is_truthy() {
+                        return Ok(());
+                    }
+                    truthy = true;
+                    out(p, v).map_err(|e| {
+                        downstream = Some(e);
+                        Unwind::Break(usize::MAX)
+                    })
+                });
+
+                match (downstream, result) {
+                    (Some(e), _) => Err(e),
+                    (None, Err(Unwind::Break(id))) => Err(Unwind::Break(id)),
+                    (None, _) if truthy => Ok(()),
+                    (None, _) => self.eval_expr(rhs, env, path, input, out),
+                }
+            }
+            Expr::Binary(ref op, ref lhs, ref rhs) => {
+                self.eval_expr(rhs, env, lenient(&path), input.clone(), &mut |_, r| {
+                    self.eval_expr(lhs, env, lenient(&path), input.clone(), &mut |_, l| {
+                        emit(&path, ops::binary(op, l, r.clone())?, out)
+                    })
+                })
+            }
+            Expr::Assign(ref lhs, ref rhs) => {
+                let paths = self.collect_paths(lhs, env, &input)?;
+                self.eval_expr(rhs, env, lenient(&path), input.clone(), &mut |_, v| {
+                    let mut result = input.clone();
+                    for p in &paths {
+                        result = path::set(result, p, v.clone())?;
+                    }
+                    emit_update(&path, result, out)
+                })
+            }
+            Expr::AssignOp(BinaryOp::Pipe, ref lhs, ref rhs) => {
+                let paths = self.collect_paths(lhs, env, &input)?;
+                let mut result = input;
+                let (mut deleted, mut notes) = (Vec::new(), Vec::new());
+                for p in paths {
+                    let old = path::get(&result, &p)?;
+                    match self.first(rhs, env, join(&path, &p), old)? {
+                        Some((new, new_notes)) => {
+                            result = path::set(result, &p, new)?;
+                            notes.extend(new_notes);
+                        }
+                        None => deleted.push(p),
+                    }
+                }
+                let result = path::delete(result, deleted)?;
+                self.with_notes(notes, || emit_update(&path, result, out))
+            }
+            Expr::AssignOp(ref op, ref lhs, ref rhs) => {
+                let paths = self.collect_paths(lhs, env, &input)?;
+                self.eval_expr(rhs, env, lenient(&path), input.clone(), &mut |_, v| {
+                    let mut result = input.clone();
+                    for p in &paths {
+                        let old = path::get(&result, p)?;
+                        result = path::set(result, p, ops::binary(op, old, v.clone())?)?;
+                    }
+                    emit_update(&path, result, out)
+                })
+            }
+
+            Expr::Filter(ref filter) => self.eval_filter(filter, env, path, input, out),
+            Expr::Index(ref expr, ref index) => {
+                self.eval_expr(expr, env, path, input.clone(), &mut |p, v| {
+                    self.eval_index(index, env, &input, p, v, out)
+                })
+            }
+            Expr::Binding(ref binding, ref body) => {
+                let mark = self.notes.borrow().len();
+                self.eval_expr(
+                    &binding.expr,
+                    env,
+                    lenient(&path),
+                    input.clone(),
+                    &mut |_, v| {
+                        // The bound value is dropped, so its notes don't apply to the outputs.
+                        self.without_notes(mark, || {
+                            self.destructure(&binding.pattern, env, v, &mut |env| {
+                                self.eval_expr(body, &env, path.clone(), input.clone(), out)
+                            })
+                        })
+                    },
+                )
+            }
+
+            Expr::FnDecl(ref decl, ref body) => {
+                let parent = env.clone();
+                let env = Rc::new(Env::Func { decl, parent });
+                self.eval_expr(body, &env, path, input, out)
+            }
+            Expr::FnCall(ref call) => self.eval_call(call, env, path, input, out),
+
+            Expr::Label(_) => out(path, input),
+            Expr::Break(ref label) => match env::lookup_label(env, &label.0) {
+                Some(id) => Err(Unwind::Break(id)),
+                None => Err(format!("$*label-{} is not defined", (label.0).0).into()),
+            },
+
+            Expr::IfElse(ref expr) => {
+                let (main, alts) = (&expr.main_clause, &expr.alt_clauses[..]);
+                self.eval_if(main, alts, &expr.fallback, env, path, input, out)
+            }
+            Expr::Reduce(ref expr) => self.eval_reduce(expr, env, path, input, out),
+            Expr::Foreach(ref expr) => self.eval_foreach(expr, env, path, input, out),
+            Expr::Try(ref expr) => {
+                let mut downstream = None;
+                let result = self.eval_expr(&expr.expr, env, path.clone(), input, &mut |p, v| {
+                    out(p, v).map_err(|e| {
+                        downstream = Some(e);
+                        Unwind::Break(usize::MAX)
+                    })
+                });
+
+                match (downstream, result) {
+                    (Some(e), _) => Err(e),
+                    (None, Err(Unwind::Error(e))) => match expr.fallback {
+                        Some(ref catch) => {
+                            self.eval_expr(catch, env, None, e, &mut |_, v| emit(&path, v, out))
+                        }
+                        None => Ok(()),
+                    },
+                    (None, result) => result,
+                }
+            }
+        }
+    }
+
+    fn eval_filter<'s>(
+        &'s self,
+        filter: &'s ExprFilter,
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        match *filter {
+            ExprFilter::Identity => out(path, input),
+            ExprFilter::Recurse => recurse(path, input, out),
+            ExprFilter::Field(ref ident) => {
+                let key = Value::String(ident.0.clone());
+                let child = path::index(&input, &key)?;
+                out(push(&path, key), child)
+            }
+            ExprFilter::Variable(ref var) => emit(&path, variable(env, var)?, out),
+            ExprFilter::Index(ref index) => {
+                self.eval_index(index, env, &input, path, input.clone(), out)
+            }
+            ExprFilter::Path(ref lhs, ref rhs) => {
+                self.eval_filter(lhs, env, path, input.clone(), &mut |p, v| match **rhs {
+                    ExprFilter::Index(ref index) => self.eval_index(index, env, &input, p, v, out),
+                    ref rhs => self.eval_filter(rhs, env, p, v, out),
+                })
+            }
+        }
+    }
+
+    /// Indexes into `value`, evaluating any index expressions against `root`.
+    fn eval_index<'s>(
+        &'s self,
+        index: &'s ExprIndex,
+        env: &Rc<Env<'s>>,
+        root: &Value,
+        path: Option<Tracked>,
+        value: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        match *index {
+            ExprIndex::Iter => {
+                for (key, child) in path::entries(&value)? {
+                    out(push(&path, key), child)?;
+                }
+                Ok(())
+            }
+            ExprIndex::Exact(ref expr) => {
+                self.eval_expr(expr, env, None, root.clone(), &mut |_, key| {
+                    let child = path::index(&value, &key)?;
+                    out(push(&path, key), child)
+                })
+            }
+            ExprIndex::Slice(ref slice) => {
+                let (lower, upper) = match *slice {
+                    ExprSlice::Lower(ref lower) => (Some(lower), None),
+                    ExprSlice::Upper(ref upper) => (None, Some(upper)),
+                    ExprSlice::Range(ref lower, ref upper) => (Some(lower), Some(upper)),
+                };
+
+                self.eval_bound(lower, env, root, &mut |start| {
+                    self.eval_bound(upper, env, root, &mut |end| {
+                        let child = path::slice(&value, &start, &end)?;
+                        let mut key = Table::new();
+                        key.insert("start".to_string(), start.clone());
+                        key.insert("end".to_string(), end);
+                        out(push(&path, Value::Table(key)), child)
+                    })
+                })
+            }
+        }
+    }
+
+    fn eval_bound<'s>(
+        &'s self,
+        bound: Option<&'s Expr>,
+        env: &Rc<Env<'s>>,
+        root: &Value,
+        f: &mut dyn FnMut(Value) -> Result<(), Unwind>,
+    ) -> Result<(), Unwind> {
+        match bound {
+            Some(expr) => self.eval_expr(expr, env, None, root.clone(), &mut |_, v| f(v)),
+            None => f(Value::Null),
+        }
+    }
+
+    /// Evaluates `reduce`. The accumulator keeps its path and notes from one update to the next,
+    /// so that the updates can set comments and styles of the input.
+    fn eval_reduce<'s>(
+        &'s self,
+        expr: &'s ExprReduce,
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        let source = &expr.binding.expr;
+        self.eval_expr(
+            &expr.acc,
+            env,
+            lenient(&path),
+            input.clone(),
+            &mut |p, init| {
+                let mark = self.notes.borrow().len();
+                let mut acc = Some((p, init, Vec::new()));
+                self.eval_expr(source, env, lenient(&path), input.clone(), &mut |_, v| {
+                    self.without_notes(mark, || {
+                        self.destructure(&expr.binding.pattern, env, v, &mut |env| {
+                            let (p, current, notes) =
+                                acc.take().unwrap_or((None, Value::Null, Vec::new()));
+                            self.with_notes(notes, || {
+                                self.eval_expr(&expr.eval, &env, p, current, &mut |p, next| {
+                                    acc = Some((p, next, self.notes_since(mark)));
+                                    Ok(())
+                                })
+                            })
+                        })
+                    })
+                })?;
+
+                let (p, value, notes) = acc.take().unwrap_or((None, Value::Null, Vec::new()));
+                self.with_notes(notes, || emit_kept(&path, p, value, out))
+            },
+        )
+    }
+
+    /// Evaluates `foreach`, whose state keeps its path and notes like the accumulator of `reduce`.
+    fn eval_foreach<'s>(
+        &'s self,
+        expr: &'s ExprForeach,
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        let source = &expr.binding.expr;
+        self.eval_expr(
+            &expr.init,
+            env,
+            lenient(&path),
+            input.clone(),
+            &mut |p, init| {
+                let mark = self.notes.borrow().len();
+                let mut state = (p, init, Vec::new());
+                self.eval_expr(source, env, lenient(&path), input.clone(), &mut |_, v| {
+                    self.without_notes(mark, || {
+                        self.destructure(&expr.binding.pattern, env, v, &mut |env| {
+                            let (p, current, notes) = state.clone();
+                            self.with_notes(notes, || {
+                                self.eval_expr(&expr.update, &env, p, current, &mut |p, next| {
+                                    state = (p.clone(), next.clone(), self.notes_since(mark));
+                                    self.eval_expr(&expr.extract, &env, p, next, &mut |p, v| {
+                                        emit_kept(&path, p, v, out)
+                                    })
+                                })
+                            })
+                        })
+                    })
+                })
+            },
+        )
+    }
+
+    fn eval_table<'s>(
+        &'s self,
+        members: &'s [(TableKey, Option<Expr>)],
+        env: &Rc<Env<'s>>,
+        path: &Option<Tracked>,
+        input: &Value,
+        acc: Table,
+        out: &mut dyn FnMut(Table) -> Result<(), Unwind>,
+    ) -> Result<(), Unwind> {
+        let ((key, value), rest) = match members.split_first() {
+            Some(split) => split,
+            None => return out(acc),
+        };
+
+        let mut insert = |k: String, v: Value| {
+            let mut acc = acc.clone();
+            acc.insert(k, v);
+            self.eval_table(rest, env, path, input, acc, out)
+        };
+
+        match (key, value) {
+            (key, Some(value)) => self.eval_table_key(key, env, input, &mut |k| {
+                self.eval_expr(value, env, lenient(path), input.clone(), &mut |_, v| {
+                    insert(k.clone(), v)
+                })
+            }),
+            (TableKey::Variable(ref var), None) => insert((var.0).0.clone(), variable(env, var)?),
+            (key, None) => self.eval_table_key(key, env, input, &mut |k| {
+                let v = path::index(input, &Value::String(k.clone()))?;
+                insert(k, v)
+            }),
+        }
+    }
+
+    fn eval_table_key<'s>(
+        &'s self,
+        key: &'s TableKey,
+        env: &Rc<Env<'s>>,
+        input: &Value,
+        f: &mut dyn FnMut(String) -> Result<(), Unwind>,
+    ) -> Result<(), Unwind> {
+        match *key {
+            TableKey::Field(ref ident) => f(ident.0.clone()),
+            TableKey::Variable(ref var) => match variable(env, var)? {
+                Value::String(k) => f(k),
+                k => Err(key_error(&k)),
+            },
+            TableKey::Literal(Literal::String(ref k)) => f(k.clone()),
+            TableKey::Literal(ref lit) => Err(key_error(&Value::from(lit.clone()))),
+            TableKey::Expr(ref expr) => {
+                self.eval_expr(expr, env, None, input.clone(), &mut |_, k| match k {
+                    Value::String(k) => f(k),
+                    k => Err(key_error(&k)),
+                })
+            }
+        }
+    }
+
+    /// Binds the variables in `pattern` to the matching parts of `value`.
+    ///
+    /// A pattern may match more than once if it contains computed keys which produce several
+    /// outputs, so `f` is called with each resulting scope in turn.
+    fn destructure<'s>(
+        &'s self,
+        pattern: &'s ExprPattern,
+        env: &Rc<Env<'s>>,
+        value: Value,
+        f: &mut dyn FnMut(Rc<Env<'s>>) -> Result<(), Unwind>,
+    ) -> Result<(), Unwind> {
+        match *pattern {
+            ExprPattern::Variable(ref var) => f(env::bind_var(env, var, value)),
+            ExprPattern::Array(ref elems) => self.destructure_array(elems, 0, env, &value, f),
+            ExprPattern::Table(ref members) => self.destructure_table(members, env, &value, f),
+        }
+    }
+
+    fn destructure_array<'s>(
+        &'s self,
+        elems: &'s [ExprPattern],
+        index: i64,
+        env: &Rc<Env<'s>>,
+        value: &Value,
+        f: &mut dyn FnMut(Rc<Env<'s>>) -> Result<(), Unwind>,
+    ) -> Result<(), Unwind> {
+        match elems.split_first() {
+            Some((elem, rest)) => {
+                let child = path::index(value, &Value::Integer(index))?;
+                self.destructure(elem, env, child, &mut |env| {
+                    self.destructure_array(rest, index + 1, &env, value, f)
+                })
+            }
+            None => f(env.clone()),
+        }
+    }
+
+    fn destructure_table<'s>(
+        &'s self,
+        members: &'s [(TableKey, ExprPattern)],
+        env: &Rc<Env<'s>>,
+        value: &Value,
+        f: &mut dyn FnMut(Rc<Env<'s>>) -> Result<(), Unwind>,
+    ) -> Result<(), Unwind> {
+        let ((key, pattern), rest) = match members.split_first() {
+            Some(split) => split,
+            None => return f(env.clone()),
+        };
+
+        let mut next = |env: &Rc<Env<'s>>, key: Value| {
+            let child = path::index(value, &key)?;
+            self.destructure(pattern, env, child, &mut |env| {
+                self.destructure_table(rest, &env, value, f)
+            })
+        };
+
+        match *key {
+            TableKey::Field(ref ident) => next(env, Value::String(ident.0.clone())),
+            TableKey::Variable(ref var) => {
+                let key = Value::String((var.0).0.clone());
+                let env = env::bind_var(env, var, path::index(value, &key)?);
+                next(&env, key)
+            }
+            TableKey::Literal(Literal::String(ref k)) => next(env, Value::String(k.clone())),
+            TableKey::Literal(ref lit) => Err(key_error(&Value::from(lit.clone()))),
+            TableKey::Expr(ref expr) => {
+                self.eval_expr(expr, env, None, value.clone(), &mut |_, k| match k {
+                    Value::String(_) => next(env, k),
+                    k => Err(key_error(&k)),
+                })
+            }
+        }
+    }
+
+    fn eval_call<'s>(
+        &'s self,
+        call: &'s ExprFnCall,
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        let (name, args) = (&call.path.0[..], &call.args[..]);
+        match env::lookup_fn(env, name, args.len()) {
+            Some(Callable::Closure(body, env)) => self.eval_expr(body, &env, path, input, out),
+            Some(Callable::Func(decl, scope)) => {
+                self.eval_fn(decl, scope, args, env, path, input, out)
+            }
+            None => {
+                let decl = match *name {
+                    [ref ident] => self.prelude.get(&ident.0).and_then(|decls| {
+                        decls.iter().find(|decl| decl.params.len() == args.len())
+                    }),
+                    _ => None,
+                };
+
+                match decl {
+                    Some(decl) => {
+                        let scope = Rc::new(Env::Root);
+                        self.eval_fn(decl, scope, args, env, path, input, out)
+                    }
+                    None => self.eval_native(&call.path, args, env, path, input, out),
+                }
+            }
+        }
+    }
+
+    /// Calls a function declared in `scope` with `args` from the caller's scope `env`.
+    #[allow(clippy::too_many_arguments)]
+    fn eval_fn<'s>(
+        &'s self,
+        decl: &'s ExprFnDecl,
+        scope: Rc<Env<'s>>,
+        args: &'s [Expr],
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        self.bind_params(&decl.params, args, env, scope, &input, &mut |scope| {
+            self.eval_expr(&decl.body, &scope, path.clone(), input.clone(), out)
+        })
+    }
+
+    /// Binds each parameter to its argument.
+    ///
+    /// Filter parameters become closures over the caller's scope, while `$name` parameters are
+    /// bound to every output of their argument in turn, as well as being callable as closures.
+    fn bind_params<'s>(
+        &'s self,
+        params: &'s [FnParam],
+        args: &'s [Expr],
+        env: &Rc<Env<'s>>,
+        scope: Rc<Env<'s>>,
+        input: &Value,
+        f: &mut dyn FnMut(Rc<Env<'s>>) -> Result<(), Unwind>,
+    ) -> Result<(), Unwind> {
+        let ((param, params), (arg, args)) = match (params.split_first(), args.split_first()) {
+            (Some(param), Some(arg)) => (param, arg),
+            _ => return f(scope),
+        };
+
+        let closure = |name, parent| Env::Closure {
+            name,
+            body: arg,
+            env: env.clone(),
+            parent,
+        };
+
+        match *param {
+            FnParam::Function(ref name) => {
+                let scope = Rc::new(closure(&name.0[..], scope));
+                self.bind_params(params, args, env, scope, input, f)
+            }
+            FnParam::Variable(ref var) => {
+                let scope = Rc::new(closure(std::slice::from_ref(&var.0), scope));
+                self.eval_expr(arg, env, None, input.clone(), &mut |_, v| {
+                    let scope = env::bind_var(&scope, var, v);
+                    self.bind_params(params, args, env, scope, input, f)
+                })
+            }
+        }
+    }
+
+    /// Evaluates each combination of outputs of `args`, with the first argument varying slowest.
+    fn eval_args<'s>(
+        &'s self,
+        args: &'s [Expr],
+        env: &Rc<Env<'s>>,
+        input: &Value,
+        acc: &mut Vec<Value>,
+        f: &mut dyn FnMut(&[Value]) -> Result<(), Unwind>,
+    ) -> Result<(), Unwind> {
+        match args.split_first() {
+            Some((arg, rest)) => self.eval_expr(arg, env, None, input.clone(), &mut |_, v| {
+                acc.push(v);
+                let result = self.eval_args(rest, env, input, acc, f);
+                acc.pop();
+                result
+            }),
+            None => f(acc),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn eval_if<'s>(
+        &'s self,
+        clause: &'s (Expr, Expr),
+        alts: &'s [(Expr, Expr)],
+        fallback: &'s Expr,
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        let (ref cond, ref then) = *clause;
+        self.eval_expr(cond, env, lenient(&path), input.clone(), &mut |_, c| {
+            let (path, input) = (path.clone(), input.clone());
+            match alts.split_first() {
+                _ if c.is_truthy() => self.eval_expr(then, env, path, input, out),
+                Some((next, rest)) => self.eval_if(next, rest, fallback, env, path, input, out),
+                None => self.eval_expr(fallback, env, path, input, out),
+            }
+        })
+    }
+
+    fn eval_label<'s>(
+        &'s self,
+        label: &'s Label,
+        body: &'s Expr,
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        let id = self.next_label();
+        let parent = env.clone();
+        let name = &(label.0).0;
+        let env = Rc::new(Env::Label { name, id, parent });
+        match self.eval_expr(body, &env, path, input, out) {
+            Err(Unwind::Break(target)) if target == id => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Returns the paths of every output of `expr`.
+    fn collect_paths<'s>(
+        &'s self,
+        expr: &'s Expr,
+        env: &Rc<Env<'s>>,
+        input: &Value,
+    ) -> Result<Vec<Path>, Unwind> {
+        let mut paths = Vec::new();
+        let root = Some(Tracked {
+            path: Vec::new(),
+            strict: true,
+        });
+        self.eval_expr(expr, env, root, input.clone(), &mut |p, _| {
+            paths.push(p.map(|p| p.path).unwrap_or_default());
+            Ok(())
+        })?;
+        Ok(paths)
+    }
+
+    /// Returns the first output of `expr` and the notes it was produced with, without evaluating
+    /// any further.
+    fn first<'s>(
+        &'s self,
+        expr: &'s Expr,
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+    ) -> Result<Option<(Value, Vec<Note>)>, Unwind> {
+        let id = self.next_label();
+        let mark = self.notes.borrow().len();
+        let mut first = None;
+        let result = self.eval_expr(expr, env, path, input, &mut |_, v| {
+            first = Some((v, self.notes_since(mark)));
+            Err(Unwind::Break(id))
+        });
+
+        match result {
+            Err(Unwind::Break(target)) if target == id => Ok(first),
+            result => result.map(|_| first),
+        }
+    }
+
+    /// Evaluates `f` with `notes` applying, as they did when a value kept for later was produced.
+    fn with_notes<R>(&self, notes: Vec<Note>, f: impl FnOnce() -> R) -> R {
+        let mark = self.notes.borrow().len();
+        self.notes.borrow_mut().extend(notes);
+        let result = f();
+        self.notes.borrow_mut().truncate(mark);
+        result
+    }
+
+    /// Evaluates `f` without the notes taken since `mark`, which belong to a value it drops.
+    fn without_notes<R>(&self, mark: usize, f: impl FnOnce() -> R) -> R {
+        let dropped = self.notes.borrow_mut().split_off(mark);
+        let result = f();
+        let mut notes = self.notes.borrow_mut();
+        notes.truncate(mark);
+        notes.extend(dropped);
+        result
+    }
+
+    /// Returns the notes taken since `mark`.
+    fn notes_since(&self, mark: usize) -> Vec<Note> {
+        self.notes.borrow()[mark..].to_vec()
+    }
+
+    fn next_label(&self) -> usize {
+        let id = self.labels.get();
+        self.labels.set(id + 1);
+        id
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+/// Emits a value computed by an expression, which is an error if paths are tracked strictly.
+fn emit(path: &Option<Tracked>, value: Value, out: Output) -> Result<(), Unwind> {
+    match *path {
+        Some(Tracked { strict: true, .. }) => {
+            let msg = format!("Invalid path expression with result {}", describe(&value));
+            Err(msg.into())
+        }
+        _ => out(None, value),
+    }
+}
+
+/// Emits a value kept by `reduce` or `foreach` with the path it was produced with, unless paths
+/// are tracked strictly, for which these are computed values.
+fn emit_kept(
+    path: &Option<Tracked>,
+    kept: Option<Tracked>,
+    value: Value,
+    out: Output,
+) -> Result<(), Unwind> {
+    match *path {
+        Some(Tracked { strict: true, .. }) => emit(path, value, out),
+        _ => out(kept, value),
+    }
+}
+
+/// Emits the input after some of its paths were updated, which keeps the path of the input.
+fn emit_update(path: &Option<Tracked>, value: Value, out: Output) -> Result<(), Unwind> {
+    match *path {
+        Some(Tracked { strict: false, .. }) => out(path.clone(), value),
+        _ => emit(path, value, out),
+    }
+}
+
+/// Returns the path of the input, for a subexpression which computes a value from it.
+fn lenient(path: &Option<Tracked>) -> Option<Tracked> {
+    path.as_ref().map(|tracked| Tracked {
+        path: tracked.path.clone(),
+        strict: false,
+    })
+}
+
+/// Returns the path of a value at `relative` within the input, for an expression updating it.
+fn join(path: &Option<Tracked>, relative: &[Value]) -> Option<Tracked> {
+    lenient(path).map(|mut tracked| {
+        tracked.path.extend_from_slice(relative);
+        tracked
+    })
+}
+
+/// Emits `value` and all of its descendants, as `..` does.
+fn recurse(path: Option<Tracked>, value: Value, out: Output) -> Result<(), Unwind> {
+    let children = match value {
+        Value::Array(_) | Value::Table(_) => path::entries(&value)?,
+        _ => Vec::new(),
+    };
+
+    out(path.clone(), value)?;
+    for (key, child) in children {
+        recurse(push(&path, key), child, out)?;
+    }
+
+    Ok(())
+}
+
+fn push(path: &Option<Tracked>, key: Value) -> Option<Tracked> {
+    path.as_ref().map(|tracked| {
+        let mut tracked = tracked.clone();
+        tracked.path.push(key);
+        tracked
+    })
+}
+
+fn variable(env: &Rc<Env>, var: &Variable) -> Result<Value, Unwind> {
+    match env::lookup_var(env, var) {
+        Some(value) => Ok(value.clone()),
+        None if (var.0).0 == "ENV" => Ok(builtins::environment()),
+        None => Err(format!("{} is not defined", var).into()),
+    }
+}
+
+fn key_error(key: &Value) -> Unwind {
+    format!("Object keys must be strings, not {}", key.type_name()).into()
+}
+
+/// Returns the value of a literal such as `{ a = [1, 2] }`, for building values in tests.
+#[cfg(test)]
+pub(crate) fn value(literal: &str) -> Value {
+    let filter: Filter = literal.parse().unwrap();
+    Interpreter::new()
+        .eval(&filter, Value::Null)
+        .unwrap()
+        .remove(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(filter: &str, input: &str) -> Result<Vec<Value>, String> {
+        let filter: Filter = filter.parse().unwrap();
+        let input = value(input);
+        Interpreter::new()
+            .eval(&filter, input)
+            .map_err(|e| e.to_string())
+    }
+
+    fn values(toml: &[&str]) -> Result<Vec<Value>, String> {
+        Ok(toml.iter().map(|s| value(s)).collect())
+    }
+
+    #[test]
+    fn paths_and_indices() {
+        let input = "{ a = { b = [1, 2, 3] }, c = 'd' }";
+        assert_eq!(run(".a.b[1]", input), values(&["2"]));
+        assert_eq!(run(".a.b[-1]", input), values(&["3"]));
+        assert_eq!(run(".a.b[]", input), values(&["1", "2", "3"]));
+        assert_eq!(
+            run(".[\"c\"], .missing", input),
+            Ok(vec![value("'d'"), Value::Null])
+        );
+        assert_eq!(
+            run("[paths]", input),
+            values(&["[['a'], ['a', 'b'], ['a', 'b', 0], ['a', 'b', 1], ['a', 'b', 2], ['c']]"])
+        );
+        assert!(run(".c.d", input).is_err());
+        assert_eq!(run(".c.d?", input), values(&[]));
+    }
+
+    #[test]
+    fn slices() {
+        let input = "{ a = [0, 1, 2, 3, 4], s = 'häßlich' }";
+        assert_eq!(run(".a[2:]", input), values(&["[2, 3, 4]"]));
+        assert_eq!(run(".a[:3]", input), values(&["[0, 1, 2]"]));
+        assert_eq!(run(".a[1:4]", input), values(&["[1, 2, 3]"]));
+        assert_eq!(run(".a[-2:]", input), values(&["[3, 4]"]));
+        assert_eq!(run(".a[1.2:3.5]", input), values(&["[1, 2, 3]"]));
+        assert_eq!(run(".s[1:4]", input), values(&["'äßl'"]));
+        assert_eq!(run(".s[-3:]", input), values(&["'ich'"]));
+        let path = "['a', { start = 1, end = 3 }]";
+        assert_eq!(run("path(.a[1:3])", input), values(&[path]));
+    }
+
+    #[test]
+    fn assign_through_slices() {
+        let input = "{ authors = ['a', 'b', 'c'], features = { default = ['x', 'y', 'z'] } }";
+        let assigned = "{ authors = ['a'], features = { default = ['x', 'y', 'z'] } }";
+        assert_eq!(run(".authors[1:] = []", input), values(&[assigned]));
+        let deleted = "{ authors = ['a', 'b', 'c'], features = { default = ['z'] } }";
+        assert_eq!(
+            run("del(.features.default[0:2])", input),
+            values(&[deleted])
+        );
+        let updated = "{ authors = ['a', 'b', 'c'], features = { default = ['y', 'z'] } }";
+        assert_eq!(
+            run(".features.default[:2] |= .[1:]", input),
+            values(&[updated])
+        );
+        assert!(run(".authors[1:] = 'd'", input).is_err());
+    }
+
+    #[test]
+    fn updates() {
+        let input = "{ a = 1, b = [1, 2] }";
+        assert_eq!(run(".a = 5 | .a", input), values(&["5"]));
+        assert_eq!(run(".b[] += 10 | .b", input), values(&["[11, 12]"]));
+        assert_eq!(run(".b |= map(. * 2) | .b", input), values(&["[2, 4]"]));
+        assert_eq!(run(".b[] |= empty | .b", input), values(&["[]"]));
+        assert_eq!(run("del(.a) | keys", input), values(&["['b']"]));
+        assert_eq!(run(".c.d = 1 | .c", input), values(&["{ d = 1 }"]));
+        assert!(run("1 = 2", input).is_err());
+    }
+
+    #[test]
+    fn alternatives() {
+        let input = "{ package = { name = 'tq', authors = [] }, a = false }";
+        assert_eq!(
+            run(".package.edition // '2015'", input),
+            values(&["'2015'"])
+        );
+        assert_eq!(run(".package.name // 'x'", input), values(&["'tq'"]));
+        assert_eq!(run("(false, 1, null, 2) // 3", input), values(&["1", "2"]));
+        assert_eq!(run("(false, null) // 4", input), values(&["4"]));
+        assert_eq!(run("empty // 5", input), values(&["5"]));
+        assert_eq!(run("error('x') // 6", input), values(&["6"]));
+        assert_eq!(run(".a // .b // 7", input), values(&["7"]));
+        assert_eq!(run("[1 // 2, 3]", input), values(&["[1, 3]"]));
+        assert!(run("1 // error('downstream')", input).is_ok());
+        assert!(run("(1 // 2) | error('downstream')", input).is_err());
+        assert_eq!(
+            run("path(.a // .package.name)", input),
+            values(&["['package', 'name']"])
+        );
+        assert_eq!(
+            run(".package.edition //= '2015' | .package.edition", input),
+            values(&["'2015'"])
+        );
+        assert_eq!(run(".a //= 8 | .a", input), values(&["8"]));
+        assert_eq!(
+            run(".package.name //= 'x' | .package.name", input),
+            values(&["'tq'"])
+        );
+    }
+
+    #[test]
+    fn inputs() {
+        let mut interpreter = Interpreter::new();
+        let mut rest = vec![Ok(value("2")), Ok(value("3"))].into_iter();
+        interpreter.set_inputs(move || rest.next());
+
+        let filter: Filter = "[., input], [inputs]".parse().unwrap();
+        let outputs = interpreter.eval(&filter, value("1"));
+        assert_eq!(
+            outputs.map_err(|e| e.to_string()),
+            values(&["[1, 2]", "[3]"])
+        );
+
+        let filter: Filter = "input".parse().unwrap();
+        let err = interpreter.eval(&filter, value("1")).unwrap_err();
+        assert_eq!(err.to_string(), "No more inputs");
+        assert!(run("[inputs]", "1").is_ok());
+    }
+
+    #[test]
+    fn control_flow() {
+        let input = "[1, 2, 3, 4]";
+        assert_eq!(run("map(select(. > 2))", input), values(&["[3, 4]"]));
+        assert_eq!(run("reduce .[] as $x (0; . + $x)", input), values(&["10"]));
+        assert_eq!(
+            run("[foreach .[] as $x (0; . + $x; [$x, .])]", input),
+            values(&["[[1, 1], [2, 3], [3, 6], [4, 10]]"])
+        );
+        assert_eq!(run("[limit(2; .[])]", input), values(&["[1, 2]"]));
+        assert_eq!(run("first(.[]), last", input), values(&["1", "4"]));
+        assert_eq!(
+            run(
+                "if length > 3 then 'long' elif length > 1 then 'short' else 'tiny' end",
+                input
+            ),
+            values(&["'long'"])
+        );
+        assert_eq!(run("try error('oops') catch .", input), values(&["'oops'"]));
+        assert_eq!(
+            run(". as [$a, $b] | {a = $a, $b}", input),
+            values(&["{ a = 1, b = 2 }"])
+        );
+        assert_eq!(
+            run(
+                "def f($n): if $n > 0 then $n, f($n - 1) else empty end; [f(3)]",
+                input
+            ),
+            values(&["[3, 2, 1]"])
+        );
+        assert_eq!(
+            run("[.[] | tostring] | join(',')", input),
+            values(&["'1,2,3,4'"])
+        );
+        assert_eq!(
+            run("undefined_fn", input),
+            Err("undefined_fn/0 is not defined".to_string())
+        );
+    }
+}
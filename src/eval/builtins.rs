@@ -0,0 +1,605 @@
+//! Builtin functions implemented natively rather than in `builtin.tq`.
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::env::Env;
+use super::{emit, Interpreter, Note, Output, Tracked, Unwind};
+use crate::ast::tokens::IdentPath;
+use crate::ast::{BinaryOp, Expr};
+use crate::output::toml::Style;
+use crate::value::ops::{self, describe};
+use crate::value::stream::Events;
+use crate::value::{path, Value};
+
+/// A builtin which maps its input and the values of its arguments to a single output.
+type Function = fn(Value, &[Value]) -> Result<Value, String>;
+
+impl Interpreter {
+    /// Calls the native builtin named by `name`, or fails if there is no such builtin.
+    pub(super) fn eval_native<'s>(
+        &'s self,
+        name: &'s IdentPath,
+        args: &'s [Expr],
+        env: &Rc<Env<'s>>,
+        path: Option<Tracked>,
+        input: Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        let arity = args.len();
+        let not_defined = || Unwind::from(format!("{}/{} is not defined", name, arity));
+        let name = match name.0[..] {
+            [ref ident] => ident.0.as_str(),
+            _ => return Err(not_defined()),
+        };
+
+        match (name, arity) {
+            ("empty", 0) => Ok(()),
+            ("error", 0) => Err(Unwind::Error(input)),
+            ("input", 0) => {
+                let next = self.inputs.borrow_mut().as_mut().and_then(|next| next());
+                match next {
+                    Some(Ok(value)) => emit(&path, value, out),
+                    Some(Err(err)) => Err(err.into()),
+                    None => Err("No more inputs".to_string().into()),
+                }
+            }
+            ("getcomment", 0) => {
+                let comment = path.as_ref().and_then(|p| self.comment(&p.path));
+                emit(&path, comment.map_or(Value::Null, Value::String), out)
+            }
+            ("setcomment", 1) => {
+                let arg = super::lenient(&path);
+                self.eval_expr(&args[0], env, arg, input.clone(), &mut |_, comment| {
+                    let comment = match comment {
+                        Value::String(comment) => Some(comment),
+                        Value::Null => None,
+                        comment => {
+                            let msg =
+                                format!("Comments must be strings, not {}", comment.type_name());
+                            return Err(msg.into());
+                        }
+                    };
+                    self.set_comment(&path, comment, &input, out)
+                })
+            }
+            ("location", 0) => {
+                let location = path.as_ref().and_then(|p| {
+                    let (ref file, ref locations) = *self.locations.borrow();
+                    let location = locations.get(&p.path)?;
+                    let table = vec![
+                        ("file".to_string(), Value::from(file.as_str())),
+                        ("line".to_string(), Value::Integer(location.line as i64)),
+                        ("column".to_string(), Value::Integer(location.column as i64)),
+                    ];
+                    Some(Value::Table(table.into_iter().collect()))
+                });
+                emit(&path, location.unwrap_or(Value::Null), out)
+            }
+            ("tostream", 0) => {
+                for event in Events::new(input) {
+                    emit(&path, event, out)?;
+                }
+                Ok(())
+            }
+            ("fromstream", 1) => {
+                // The value being built, and whether it was emitted by the previous event.
+                let (mut x, mut e) = (Value::Null, false);
+                self.eval_expr(
+                    &args[0],
+                    env,
+                    super::lenient(&path),
+                    input,
+                    &mut |_, event| {
+                        if e {
+                            x = Value::Null;
+                        }
+                        let is_leaf = ops::equals(&length(event.clone(), &[])?, &Value::Integer(2));
+                        let p = path::index(&event, &Value::Integer(0))?;
+                        let len = length(p.clone(), &[])?;
+                        if is_leaf {
+                            e = ops::equals(&len, &Value::Integer(0));
+                            let leaf = path::index(&event, &Value::Integer(1))?;
+                            // The prelude appended the path to `["x"]`, to which `null` adds nothing.
+                            let keys = match p {
+                                Value::Array(keys) => keys,
+                                Value::Null => Vec::new(),
+                                p => {
+                                    return Err(format!(
+                                        "Path must be specified as an array, not {}",
+                                        p.type_name()
+                                    )
+                                    .into())
+                                }
+                            };
+                            x = path::set(std::mem::replace(&mut x, Value::Null), &keys, leaf)?;
+                        } else {
+                            e = ops::equals(&len, &Value::Integer(1));
+                        }
+                        if e {
+                            emit(&path, x.clone(), out)
+                        } else {
+                            Ok(())
+                        }
+                    },
+                )
+            }
+            ("delcomment", 0) => self.set_comment(&path, None, &input, out),
+            (name @ ("inline" | "standard" | "dotted" | "aot"), 0) => {
+                let style = match name {
+                    "inline" => Style::Inline,
+                    "standard" => Style::Standard,
+                    "dotted" => Style::Dotted,
+                    _ => Style::ArrayOfTables,
+                };
+                if !style.fits(&input) {
+                    let msg = format!("{} cannot be written with {}", describe(&input), name);
+                    return Err(msg.into());
+                }
+                let note = match path {
+                    Some(ref tracked) => Note::Style(tracked.path.clone(), style),
+                    None => {
+                        let msg = "Cannot set the style of a computed value";
+                        return Err(msg.to_string().into());
+                    }
+                };
+                self.with_notes(vec![note], || out(path, input))
+            }
+            ("path", 1) => {
+                let root = Some(Tracked {
+                    path: Vec::new(),
+                    strict: true,
+                });
+                self.eval_expr(&args[0], env, root, input, &mut |p, _| {
+                    emit(
+                        &path,
+                        Value::Array(p.map(|p| p.path).unwrap_or_default()),
+                        out,
+                    )
+                })
+            }
+            ("getpath", 1) => self.eval_expr(&args[0], env, None, input.clone(), &mut |_, p| {
+                let p = match p {
+                    Value::Array(p) => p,
+                    p => {
+                        return Err(format!(
+                            "Path must be specified as an array, not {}",
+                            p.type_name()
+                        )
+                        .into())
+                    }
+                };
+                let value = path::get(&input, &p)?;
+                let full = path.as_ref().map(|base| Tracked {
+                    path: base.path.iter().cloned().chain(p).collect(),
+                    strict: base.strict,
+                });
+                out(full, value)
+            }),
+            ("range", 2) => self.eval_args(args, env, &input, &mut Vec::new(), &mut |args| {
+                let (from, upto) = match (&args[0], &args[1]) {
+                    (Value::Integer(from), Value::Integer(upto)) => {
+                        for i in *from..*upto {
+                            emit(&path, Value::Integer(i), out)?;
+                        }
+                        return Ok(());
+                    }
+                    (from, upto) => match (from.as_f64(), upto.as_f64()) {
+                        (Some(from), Some(upto)) => (from, upto),
+                        _ => return Err("Range bounds must be numeric".to_string().into()),
+                    },
+                };
+
+                let mut i = from;
+                while i < upto {
+                    emit(&path, Value::Float(i), out)?;
+                    i += 1.0;
+                }
+                Ok(())
+            }),
+            (name, arity) => match function(name, arity) {
+                Some(f) => self.eval_args(args, env, &input, &mut Vec::new(), &mut |args| {
+                    let value = f(input.clone(), args)?;
+                    // These change some paths of their input rather than computing a new value.
+                    if matches!(name, "setpath" | "delpaths") {
+                        super::emit_update(&path, value, out)
+                    } else {
+                        emit(&path, value, out)
+                    }
+                }),
+                None => Err(not_defined()),
+            },
+        }
+    }
+
+    /// Returns the comments of the value at `path`, with the changes applying to the output being
+    /// evaluated.
+    fn comment(&self, path: &[Value]) -> Option<String> {
+        let notes = self.notes.borrow();
+        let changed = notes.iter().rev().find_map(|note| match *note {
+            Note::Comment(ref p, ref comment) if p[..] == *path => Some(comment.clone()),
+            _ => None,
+        });
+        match changed {
+            Some(comment) => comment,
+            None => self.comments.borrow().get(path).map(str::to_string),
+        }
+    }
+
+    /// Emits `input` with the comments of the value at `path` replaced, which must be a value
+    /// selected from the input.
+    fn set_comment(
+        &self,
+        path: &Option<Tracked>,
+        comment: Option<String>,
+        input: &Value,
+        out: Output,
+    ) -> Result<(), Unwind> {
+        match *path {
+            Some(ref tracked) => {
+                let note = Note::Comment(tracked.path.clone(), comment);
+                self.with_notes(vec![note], || out(path.clone(), input.clone()))
+            }
+            None => Err("Cannot set the comment of a computed value"
+                .to_string()
+                .into()),
+        }
+    }
+}
+
+/// Returns the table of environment variables, as seen by `$ENV` and `env`.
+pub(super) fn environment() -> Value {
+    let vars = std::env::vars().map(|(k, v)| (k, Value::String(v)));
+    Value::Table(vars.collect())
+}
+
+fn function(name: &str, arity: usize) -> Option<Function> {
+    let f: Function = match (name, arity) {
+        ("null", 0) => |_, _| Ok(Value::Null),
+        ("not", 0) => |v, _| Ok(Value::Boolean(!v.is_truthy())),
+        ("type", 0) => |v, _| Ok(Value::from(v.type_name())),
+        ("length", 0) => length,
+        ("utf8bytelength", 0) => |v, _| match v {
+            Value::String(s) => Ok(Value::Integer(s.len() as i64)),
+            v => Err(format!(
+                "{} only strings have UTF-8 byte length",
+                describe(&v)
+            )),
+        },
+        ("keys", 0) => |v, _| keys(v, true),
+        ("keys_unsorted", 0) => |v, _| keys(v, false),
+        ("has", 1) => has,
+        ("contains", 1) => |v, args| match contains(&v, &args[0]) {
+            Some(b) => Ok(Value::Boolean(b)),
+            None => Err(format!(
+                "{} and {} cannot have their containment checked",
+                describe(&v),
+                describe(&args[0])
+            )),
+        },
+        ("setpath", 2) => |v, args| match args[0] {
+            Value::Array(ref p) => path::set(v, p, args[1].clone()),
+            ref p => Err(format!(
+                "Path must be specified as an array, not {}",
+                p.type_name()
+            )),
+        },
+        ("delpaths", 1) => |v, args| match args[0] {
+            Value::Array(ref paths) => {
+                let paths = paths.iter().map(|p| match *p {
+                    Value::Array(ref p) => Ok(p.clone()),
+                    ref p => Err(format!(
+                        "Path must be specified as an array, not {}",
+                        p.type_name()
+                    )),
+                });
+                path::delete(v, paths.collect::<Result<_, _>>()?)
+            }
+            ref p => Err(format!(
+                "Paths must be specified as an array, not {}",
+                p.type_name()
+            )),
+        },
+        ("tostring", 0) => |v, _| match v {
+            Value::String(s) => Ok(Value::String(s)),
+            v => Ok(Value::String(v.to_string())),
+        },
+        ("tonumber", 0) => tonumber,
+        ("infinite", 0) => |_, _| Ok(Value::Float(f64::INFINITY)),
+        ("nan", 0) => |_, _| Ok(Value::Float(f64::NAN)),
+        ("isinfinite", 0) => |v, _| math(v, |f| f.is_infinite()).map(Value::Boolean),
+        ("isnan", 0) => |v, _| math(v, f64::is_nan).map(Value::Boolean),
+        ("isnormal", 0) => |v, _| math(v, f64::is_normal).map(Value::Boolean),
+        ("floor", 0) => |v, _| math(v, f64::floor).map(integral),
+        ("ceil", 0) => |v, _| math(v, f64::ceil).map(integral),
+        ("round", 0) => |v, _| math(v, f64::round).map(integral),
+        ("abs", 0) => |v, _| match v {
+            Value::Integer(i) => Ok(i
+                .checked_abs()
+                .map_or(Value::Float(-(i as f64)), Value::Integer)),
+            v => math(v, f64::abs).map(Value::Float),
+        },
+        ("fabs", 0) => |v, _| math(v, f64::abs).map(Value::Float),
+        ("sqrt", 0) => |v, _| math(v, f64::sqrt).map(Value::Float),
+        ("exp", 0) => |v, _| math(v, f64::exp).map(Value::Float),
+        ("exp2", 0) => |v, _| math(v, f64::exp2).map(Value::Float),
+        ("exp10", 0) => |v, _| math(v, |f| 10f64.powf(f)).map(Value::Float),
+        ("log", 0) => |v, _| math(v, f64::ln).map(Value::Float),
+        ("log2", 0) => |v, _| math(v, f64::log2).map(Value::Float),
+        ("log10", 0) => |v, _| math(v, f64::log10).map(Value::Float),
+        ("pow", 2) => |_, args| match (args[0].as_f64(), args[1].as_f64()) {
+            (Some(base), Some(exp)) => Ok(Value::Float(base.powf(exp))),
+            _ => Err(format!(
+                "{} cannot be raised to the power of {}",
+                describe(&args[0]),
+                describe(&args[1])
+            )),
+        },
+        ("sort", 0) => sort,
+        ("min", 0) => |v, _| extreme(v, None, Ordering::Less),
+        ("max", 0) => |v, _| extreme(v, None, Ordering::Greater),
+        ("_sort_by_impl", 1) => |v, args| {
+            sort_by(v, &args[0])
+                .map(|pairs| Value::Array(pairs.into_iter().map(|(_, v)| v).collect()))
+        },
+        ("_group_by_impl", 1) => |v, args| {
+            let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+            for (key, value) in sort_by(v, &args[0])? {
+                match groups.last_mut() {
+                    Some((ref last, ref mut group)) if ops::equals(last, &key) => group.push(value),
+                    _ => groups.push((key, vec![value])),
+                }
+            }
+            Ok(Value::Array(
+                groups.into_iter().map(|(_, g)| Value::Array(g)).collect(),
+            ))
+        },
+        ("_min_by_impl", 1) => |v, args| extreme(v, Some(&args[0]), Ordering::Less),
+        ("_max_by_impl", 1) => |v, args| extreme(v, Some(&args[0]), Ordering::Greater),
+        ("startswith", 1) => |v, args| match (v, &args[0]) {
+            (Value::String(s), Value::String(prefix)) => {
+                Ok(Value::Boolean(s.starts_with(prefix.as_str())))
+            }
+            _ => Err("startswith() requires string inputs".to_string()),
+        },
+        ("endswith", 1) => |v, args| match (v, &args[0]) {
+            (Value::String(s), Value::String(suffix)) => {
+                Ok(Value::Boolean(s.ends_with(suffix.as_str())))
+            }
+            _ => Err("endswith() requires string inputs".to_string()),
+        },
+        ("ltrimstr", 1) => |v, args| match (v, &args[0]) {
+            (Value::String(s), Value::String(prefix)) => match s.strip_prefix(prefix.as_str()) {
+                Some(rest) => Ok(Value::from(rest)),
+                None => Ok(Value::String(s)),
+            },
+            (v, _) => Ok(v),
+        },
+        ("rtrimstr", 1) => |v, args| match (v, &args[0]) {
+            (Value::String(s), Value::String(suffix)) => match s.strip_suffix(suffix.as_str()) {
+                Some(rest) => Ok(Value::from(rest)),
+                None => Ok(Value::String(s)),
+            },
+            (v, _) => Ok(v),
+        },
+        ("trim", 0) => |v, _| trim(v, str::trim),
+        ("ltrim", 0) => |v, _| trim(v, str::trim_start),
+        ("rtrim", 0) => |v, _| trim(v, str::trim_end),
+        ("explode", 0) => |v, _| match v {
+            Value::String(s) => Ok(Value::Array(
+                s.chars().map(|c| Value::Integer(c as i64)).collect(),
+            )),
+            v => Err(format!("{} cannot be exploded", describe(&v))),
+        },
+        ("implode", 0) => implode,
+        ("split", 1) => |v, args| ops::binary(&BinaryOp::Div, v, args[0].clone()),
+        ("_strindices", 1) => |v, args| match (v, &args[0]) {
+            (Value::String(s), Value::String(needle)) if !needle.is_empty() => {
+                let indices = s
+                    .char_indices()
+                    .enumerate()
+                    .filter(|(_, (i, _))| s[*i..].starts_with(needle.as_str()))
+                    .map(|(n, _)| Value::Integer(n as i64));
+                Ok(Value::Array(indices.collect()))
+            }
+            (Value::String(_), Value::String(_)) => Ok(Value::Null),
+            _ => Err("_strindices() requires string inputs".to_string()),
+        },
+        ("env", 0) => |_, _| Ok(environment()),
+        ("debug", 0) => |v, _| {
+            eprintln!("[\"DEBUG:\", {}]", v);
+            Ok(v)
+        },
+        ("stderr", 0) => |v, _| {
+            eprint!("{}", v);
+            Ok(v)
+        },
+        _ => return None,
+    };
+
+    Some(f)
+}
+
+fn length(value: Value, _: &[Value]) -> Result<Value, String> {
+    match value {
+        Value::Null => Ok(Value::Integer(0)),
+        Value::Boolean(_) | Value::Datetime(_) => {
+            Err(format!("{} has no length", describe(&value)))
+        }
+        Value::Integer(i) => Ok(i
+            .checked_abs()
+            .map_or(Value::Float(-(i as f64)), Value::Integer)),
+        Value::Float(f) => Ok(Value::Float(f.abs())),
+        Value::String(s) => Ok(Value::Integer(s.chars().count() as i64)),
+        Value::Array(a) => Ok(Value::Integer(a.len() as i64)),
+        Value::Table(t) => Ok(Value::Integer(t.len() as i64)),
+    }
+}
+
+fn keys(value: Value, sort: bool) -> Result<Value, String> {
+    match value {
+        Value::Table(t) => {
+            let mut keys: Vec<_> = t.into_keys().collect();
+            if sort {
+                keys.sort();
+            }
+            Ok(Value::Array(keys.into_iter().map(Value::String).collect()))
+        }
+        Value::Array(a) => Ok(Value::Array(
+            (0..a.len() as i64).map(Value::Integer).collect(),
+        )),
+        value => Err(format!("{} has no keys", describe(&value))),
+    }
+}
+
+fn has(value: Value, args: &[Value]) -> Result<Value, String> {
+    match (&value, &args[0]) {
+        (Value::Table(t), Value::String(k)) => Ok(Value::Boolean(t.contains_key(k))),
+        (Value::Array(a), key) if key.as_f64().is_some() => {
+            let i = key.as_f64().unwrap();
+            Ok(Value::Boolean(i >= 0.0 && i < a.len() as f64))
+        }
+        (value, key) => Err(format!(
+            "Cannot check whether {} has a {} key",
+            value.type_name(),
+            key.type_name()
+        )),
+    }
+}
+
+/// Returns whether `b` is contained within `a`, or `None` if they cannot be compared.
+fn contains(a: &Value, b: &Value) -> Option<bool> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Some(a.contains(b.as_str())),
+        (Value::Array(a), Value::Array(b)) => {
+            let found = |b: &Value| a.iter().any(|a| contains(a, b) == Some(true));
+            Some(b.iter().all(found))
+        }
+        (Value::Table(a), Value::Table(b)) => Some(
+            b.iter()
+                .all(|(k, b)| a.get(k).and_then(|a| contains(a, b)) == Some(true)),
+        ),
+        (a, b) if a.type_name() == b.type_name() => Some(ops::equals(a, b)),
+        _ => None,
+    }
+}
+
+fn tonumber(value: Value, _: &[Value]) -> Result<Value, String> {
+    match value {
+        Value::Integer(_) | Value::Float(_) => Ok(value),
+        Value::String(ref s) => {
+            let text = s.trim();
+            text.parse::<i64>()
+                .map(Value::Integer)
+                .or_else(|_| text.parse::<f64>().map(Value::Float))
+                .map_err(|_| format!("Cannot parse '{}' as number", s))
+        }
+        value => Err(format!("{} cannot be parsed as a number", describe(&value))),
+    }
+}
+
+fn math<T, F: FnOnce(f64) -> T>(value: Value, f: F) -> Result<T, String> {
+    match value.as_f64() {
+        Some(n) => Ok(f(n)),
+        None => Err(format!("{} number required", describe(&value))),
+    }
+}
+
+/// Converts a float with no fractional part into an integer, if it fits.
+fn integral(f: f64) -> Value {
+    if f.is_finite() && f >= i64::MIN as f64 && f < i64::MAX as f64 {
+        Value::Integer(f as i64)
+    } else {
+        Value::Float(f)
+    }
+}
+
+fn trim(value: Value, f: fn(&str) -> &str) -> Result<Value, String> {
+    match value {
+        Value::String(s) => Ok(Value::from(f(&s))),
+        value => Err(format!("{} cannot be trimmed", describe(&value))),
+    }
+}
+
+fn implode(value: Value, _: &[Value]) -> Result<Value, String> {
+    let codepoints = match value {
+        Value::Array(a) => a,
+        value => return Err(format!("{} cannot be imploded", describe(&value))),
+    };
+
+    codepoints
+        .iter()
+        .map(|c| match *c {
+            Value::Integer(i) if i >= 0 && i <= u32::MAX as i64 => std::char::from_u32(i as u32)
+                .ok_or_else(|| format!("Invalid codepoint literal {}", i)),
+            ref c => Err(format!(
+                "Unicode codepoint must be numeric, not {}",
+                c.type_name()
+            )),
+        })
+        .collect::<Result<String, _>>()
+        .map(Value::String)
+}
+
+fn sort(value: Value, _: &[Value]) -> Result<Value, String> {
+    match value {
+        Value::Array(mut a) => {
+            a.sort_by(ops::compare);
+            Ok(Value::Array(a))
+        }
+        value => Err(format!(
+            "{} cannot be sorted, as it is not an array",
+            describe(&value)
+        )),
+    }
+}
+
+/// Pairs each element of `value` with its key from `keys` and sorts them stably by key.
+fn sort_by(value: Value, keys: &Value) -> Result<Vec<(Value, Value)>, String> {
+    match (value, keys) {
+        (Value::Array(values), Value::Array(keys)) if values.len() == keys.len() => {
+            let mut pairs: Vec<_> = keys.iter().cloned().zip(values).collect();
+            pairs.sort_by(|(a, _), (b, _)| ops::compare(a, b));
+            Ok(pairs)
+        }
+        (value, _) => Err(format!(
+            "{} cannot be sorted, as it is not an array",
+            describe(&value)
+        )),
+    }
+}
+
+/// Returns the minimum or maximum element of an array, optionally comparing by `keys`.
+///
+/// Ties are resolved in favour of the first minimum and the last maximum, as in jq.
+fn extreme(value: Value, keys: Option<&Value>, wanted: Ordering) -> Result<Value, String> {
+    let pairs = match keys {
+        Some(keys) => sort_by(value, keys)?,
+        None => match value {
+            Value::Array(a) => a.into_iter().map(|v| (v.clone(), v)).collect(),
+            value => {
+                return Err(format!(
+                    "{} cannot be compared, as it is not an array",
+                    describe(&value)
+                ))
+            }
+        },
+    };
+
+    let mut best: Option<(Value, Value)> = None;
+    for (key, value) in pairs {
+        let replace = match best {
+            Some((ref best_key, _)) => match ops::compare(&key, best_key) {
+                Ordering::Equal => wanted == Ordering::Greater,
+                ordering => ordering == wanted,
+            },
+            None => true,
+        };
+
+        if replace {
+            best = Some((key, value));
+        }
+    }
+
+    Ok(best.map_or(Value::Null, |(_, value)| value))
+}
@@ -0,0 +1,128 @@
+//! Lexical scopes of variables, functions and labels.
+
+use std::rc::Rc;
+
+use crate::ast::tokens::{Ident, Variable};
+use crate::ast::{Expr, ExprFnDecl};
+use crate::value::Value;
+
+/// A single binding in a chain of lexical scopes.
+///
+/// Scopes are persistent linked lists, so that closures can capture the environment in which they
+/// were created without copying it.
+pub enum Env<'a> {
+    /// The outermost scope, in which only builtin functions are defined.
+    Root,
+    /// `. as $name | ...`
+    Var {
+        name: &'a Ident,
+        value: Value,
+        parent: Rc<Env<'a>>,
+    },
+    /// `def name(params): body; ...`
+    Func {
+        decl: &'a ExprFnDecl,
+        parent: Rc<Env<'a>>,
+    },
+    /// A filter passed as an argument to a function, evaluated in the caller's scope.
+    Closure {
+        name: &'a [Ident],
+        body: &'a Expr,
+        env: Rc<Env<'a>>,
+        parent: Rc<Env<'a>>,
+    },
+    /// `label $name | ...`
+    Label {
+        name: &'a Ident,
+        id: usize,
+        parent: Rc<Env<'a>>,
+    },
+}
+
+/// A function found by [`lookup_fn`](./fn.lookup_fn.html).
+pub enum Callable<'a> {
+    /// A function declaration, along with the scope it was declared in, which includes itself.
+    Func(&'a ExprFnDecl, Rc<Env<'a>>),
+    /// A closure body, along with the scope it should be evaluated in.
+    Closure(&'a Expr, Rc<Env<'a>>),
+}
+
+/// Returns a new scope in which `var` is bound to `value`.
+pub fn bind_var<'a>(env: &Rc<Env<'a>>, var: &'a Variable, value: Value) -> Rc<Env<'a>> {
+    Rc::new(Env::Var {
+        name: &var.0,
+        value,
+        parent: env.clone(),
+    })
+}
+
+/// Looks up the value of a variable.
+pub fn lookup_var<'e>(mut env: &'e Rc<Env>, var: &Variable) -> Option<&'e Value> {
+    loop {
+        env = match **env {
+            Env::Root => return None,
+            Env::Var {
+                name,
+                ref value,
+                ref parent,
+            } => {
+                if *name == var.0 {
+                    return Some(value);
+                }
+                parent
+            }
+            Env::Func { ref parent, .. }
+            | Env::Closure { ref parent, .. }
+            | Env::Label { ref parent, .. } => parent,
+        };
+    }
+}
+
+/// Looks up a user-defined function or closure by name and arity.
+pub fn lookup_fn<'a>(mut env: &Rc<Env<'a>>, path: &[Ident], arity: usize) -> Option<Callable<'a>> {
+    loop {
+        env = match **env {
+            Env::Root => return None,
+            Env::Func { decl, ref parent } => {
+                if decl.name.0 == path && decl.params.len() == arity {
+                    return Some(Callable::Func(decl, env.clone()));
+                }
+                parent
+            }
+            Env::Closure {
+                name,
+                body,
+                env: ref closure_env,
+                ref parent,
+            } => {
+                if name == path && arity == 0 {
+                    return Some(Callable::Closure(body, closure_env.clone()));
+                }
+                parent
+            }
+            Env::Var { ref parent, .. } | Env::Label { ref parent, .. } => parent,
+        };
+    }
+}
+
+/// Looks up the unique identifier of a label.
+pub fn lookup_label(mut env: &Rc<Env>, label: &Variable) -> Option<usize> {
+    loop {
+        env = match **env {
+            Env::Root => return None,
+            Env::Label {
+                name,
+                id,
+                ref parent,
+            } => {
+                if *name == label.0 {
+                    return Some(id);
+                }
+                parent
+            }
+            Env::Var { ref parent, .. }
+            | Env::Func { ref parent, .. }
+            | Env::Closure { ref parent, .. } => parent,
+        };
+    }
+}
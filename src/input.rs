@@ -0,0 +1,247 @@
+//! Parsing of filter inputs in formats other than TOML.
+//!
+//! TOML inputs are parsed as [`Document`](../document/struct.Document.html)s instead, so that
+//! they can be written back with their formatting intact.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use toml_edit::ImDocument;
+
+use crate::output::json::DATETIME_TAG;
+use crate::value::Value;
+
+pub use self::flat::from_flat;
+pub use self::yaml::from_yaml;
+
+mod flat;
+pub mod stream;
+pub(crate) mod yaml;
+
+/// A format in which inputs are read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// Detected from the extension of the file, or its contents.
+    Auto,
+    /// One `path = value` line per leaf, as written by the `flat` output format.
+    Flat,
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// Resolves `Auto` to the format of the file at `path`, whose contents are `text`.
+    ///
+    /// Files ending in `.json`, `.toml`, `.yaml` or `.yml` are read as such. Otherwise, text which
+    /// parses as JSON and starts with `{` or `[` is read as JSON, text starting with a `---` YAML
+    /// document marker is read as YAML, and anything else as TOML.
+    pub fn detect(self, path: Option<&Path>, text: &str) -> Format {
+        if self != Format::Auto {
+            return self;
+        }
+
+        match from_extension(path) {
+            Some(format) => format,
+            None if text.starts_with("---") => Format::Yaml,
+            None if text.trim_start().starts_with(['{', '[']) && from_json(text, false).is_ok() => {
+                Format::Json
+            }
+            None => Format::Toml,
+        }
+    }
+
+    /// Like [`detect`](#method.detect), but from only the start of the file, as when it is
+    /// streamed.
+    ///
+    /// Text starting with `{`, or with `[` on a line which is not a TOML table header, is read as
+    /// JSON rather than parsed as a whole.
+    pub fn detect_start(self, path: Option<&Path>, start: &str) -> Format {
+        if self != Format::Auto {
+            return self;
+        }
+
+        let start = match from_extension(path) {
+            Some(format) => return format,
+            None if start.starts_with("---") => return Format::Yaml,
+            None => start.trim_start(),
+        };
+        let line = start.lines().next().unwrap_or("");
+        if start.starts_with('{') || (start.starts_with('[') && ImDocument::parse(line).is_err()) {
+            Format::Json
+        } else {
+            Format::Toml
+        }
+    }
+}
+
+fn from_extension(path: Option<&Path>) -> Option<Format> {
+    match path.and_then(|p| p.extension()).and_then(|e| e.to_str()) {
+        Some("json") => Some(Format::Json),
+        Some("toml") => Some(Format::Toml),
+        Some("yaml") | Some("yml") => Some(Format::Yaml),
+        _ => None,
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Format::Auto),
+            "flat" => Ok(Format::Flat),
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "yaml" => Ok(Format::Yaml),
+            s => Err(format!("Unknown input format `{}`", s)),
+        }
+    }
+}
+
+/// Splits a file with TOML front matter into the text before the TOML, the TOML itself, and the
+/// text after it, starting with the closing fence.
+///
+/// The front matter is delimited by `+++` lines, as in Zola and Hugo pages, or by a `---cargo`
+/// fence as in cargo scripts, which may follow a `#!` line. Returns `None` if there is none.
+pub fn front_matter(text: &str) -> Option<(&str, &str, &str)> {
+    let line_end = |from: usize| text[from..].find('\n').map_or(text.len(), |i| from + i + 1);
+    let start = if text.starts_with("#!") && !text.starts_with("#![") {
+        line_end(0)
+    } else {
+        0
+    };
+
+    let body = line_end(start);
+    let open = text[start..body].trim_end();
+    let fence = match open.len() - open.trim_start_matches('-').len() {
+        _ if open == "+++" => open,
+        n if n >= 3 && matches!(open[n..].trim(), "" | "cargo") => &open[..n],
+        _ => return None,
+    };
+
+    let mut pos = body;
+    while pos < text.len() {
+        let end = line_end(pos);
+        if text[pos..end].trim_end() == fence {
+            return Some((&text[..body], &text[body..pos], &text[pos..]));
+        }
+        pos = end;
+    }
+    None
+}
+
+/// Parses a sequence of whitespace-separated JSON values.
+///
+/// With `tagged_datetimes`, objects of the form `{"$datetime": "..."}` are read as datetimes.
+pub fn from_json(text: &str, tagged_datetimes: bool) -> Result<Vec<Value>, String> {
+    serde_json::Deserializer::from_str(text)
+        .into_iter()
+        .map(|value| match value {
+            Ok(value) => from_json_value(value, tagged_datetimes),
+            Err(err) => Err(err.to_string()),
+        })
+        .collect()
+}
+
+fn from_json_value(value: serde_json::Value, tagged_datetimes: bool) -> Result<Value, String> {
+    use serde_json::Value as J;
+    match value {
+        J::Null => Ok(Value::Null),
+        J::Bool(b) => Ok(Value::Boolean(b)),
+        J::Number(n) => match n.as_i64() {
+            Some(i) => Ok(Value::Integer(i)),
+            None => Ok(Value::Float(n.as_f64().unwrap_or(f64::NAN))),
+        },
+        J::String(s) => Ok(Value::String(s)),
+        J::Array(array) => array
+            .into_iter()
+            .map(|v| from_json_value(v, tagged_datetimes))
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        J::Object(object) => {
+            if tagged_datetimes && object.len() == 1 {
+                if let Some(J::String(dt)) = object.get(DATETIME_TAG) {
+                    return dt
+                        .parse()
+                        .map(Value::Datetime)
+                        .map_err(|_| format!("Invalid datetime {:?}", dt));
+                }
+            }
+
+            object
+                .into_iter()
+                .map(|(k, v)| from_json_value(v, tagged_datetimes).map(|v| (k, v)))
+                .collect::<Result<_, _>>()
+                .map(Value::Table)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_stream() {
+        let values = from_json(r#"{"b": [1, 2.5, null], "a": "x"} 3 "y""#, false).unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0].to_string(), r#"{ b = [1, 2.5, null], a = "x" }"#);
+        assert_eq!(values[1], Value::Integer(3));
+        assert!(from_json("{", false).is_err());
+    }
+
+    #[test]
+    fn tagged_datetimes() {
+        let json = r#"[{"$datetime": "1979-05-27T07:32:00Z"}, {"$datetime": 1}]"#;
+        let expected = "[1979-05-27T07:32:00Z, { \"$datetime\" = 1 }]";
+        assert_eq!(from_json(json, true).unwrap()[0].to_string(), expected);
+        assert!(from_json(r#"{"$datetime": "yesterday"}"#, true).is_err());
+        assert_eq!(from_json(r#"{"$datetime": "x"}"#, false).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn front_matter() {
+        let page = "+++\ntitle = 'tq'\n+++\n# Hello\n+++\n";
+        let expected = ("+++\n", "title = 'tq'\n", "+++\n# Hello\n+++\n");
+        assert_eq!(super::front_matter(page), Some(expected));
+
+        let script = "#!/usr/bin/env cargo\n----cargo\r\n[dependencies]\n---\n----\r\nfn main() {}";
+        let expected = (
+            "#!/usr/bin/env cargo\n----cargo\r\n",
+            "[dependencies]\n---\n",
+            "----\r\nfn main() {}",
+        );
+        assert_eq!(super::front_matter(script), Some(expected));
+
+        assert_eq!(super::front_matter("+++\na = 1\n"), None);
+        assert_eq!(super::front_matter("---yaml\na: 1\n---\n"), None);
+        assert_eq!(super::front_matter("#![allow(unused)]\n---\n---\n"), None);
+    }
+
+    #[test]
+    fn detect() {
+        let auto = |path: &str, text: &str| Format::Auto.detect(Some(Path::new(path)), text);
+        assert_eq!(auto("Cargo.toml", "{}"), Format::Toml);
+        assert_eq!(auto("metadata.json", "a = 1"), Format::Json);
+        assert_eq!(auto("-", r#"{"a": 1}"#), Format::Json);
+        assert_eq!(auto("-", "[package]\nname = 'tq'"), Format::Toml);
+        assert_eq!(auto("-", "[1, 2]"), Format::Json);
+        assert_eq!(auto(".gitlab-ci.yml", "[1, 2]"), Format::Yaml);
+        assert_eq!(auto("-", "---\na: 1"), Format::Yaml);
+        assert_eq!(Format::Toml.detect(None, "{}"), Format::Toml);
+
+        let start = |text: &str| Format::Auto.detect_start(Some(Path::new("-")), text);
+        assert_eq!(start("[package]\nname = 'tq'"), Format::Toml);
+        assert_eq!(start("[[bin]] # first\n"), Format::Toml);
+        assert_eq!(start("\n[\n  1,"), Format::Json);
+        assert_eq!(start("[{\"a\": 1}, {\"a\": 2"), Format::Json);
+        assert_eq!(start("{\"a\":"), Format::Json);
+        assert_eq!(start("---\na: 1"), Format::Yaml);
+        assert_eq!(start("a = 1"), Format::Toml);
+        assert_eq!(
+            Format::Auto.detect_start(Some(Path::new("a.json")), "a"),
+            Format::Json
+        );
+    }
+}
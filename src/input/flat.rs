@@ -0,0 +1,125 @@
+//! Parsing of values written one line per leaf, as by `--output-format flat`.
+//!
+//! Each line is a path, followed by `=` and an inline TOML value or `null`. Keys in the path are
+//! bare or quoted TOML keys separated by dots, and array indices are written in brackets, e.g.
+//! `package.metadata."docs.rs".all-features = true` or `bin[0].name = "tq"`. Blank lines and
+//! lines starting with `#` are ignored.
+
+use crate::value::{path, Value};
+
+/// Parses the leaves of a table or array, one per line.
+pub fn from_flat(text: &str) -> Result<Value, String> {
+    let mut root = Value::Null;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (keys, value) = leaf(line).map_err(|msg| format!("{} at line {}", msg, i + 1))?;
+        root = path::set(root, &keys, value)?;
+    }
+
+    match root {
+        Value::Null => Ok(Value::Table(Default::default())),
+        root => Ok(root),
+    }
+}
+
+/// Parses a `path = value` line into the components of the path and the value.
+fn leaf(line: &str) -> Result<(Vec<Value>, Value), String> {
+    let mut keys = Vec::new();
+    let mut rest = line;
+    loop {
+        rest = rest.trim_start();
+        if let Some(index) = rest.strip_prefix('[') {
+            let end = index.find(']').ok_or("expected `]`")?;
+            let i: i64 = index[..end].trim().parse().map_err(|_| "invalid index")?;
+            keys.push(Value::Integer(i));
+            rest = &index[end + 1..];
+        } else if rest.starts_with('=') && !keys.is_empty() {
+            break;
+        } else {
+            if !keys.is_empty() {
+                rest = rest.strip_prefix('.').ok_or("expected `.`, `[` or `=`")?;
+                rest = rest.trim_start();
+            }
+            let (key, remainder) = key(rest)?;
+            keys.push(Value::String(key));
+            rest = remainder;
+        }
+    }
+
+    let value = rest[1..].trim();
+    match value {
+        "null" => Ok((keys, Value::Null)),
+        value => Ok((keys, toml_value(value)?)),
+    }
+}
+
+/// Parses a bare or quoted key at the start of `text`, returning it with the rest of `text`.
+fn key(text: &str) -> Result<(String, &str), String> {
+    let end = match text.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => {
+            let mut escaped = false;
+            let end = text[1..].find(|c| {
+                let end = c == quote && !escaped;
+                escaped = quote == '"' && c == '\\' && !escaped;
+                end
+            });
+            end.map(|end| end + 2).ok_or("unterminated key")?
+        }
+        _ => text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(text.len()),
+    };
+
+    match (&text[..end], toml_value(&text[..end])) {
+        ("", _) => Err("expected a key".to_string()),
+        (key, _) if !key.starts_with(['"', '\'']) => Ok((key.to_string(), &text[end..])),
+        (_, Ok(Value::String(key))) => Ok((key, &text[end..])),
+        _ => Err("invalid key".to_string()),
+    }
+}
+
+/// Parses an inline TOML value.
+fn toml_value(text: &str) -> Result<Value, String> {
+    let mut table: toml::value::Table = toml::from_str(&format!("value = {}", text))
+        .map_err(|_| format!("invalid value `{}`", text))?;
+    Ok(table
+        .remove("value")
+        .map(Value::from)
+        .unwrap_or(Value::Null))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let flat = "# comment\n\nbin[0].name = \"tq\"\nbin[1] = 'x'\n'a.b'.\"c\\\"\" = [1, 2]\n";
+        let expected = "{ bin = [{ name = \"tq\" }, \"x\"], \"a.b\" = { \"c\\\"\" = [1, 2] } }";
+        assert_eq!(from_flat(flat).unwrap().to_string(), expected);
+        assert_eq!(
+            from_flat("[0][1] = null").unwrap().to_string(),
+            "[[null, null]]"
+        );
+        assert_eq!(from_flat("").unwrap().to_string(), "{}");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            from_flat("a = 1\nb.c").unwrap_err(),
+            "expected `.`, `[` or `=` at line 2"
+        );
+        assert_eq!(
+            from_flat("a = \"").unwrap_err(),
+            "invalid value `\"` at line 1"
+        );
+        assert!(from_flat("= 1").is_err());
+        assert!(from_flat("a[x] = 1").is_err());
+        assert!(from_flat("a = 1\na.b = 2").is_err());
+    }
+}
@@ -0,0 +1,798 @@
+//! Incremental reading of inputs as `[path, leaf]` events, as done by `--stream`.
+//!
+//! JSON is read one token at a time and TOML one table at a time, so that the events of an input
+//! are produced before the rest of it is read, and only the table being read is kept in memory.
+//! The events are those `tostream` produces for each value, except that a TOML table which is
+//! written in several places, as `[a]` is in `[a]`, `[b]`, `[a.c]`, is closed and opened again.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::BufRead;
+use std::ops::Range;
+
+use toml_edit::{ImDocument, Item};
+
+use crate::document;
+use crate::output::json::DATETIME_TAG;
+use crate::value::{Table, Value};
+
+type Path = Vec<Value>;
+
+/// The events of values whose leaves are read one after the other.
+#[derive(Default)]
+struct Events {
+    queue: VecDeque<Value>,
+    /// The path of the last leaf of the value being read, if any.
+    last: Option<Path>,
+}
+
+impl Events {
+    /// Queues the event of `leaf`, after those closing the arrays and tables of the previous leaf
+    /// which do not hold this one.
+    fn leaf(&mut self, path: Path, leaf: Value) {
+        if let Some(last) = self.last.take() {
+            let common = last.iter().zip(&path).take_while(|(a, b)| a == b).count();
+            self.close(&last, common + 1);
+        }
+        let event = vec![Value::Array(path.clone()), leaf];
+        self.queue.push_back(Value::Array(event));
+        self.last = Some(path);
+    }
+
+    /// Queues the leaves of `value`, at `path`.
+    fn leaves(&mut self, path: &mut Path, value: Value) {
+        match value {
+            Value::Array(array) if !array.is_empty() => {
+                for (i, v) in array.into_iter().enumerate() {
+                    path.push(Value::Integer(i as i64));
+                    self.leaves(path, v);
+                    path.pop();
+                }
+            }
+            Value::Table(table) if !table.is_empty() => {
+                for (k, v) in table {
+                    path.push(Value::String(k));
+                    self.leaves(path, v);
+                    path.pop();
+                }
+            }
+            leaf => self.leaf(path.clone(), leaf),
+        }
+    }
+
+    /// Closes every array and table of the value being read.
+    fn end(&mut self) {
+        if let Some(last) = self.last.take() {
+            self.close(&last, 0);
+        }
+    }
+
+    /// Closes the arrays and tables holding `last`, except for the outermost `open` of them.
+    fn close(&mut self, last: &[Value], open: usize) {
+        for end in (open + 1..=last.len()).rev() {
+            let event = vec![Value::Array(last[..end].to_vec())];
+            self.queue.push_back(Value::Array(event));
+        }
+    }
+}
+
+/// The events of a sequence of whitespace-separated JSON values, read one token at a time.
+pub struct JsonEvents<R> {
+    reader: R,
+    tagged_datetimes: bool,
+    events: Events,
+    /// The array or table being read at each level, innermost last.
+    stack: Vec<Frame>,
+    /// Whether a value is expected next, rather than what follows one.
+    value_next: bool,
+    line: usize,
+    column: usize,
+    done: bool,
+}
+
+enum Frame {
+    /// The index of the element being read.
+    Array(i64),
+    /// The key of the member being read.
+    Table(String),
+}
+
+impl<R: BufRead> JsonEvents<R> {
+    /// With `tagged_datetimes`, objects of the form `{"$datetime": "..."}` are read as datetimes.
+    pub fn new(reader: R, tagged_datetimes: bool) -> Self {
+        JsonEvents {
+            reader,
+            tagged_datetimes,
+            events: Events::default(),
+            stack: Vec::new(),
+            value_next: true,
+            line: 1,
+            column: 0,
+            done: false,
+        }
+    }
+
+    /// Reads the next token, queuing the events it ends.
+    fn step(&mut self) -> Result<(), String> {
+        let byte = match self.peek_token()? {
+            Some(byte) => byte,
+            None if self.stack.is_empty() && self.value_next => {
+                self.done = true;
+                return Ok(());
+            }
+            None => return Err(self.error("EOF while parsing a value")),
+        };
+        if self.value_next {
+            return self.value(byte);
+        }
+
+        self.bump()?;
+        let array = matches!(self.stack.last(), Some(Frame::Array(_)));
+        match byte {
+            b',' if array => {
+                if let Some(Frame::Array(ref mut i)) = self.stack.last_mut() {
+                    *i += 1;
+                }
+                self.value_next = true;
+            }
+            b',' => {
+                let key = self.key()?;
+                self.stack.pop();
+                self.stack.push(Frame::Table(key));
+                self.value_next = true;
+            }
+            b']' if array => {
+                self.stack.pop();
+                self.end_value();
+            }
+            b'}' if !array => {
+                self.stack.pop();
+                self.end_value();
+            }
+            _ if array => return Err(self.error("expected `,` or `]`")),
+            _ => return Err(self.error("expected `,` or `}`")),
+        }
+        Ok(())
+    }
+
+    /// Reads a value starting with `byte`, or the first token of one.
+    fn value(&mut self, byte: u8) -> Result<(), String> {
+        let leaf = match byte {
+            b'[' => {
+                self.bump()?;
+                if self.peek_token()? == Some(b']') {
+                    self.bump()?;
+                    Value::Array(Vec::new())
+                } else {
+                    self.stack.push(Frame::Array(0));
+                    return Ok(());
+                }
+            }
+            b'{' => {
+                self.bump()?;
+                if self.peek_token()? == Some(b'}') {
+                    self.bump()?;
+                    Value::Table(Table::new())
+                } else {
+                    let key = self.key()?;
+                    if self.tagged_datetimes && key == DATETIME_TAG {
+                        return self.datetime();
+                    }
+                    self.stack.push(Frame::Table(key));
+                    return Ok(());
+                }
+            }
+            b'"' => Value::String(self.string()?),
+            b'-' | b'0'..=b'9' => self.number()?,
+            _ => {
+                let column = self.column;
+                match self.take_while(|b| b.is_ascii_alphanumeric())?.as_slice() {
+                    b"true" => Value::Boolean(true),
+                    b"false" => Value::Boolean(false),
+                    b"null" => Value::Null,
+                    _ => return Err(self.error_at("expected value", column)),
+                }
+            }
+        };
+        self.events.leaf(self.path(), leaf);
+        self.end_value();
+        Ok(())
+    }
+
+    /// Reads the rest of a table whose first key is `$datetime`, which is a datetime if that is
+    /// its only member and a string.
+    fn datetime(&mut self) -> Result<(), String> {
+        self.stack.push(Frame::Table(DATETIME_TAG.to_string()));
+        if self.peek_token()? != Some(b'"') {
+            return Ok(());
+        }
+
+        let dt = self.string()?;
+        if self.peek_token()? == Some(b'}') {
+            self.bump()?;
+            self.stack.pop();
+            let value = dt
+                .parse()
+                .map(Value::Datetime)
+                .map_err(|_| format!("Invalid datetime {:?}", dt))?;
+            self.events.leaf(self.path(), value);
+            self.end_value();
+        } else {
+            self.events.leaf(self.path(), Value::String(dt));
+            self.value_next = false;
+        }
+        Ok(())
+    }
+
+    /// Moves on after a value, which ends the events of a top-level one.
+    fn end_value(&mut self) {
+        self.value_next = self.stack.is_empty();
+        if self.stack.is_empty() {
+            self.events.end();
+        }
+    }
+
+    /// Reads the key of a table member, along with the `:` after it.
+    fn key(&mut self) -> Result<String, String> {
+        if self.peek_token()? != Some(b'"') {
+            return Err(self.error("key must be a string"));
+        }
+        let key = self.string()?;
+        if self.peek_token()? != Some(b':') {
+            return Err(self.error("expected `:`"));
+        }
+        self.bump()?;
+        Ok(key)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut raw = vec![self.bump()?.unwrap_or(b'"')];
+        loop {
+            match self.bump()? {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    raw.push(b'\\');
+                    raw.extend(self.bump()?);
+                }
+                Some(byte) => raw.push(byte),
+                None => return Err(self.error("EOF while parsing a string")),
+            }
+        }
+        raw.push(b'"');
+        serde_json::from_slice(&raw).map_err(|e| self.error(&error_message(&e)))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let raw = self.take_while(|b| b.is_ascii_digit() || b"+-.eE".contains(&b))?;
+        match serde_json::from_slice::<serde_json::Number>(&raw) {
+            Ok(n) => match n.as_i64() {
+                Some(i) => Ok(Value::Integer(i)),
+                None => Ok(Value::Float(n.as_f64().unwrap_or(f64::NAN))),
+            },
+            Err(e) => Err(self.error(&error_message(&e))),
+        }
+    }
+
+    /// Returns the path of the value being read.
+    fn path(&self) -> Path {
+        let key = |frame: &Frame| match *frame {
+            Frame::Array(i) => Value::Integer(i),
+            Frame::Table(ref key) => Value::String(key.clone()),
+        };
+        self.stack.iter().map(key).collect()
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        while let Some(byte) = self.peek()?.filter(|&b| f(b)) {
+            bytes.push(byte);
+            self.bump()?;
+        }
+        Ok(bytes)
+    }
+
+    /// Skips whitespace, and returns the first byte of the next token.
+    fn peek_token(&mut self) -> Result<Option<u8>, String> {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek()? {
+            self.bump()?;
+        }
+        self.peek()
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, String> {
+        let buf = self.reader.fill_buf().map_err(|e| e.to_string())?;
+        Ok(buf.first().copied())
+    }
+
+    fn bump(&mut self) -> Result<Option<u8>, String> {
+        let byte = self.peek()?;
+        if let Some(byte) = byte {
+            self.reader.consume(1);
+            if byte == b'\n' {
+                self.line += 1;
+                self.column = 0;
+            } else {
+                self.column += 1;
+            }
+        }
+        Ok(byte)
+    }
+
+    fn error(&self, message: &str) -> String {
+        self.error_at(message, self.column)
+    }
+
+    /// Describes an error at the byte after `column` of the current line.
+    fn error_at(&self, message: &str, column: usize) -> String {
+        format!("{} at line {} column {}", message, self.line, column + 1)
+    }
+}
+
+impl<R: BufRead> Iterator for JsonEvents<R> {
+    type Item = Result<Value, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.queue.pop_front() {
+                return Some(Ok(event));
+            } else if self.done {
+                return None;
+            } else if let Err(err) = self.step() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// Returns the message of a JSON error, without the position serde gives within the token.
+fn error_message(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+/// The events of a TOML document, read one table at a time.
+pub struct TomlEvents<R> {
+    reader: R,
+    /// The name of the file being read, for error messages.
+    name: String,
+    events: Events,
+    /// The header which starts the next table, if it was read already.
+    header: Option<String>,
+    /// The number of lines read so far.
+    line: usize,
+    /// Whether the keys before the first header were read.
+    started: bool,
+    scanner: Scanner,
+    /// The kinds of the tables and keys read so far, to reject those defined twice. Their values
+    /// are not kept.
+    kinds: HashMap<Vec<String>, Kind>,
+    /// The number of elements read of each array of tables.
+    arrays: HashMap<Vec<String>, i64>,
+    /// A table with no keys, which is a leaf unless the table after it is nested in it.
+    empty: Option<Path>,
+    done: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    /// A table which only holds other tables so far, as `a` does after `[a.b]`.
+    Implicit,
+    Table,
+    Leaf,
+}
+
+impl<R: BufRead> TomlEvents<R> {
+    /// Reads a document from `reader`, named `name` in error messages.
+    pub fn new(reader: R, name: &str) -> Self {
+        TomlEvents {
+            reader,
+            name: name.to_string(),
+            events: Events::default(),
+            header: None,
+            line: 0,
+            started: false,
+            scanner: Scanner::default(),
+            kinds: HashMap::new(),
+            arrays: HashMap::new(),
+            empty: None,
+            done: false,
+        }
+    }
+
+    /// Reads the next table, up to the header of the one after it, and queues its events.
+    ///
+    /// Returns `false` once the whole document was read.
+    fn read_table(&mut self) -> Result<bool, String> {
+        let (mut text, first_line) = match self.header.take() {
+            Some(header) => (header, self.line),
+            None if self.started => return Ok(false),
+            None => (String::new(), 1),
+        };
+        let root = !self.started;
+        self.started = true;
+
+        loop {
+            let mut line = String::new();
+            let read = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            self.line += 1;
+            let header = self.scanner.at_top_level() && line.trim_start().starts_with('[');
+            self.scanner.scan(&line);
+            if header {
+                self.header = Some(line);
+                break;
+            }
+            text.push_str(&line);
+        }
+
+        let doc = ImDocument::parse(text.as_str())
+            .map_err(|e| document::diagnostic(&text, &self.name, first_line, &e))?;
+        let name = self.name.clone();
+        let error = |message: String, span: Option<Range<usize>>| {
+            let span = span.unwrap_or(0..0);
+            document::annotate(&message, &text, &name, first_line, span)
+        };
+
+        let (table, keys, array) = if root {
+            (doc.as_table(), Vec::new(), false)
+        } else {
+            header(doc.as_table())
+        };
+
+        let mut path = Vec::new();
+        for (i, (key, span)) in keys.iter().enumerate() {
+            path.push(Value::String(key.clone()));
+            let last = i + 1 == keys.len();
+            if let Some(&count) = self.arrays.get(&path_key(&path)) {
+                if !(last && array) {
+                    path.push(Value::Integer(count - 1));
+                }
+            }
+
+            if last && array {
+                if self.kinds.contains_key(&path_key(&path)) {
+                    let message = format!("invalid table header: duplicate key `{}`", key);
+                    return Err(error(message, span.clone()));
+                }
+                let count = self.arrays.entry(path_key(&path)).or_insert(0);
+                *count += 1;
+                path.push(Value::Integer(*count - 1));
+                self.kinds.insert(path_key(&path), Kind::Table);
+            } else if last {
+                match self.kinds.insert(path_key(&path), Kind::Table) {
+                    None | Some(Kind::Implicit) => {}
+                    Some(_) => {
+                        let message = format!("invalid table header: duplicate key `{}`", key);
+                        return Err(error(message, span.clone()));
+                    }
+                }
+            } else if *self.kinds.entry(path_key(&path)).or_insert(Kind::Implicit) == Kind::Leaf {
+                let message = format!("invalid table header: `{}` is not a table", key);
+                return Err(error(message, span.clone()));
+            }
+        }
+
+        for (key, item) in table.iter() {
+            let mut key_path = path.clone();
+            key_path.push(Value::String(key.to_string()));
+            if self.kinds.contains_key(&path_key(&key_path))
+                || self.arrays.contains_key(&path_key(&key_path))
+            {
+                let span = table.key(key).and_then(|k| k.span());
+                return Err(error(format!("duplicate key `{}`", key), span));
+            }
+            self.define(key_path, item);
+        }
+
+        if let Some(empty) = self.empty.take() {
+            if !path.starts_with(&empty) {
+                self.events.leaf(empty, Value::Table(Table::new()));
+            }
+        }
+        match document::from_table(table)? {
+            Value::Table(ref t) if t.is_empty() => {
+                if !root {
+                    self.empty = Some(path);
+                }
+            }
+            value => self.events.leaves(&mut path, value),
+        }
+        Ok(true)
+    }
+
+    /// Records the kind of the key at `path`, and of those of the tables it defines.
+    fn define(&mut self, path: Path, item: &Item) {
+        if let Item::Table(ref table) = *item {
+            for (key, item) in table.iter() {
+                let mut path = path.clone();
+                path.push(Value::String(key.to_string()));
+                self.define(path, item);
+            }
+            self.kinds.insert(path_key(&path), Kind::Table);
+        } else {
+            self.kinds.insert(path_key(&path), Kind::Leaf);
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TomlEvents<R> {
+    type Item = Result<Value, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.queue.pop_front() {
+                return Some(Ok(event));
+            } else if self.done {
+                return None;
+            }
+
+            match self.read_table() {
+                Ok(true) => {}
+                Ok(false) => {
+                    if let Some(empty) = self.empty.take() {
+                        self.events.leaf(empty, Value::Table(Table::new()));
+                    }
+                    // An empty document is a single leaf.
+                    if self.events.last.is_none() {
+                        self.events.leaf(Vec::new(), Value::Table(Table::new()));
+                    }
+                    self.events.end();
+                    self.done = true;
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// A key of a table header, along with where it was written.
+type HeaderKey = (String, Option<Range<usize>>);
+
+/// Walks down from the root of a document holding a single table to that table. Returns it along
+/// with the keys of its header, and whether it is an element of an array of tables.
+fn header(mut table: &toml_edit::Table) -> (&toml_edit::Table, Vec<HeaderKey>, bool) {
+    let mut keys = Vec::new();
+    while let Some((key, item)) = table.iter().next() {
+        keys.push((key.to_string(), table.key(key).and_then(|k| k.span())));
+        match *item {
+            Item::Table(ref t) if t.is_implicit() => table = t,
+            Item::Table(ref t) => return (t, keys, false),
+            Item::ArrayOfTables(ref tables) => return (tables.get(0).unwrap_or(table), keys, true),
+            _ => break,
+        }
+    }
+    (table, keys, false)
+}
+
+/// Returns the key of `path` in the kinds of tables and keys read so far.
+fn path_key(path: &[Value]) -> Vec<String> {
+    path.iter().map(Value::to_string).collect()
+}
+
+/// Tracks whether each line of a TOML document starts within a value, where a line starting with
+/// `[` is part of it rather than a table header.
+#[derive(Default)]
+struct Scanner {
+    /// The number of arrays and inline tables open.
+    depth: usize,
+    /// The delimiter of the multi-line string open, if any.
+    multiline: Option<&'static str>,
+}
+
+impl Scanner {
+    fn at_top_level(&self) -> bool {
+        self.depth == 0 && self.multiline.is_none()
+    }
+
+    fn scan(&mut self, line: &str) {
+        let mut rest = line;
+        loop {
+            if let Some(delimiter) = self.multiline {
+                match string_end(rest, delimiter) {
+                    Some(end) => {
+                        rest = &rest[end..];
+                        self.multiline = None;
+                    }
+                    None => return,
+                }
+            }
+
+            let i = match rest.find(['#', '"', '\'', '[', ']', '{', '}']) {
+                Some(i) => i,
+                None => return,
+            };
+            rest = &rest[i..];
+            let delimiter = match rest.as_bytes()[0] {
+                b'#' => return,
+                b'[' | b'{' => {
+                    self.depth += 1;
+                    rest = &rest[1..];
+                    continue;
+                }
+                b']' | b'}' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    rest = &rest[1..];
+                    continue;
+                }
+                b'"' if rest.starts_with("\"\"\"") => {
+                    self.multiline = Some("\"\"\"");
+                    rest = &rest[3..];
+                    continue;
+                }
+                b'\'' if rest.starts_with("'''") => {
+                    self.multiline = Some("'''");
+                    rest = &rest[3..];
+                    continue;
+                }
+                b'"' => "\"",
+                _ => "'",
+            };
+            match string_end(&rest[1..], delimiter) {
+                Some(end) => rest = &rest[1 + end..],
+                None => return,
+            }
+        }
+    }
+}
+
+/// Returns the index after the end of a string closed by `delimiter` in `text`, which starts
+/// within it.
+fn string_end(text: &str, delimiter: &str) -> Option<usize> {
+    let escapes = delimiter.starts_with('"');
+    let mut i = 0;
+    while i < text.len() {
+        if escapes && text.as_bytes()[i] == b'\\' {
+            i += 2;
+        } else if text[i..].starts_with(delimiter) {
+            // A multi-line string may end with up to two quotes of its own.
+            let quotes = text[i + delimiter.len()..]
+                .bytes()
+                .take(2)
+                .take_while(|&b| delimiter.len() == 3 && b == delimiter.as_bytes()[0])
+                .count();
+            return Some(i + delimiter.len() + quotes);
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::from_json;
+    use crate::value::stream::Events as ValueEvents;
+
+    fn json(text: &str) -> Result<Vec<String>, String> {
+        JsonEvents::new(text.as_bytes(), true)
+            .map(|e| e.map(|e| e.to_string()))
+            .collect()
+    }
+
+    fn toml(text: &str) -> Result<Vec<String>, String> {
+        TomlEvents::new(text.as_bytes(), "a.toml")
+            .map(|e| e.map(|e| e.to_string()))
+            .collect()
+    }
+
+    fn tostream(values: Vec<Value>) -> Result<Vec<String>, String> {
+        Ok(values
+            .into_iter()
+            .flat_map(ValueEvents::new)
+            .map(|e| e.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn json_events() {
+        let inputs = [
+            r#"{"a": 1, "b": [true, [], {"c": {}, "d": [[1.5]]}], "e": "x\"y"}"#,
+            r#"[] [[], {}, null] "x" -2 {"a": [{"b": 1}]}"#,
+            r#"{"$datetime": "1979-05-27T07:32:00Z"} [{"$datetime": "x", "a": 1}]"#,
+            r#"{"a": {"$datetime": 1}, "b": "\ud83d\ude00"}"#,
+            "",
+        ];
+        for input in &inputs {
+            assert_eq!(json(input), tostream(from_json(input, true).unwrap()));
+        }
+
+        for input in &[
+            "[1, 2",
+            "[1 2]",
+            r#"{"a" 1}"#,
+            "{1: 2}",
+            "[tru]",
+            "[1]]",
+            "\"\\x\"",
+        ] {
+            assert!(json(input).is_err(), "{}", input);
+        }
+        let err = json("[1,\n  {\"a\": nul}]").unwrap_err();
+        assert_eq!(err, "expected value at line 2 column 9");
+
+        // The events read before an error are still produced.
+        let events: Vec<_> = JsonEvents::new(&b"[1, x]"[..], false).collect();
+        assert_eq!(events[0], Ok(crate::eval::value("[[0], 1]")));
+        assert!(events[1].is_err());
+    }
+
+    #[test]
+    fn toml_events() {
+        let inputs = [
+            "a = 1\nb = { c = [1, { d = [] }] }\n\n[e]\nf = '''\n[g]\n'''\n[e.h]\n",
+            "[[a]]\nb = 1\n[[a]]\n[a.c]\nd = [\n  [1],\n  { e = '] #' },\n]\n[[a.f]]\n",
+            "[a.b]\n# [c]\nd.e = \"\"\"\nx\\\"\"\"\"\n[f] # comment\n",
+            "[a]\n[a.b]\n[c]\n",
+            "",
+        ];
+        for input in &inputs {
+            let value = input.parse::<document::Document>().unwrap().value().clone();
+            assert_eq!(toml(input), tostream(vec![value]), "{}", input);
+        }
+
+        // Tables written in several places are closed and opened again.
+        let expected = [
+            r#"[["a", "b"], 1]"#,
+            r#"[["a", "b"]]"#,
+            r#"[["c", "d"], 2]"#,
+            r#"[["c", "d"]]"#,
+            r#"[["a", "e", "f"], 3]"#,
+            r#"[["a", "e", "f"]]"#,
+            r#"[["a", "e"]]"#,
+            r#"[["a"]]"#,
+        ];
+        let input = "[a]\nb = 1\n[c]\nd = 2\n[a.e]\nf = 3\n";
+        assert_eq!(
+            toml(input),
+            Ok(expected.iter().map(|e| e.to_string()).collect())
+        );
+    }
+
+    #[test]
+    fn toml_errors() {
+        let inputs = [
+            "[a]\n[a]\n",
+            "[a]\nb = 1\n[a.b]\n",
+            "[a]\nb = 1\n[a.b.c]\n",
+            "a.b = 1\n[a.b]\n",
+            "[a.b]\nc = 1\n[a]\nb = 2\n",
+            "[[a]]\n[a]\n",
+            "a = []\n[[a]]\n",
+            "[a]\nb = 1\nb = 2\n",
+        ];
+        for input in &inputs {
+            assert!(input.parse::<document::Document>().is_err(), "{}", input);
+            assert!(toml(input).is_err(), "{}", input);
+        }
+        let valid = [
+            "[a.b.c]\n[a]\nd = 1\n",
+            "[a]\nb.c = 1\n[a.b.d]\n",
+            "[[a]]\n[a.b]\n[[a]]\n[a.b]\n",
+        ];
+        for input in &valid {
+            let value = input.parse::<document::Document>().unwrap().value().clone();
+            let mut expected = tostream(vec![value]).unwrap();
+            let mut events = toml(input).unwrap();
+            expected.sort();
+            events.sort();
+            assert_eq!(events, expected, "{}", input);
+        }
+
+        let err = toml("a = 1\n\n[b]\nc = 1\n[b]\n").unwrap_err();
+        assert!(err.starts_with("invalid table header: duplicate key `b`\n --> a.toml:5:2\n"));
+        let err = toml("[b]\nc = [\n1,,\n]\n").unwrap_err();
+        assert!(err.contains(" --> a.toml:3:"), "{}", err);
+    }
+}
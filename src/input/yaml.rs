@@ -0,0 +1,204 @@
+//! Parsing of YAML streams.
+//!
+//! Plain scalars are resolved with the YAML 1.2 core schema, extended with timestamps, which are
+//! read as datetimes. Aliases are replaced by the nodes they refer to, and `<<` merge keys are
+//! expanded. Mapping keys must be strings, since tables cannot have any other keys.
+
+use std::collections::HashMap;
+
+use yaml_rust2::parser::{Event, Parser, Tag};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+
+use crate::value::{Table, Value};
+
+/// Parses every document of a YAML stream.
+pub fn from_yaml(text: &str) -> Result<Vec<Value>, String> {
+    let mut loader = Loader {
+        parser: Parser::new_from_str(text),
+        anchors: HashMap::new(),
+    };
+
+    let mut documents = Vec::new();
+    loop {
+        match loader.next()? {
+            (Event::StreamEnd, _) => return Ok(documents),
+            (Event::DocumentStart, _) => {
+                let (event, mark) = loader.next()?;
+                documents.push(loader.node(event, mark)?);
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Loader<'a> {
+    parser: Parser<std::str::Chars<'a>>,
+    /// The nodes marked with each anchor seen so far.
+    anchors: HashMap<usize, Value>,
+}
+
+impl<'a> Loader<'a> {
+    fn next(&mut self) -> Result<(Event, Marker), String> {
+        self.parser.next_token().map_err(|e| e.to_string())
+    }
+
+    /// Reads the node starting with `event`, which was found at `mark`.
+    fn node(&mut self, event: Event, mark: Marker) -> Result<Value, String> {
+        let (value, anchor) = match event {
+            Event::Alias(id) => match self.anchors.get(&id) {
+                Some(value) => return Ok(value.clone()),
+                None => return Err(error(mark, "unknown alias")),
+            },
+            Event::Scalar(s, style, anchor, tag) => (scalar(s, style, tag.as_ref()), anchor),
+            Event::SequenceStart(anchor, _) => {
+                let mut array = Vec::new();
+                loop {
+                    match self.next()? {
+                        (Event::SequenceEnd, _) => break,
+                        (event, mark) => array.push(self.node(event, mark)?),
+                    }
+                }
+                (Value::Array(array), anchor)
+            }
+            Event::MappingStart(anchor, _) => {
+                let mut table = Table::new();
+                let mut merged = Table::new();
+                loop {
+                    let key = match self.next()? {
+                        (Event::MappingEnd, _) => break,
+                        (event, mark) => match self.node(event, mark)? {
+                            Value::String(key) => key,
+                            key => {
+                                let msg = format!("mapping keys must be strings, not {}", key);
+                                return Err(error(mark, &msg));
+                            }
+                        },
+                    };
+
+                    let (event, mark) = self.next()?;
+                    match (key.as_str(), self.node(event, mark)?) {
+                        ("<<", Value::Table(t)) => merged.extend(t),
+                        ("<<", Value::Array(tables)) => {
+                            for value in tables.into_iter().rev() {
+                                match value {
+                                    Value::Table(t) => merged.extend(t),
+                                    _ => return Err(error(mark, "only mappings can be merged")),
+                                }
+                            }
+                        }
+                        ("<<", _) => return Err(error(mark, "only mappings can be merged")),
+                        (_, value) => {
+                            table.insert(key, value);
+                        }
+                    }
+                }
+
+                // Keys of the mapping itself take precedence over merged keys.
+                for (key, value) in merged {
+                    table.entry(key).or_insert(value);
+                }
+                (Value::Table(table), anchor)
+            }
+            _ => return Err(error(mark, "unexpected event")),
+        };
+
+        if anchor != 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+        Ok(value)
+    }
+}
+
+fn error(mark: Marker, msg: &str) -> String {
+    format!("{} at line {} column {}", msg, mark.line(), mark.col() + 1)
+}
+
+/// Resolves the type of a scalar from its tag, or from its contents if it is plain.
+fn scalar(s: String, style: TScalarStyle, tag: Option<&Tag>) -> Value {
+    let tag = tag.map(|t| t.suffix.as_str());
+    match (style, tag) {
+        (_, Some("str")) => Value::String(s),
+        (TScalarStyle::Plain, _) | (_, Some("null" | "bool" | "int" | "float" | "timestamp")) => {
+            resolve(&s).unwrap_or(Value::String(s))
+        }
+        _ => Value::String(s),
+    }
+}
+
+/// Resolves a plain scalar which is not a string, e.g. `~`, `0x1f`, `.inf` or `2001-12-14`.
+pub(crate) fn resolve(s: &str) -> Option<Value> {
+    match s {
+        "" | "~" | "null" | "Null" | "NULL" => return Some(Value::Null),
+        "true" | "True" | "TRUE" => return Some(Value::Boolean(true)),
+        "false" | "False" | "FALSE" => return Some(Value::Boolean(false)),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => {
+            return Some(Value::Float(f64::INFINITY))
+        }
+        "-.inf" | "-.Inf" | "-.INF" => return Some(Value::Float(f64::NEG_INFINITY)),
+        ".nan" | ".NaN" | ".NAN" => return Some(Value::Float(f64::NAN)),
+        _ => {}
+    }
+
+    if let Some(hex) = s.strip_prefix("0x") {
+        return i64::from_str_radix(hex, 16).ok().map(Value::Integer);
+    } else if let Some(oct) = s.strip_prefix("0o") {
+        return i64::from_str_radix(oct, 8).ok().map(Value::Integer);
+    }
+
+    let digits = s.trim_start_matches(['-', '+']);
+    let numeric = digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        && digits.contains(|c: char| c.is_ascii_digit())
+        && digits.chars().all(|c| "0123456789.eE+-".contains(c));
+    if numeric {
+        if let Ok(i) = s.parse() {
+            return Some(Value::Integer(i));
+        } else if let Ok(f) = s.parse() {
+            return Some(Value::Float(f));
+        }
+    }
+
+    if s.contains(['-', ':']) {
+        return s.parse().ok().map(Value::Datetime);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scalars() {
+        let yaml =
+            "[~, true, 0x1f, -1.5e3, .inf, 2001-12-14, '2001-12-14', 12:30:00, !!str 1, 1.2.3, x]";
+        let expected = "[null, true, 31, -1500.0, inf, 2001-12-14, \"2001-12-14\", 12:30:00, \"1\", \"1.2.3\", \"x\"]";
+        assert_eq!(from_yaml(yaml).unwrap()[0].to_string(), expected);
+    }
+
+    #[test]
+    fn documents() {
+        let yaml = "---\nname: tq\nauthors:\n  - me\n---\n- 1\n- key: value\n";
+        let docs = from_yaml(yaml).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].to_string(), "{ name = \"tq\", authors = [\"me\"] }");
+        assert_eq!(docs[1].to_string(), "[1, { key = \"value\" }]");
+        assert_eq!(from_yaml("").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn anchors_and_merge_keys() {
+        let yaml = "base: &base\n  image: rust\n  script: [test]\njob:\n  <<: *base\n  script: [build]\nsame: *base\n";
+        let expected = "{ base = { image = \"rust\", script = [\"test\"] }, job = { script = [\"build\"], image = \"rust\" }, same = { image = \"rust\", script = [\"test\"] } }";
+        assert_eq!(from_yaml(yaml).unwrap()[0].to_string(), expected);
+    }
+
+    #[test]
+    fn errors() {
+        let err = from_yaml("a: 1\n1: b\n").unwrap_err();
+        assert_eq!(
+            err,
+            "mapping keys must be strings, not 1 at line 2 column 1"
+        );
+        assert!(from_yaml("a: [1").is_err());
+    }
+}
@@ -2,7 +2,12 @@
 #![recursion_limit = "128"]
 
 pub mod ast;
+pub mod document;
+pub mod eval;
+pub mod input;
+pub mod output;
 pub mod parser;
+pub mod value;
 
 #[cfg(test)]
 mod tests {
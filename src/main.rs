@@ -1,17 +1,249 @@
 #![forbid(unsafe_code)]
 
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::rc::Rc;
+use std::thread;
 
+use colored::Colorize;
+use similar::TextDiff;
 use structopt::StructOpt;
+use tempfile::NamedTempFile;
 use tq::ast::Filter;
+use tq::document::{Comments, Document, Locations};
+use tq::eval::{Error, Interpreter};
+use tq::input;
+use tq::input::stream::{JsonEvents, TomlEvents};
+use tq::output::env::Case;
+use tq::output::highlight::{highlight, Palette};
+use tq::output::tabular::{self, Flatten};
+use tq::output::toml::Styles;
+use tq::output::{self, Format, Options};
+use tq::value::stream::Events;
+use tq::value::Value;
+
+/// Stack size of the thread running the filter, since evaluation recurses deeply.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Exit status when a file cannot be read or written.
+const EXIT_IO: i32 = 2;
+/// Exit status when the filter cannot be parsed.
+const EXIT_COMPILE: i32 = 3;
+/// Exit status when an input cannot be parsed or the filter raises an error.
+const EXIT_ERROR: i32 = 5;
+
+/// An exit status, along with the message explaining it.
+type Failure = (i32, String);
+
+/// The events of an input read with `--stream`.
+type Stream = Box<dyn Iterator<Item = Result<Input, Failure>>>;
 
 #[derive(Debug, StructOpt)]
 struct Opt {
     #[structopt(default_value = ".", parse(from_str = "filter_or_default"))]
     pub filter: String,
+    /// Files to read, or standard input if none are given
     #[structopt(parse(from_os_str))]
     pub files: Vec<PathBuf>,
+    /// Replace each file with the single output of the filter
+    #[structopt(short = "i", long = "in-place")]
+    pub in_place: bool,
+    /// Keep a copy of each file edited in place, with this suffix appended to its name
+    #[structopt(long = "backup", value_name = "SUFFIX", requires = "in_place")]
+    pub backup: Option<String>,
+    /// Print a unified diff of the changes to each file instead of writing them
+    #[structopt(long = "diff", conflicts_with = "in_place")]
+    pub diff: bool,
+    /// Write each output to the file named by evaluating this filter against it
+    #[structopt(
+        long = "split-output",
+        value_name = "FILTER",
+        raw(conflicts_with_all = r#"&["in_place", "diff"]"#)
+    )]
+    pub split_output: Option<String>,
+    /// Overwrite files which already exist with --split-output
+    #[structopt(long = "force", requires = "split_output")]
+    pub force: bool,
+    /// Report each file which cannot be read or parsed and carry on with the others, rather than
+    /// stopping at the first one, or with --in-place, write every file which can be edited
+    #[structopt(long = "continue-on-error")]
+    pub continue_on_error: bool,
+    /// Colorize output, even if it is not written to a terminal
+    #[structopt(
+        short = "C",
+        long = "color-output",
+        overrides_with = "monochrome_output"
+    )]
+    pub color_output: bool,
+    /// Never colorize output
+    #[structopt(
+        short = "M",
+        long = "monochrome-output",
+        overrides_with = "color_output"
+    )]
+    pub monochrome_output: bool,
+    /// Print strings without quotes
+    #[structopt(short = "r", long = "raw-output")]
+    pub raw_output: bool,
+    /// Like --raw-output, without a newline after each output
+    #[structopt(short = "j", long = "join-output")]
+    pub join_output: bool,
+    /// Like --raw-output, with a NUL character after each output instead of a newline
+    #[structopt(long = "raw-output0", conflicts_with = "join_output")]
+    pub raw_output0: bool,
+    /// Write an ASCII record separator before each output, as described in RFC 7464
+    #[structopt(long = "seq")]
+    pub seq: bool,
+    /// Write each output on a single line, with tables written inline
+    #[structopt(
+        short = "c",
+        long = "compact-output",
+        raw(conflicts_with_all = r#"&["in_place", "diff"]"#)
+    )]
+    pub compact_output: bool,
+    /// Indent nested tables and the elements of wrapped arrays by this many spaces
+    #[structopt(long = "indent", value_name = "N")]
+    pub indent: Option<usize>,
+    /// Indent nested tables and the elements of wrapped arrays with tabs
+    #[structopt(long = "tab", conflicts_with = "indent")]
+    pub tab: bool,
+    /// Break arrays over several lines when they would be wider than this many columns
+    #[structopt(long = "array-wrap", value_name = "N")]
+    pub array_wrap: Option<usize>,
+    /// Escape non-ASCII characters in strings and keys
+    #[structopt(short = "a", long = "ascii-output")]
+    pub ascii_output: bool,
+    /// Sort the keys of every table in the output
+    #[structopt(short = "S", long = "sort-keys")]
+    pub sort_keys: bool,
+    /// Format of the input files: json, toml, yaml, flat, or auto to detect it from each file
+    #[structopt(long = "input-format", value_name = "FORMAT", default_value = "auto")]
+    pub input_format: input::Format,
+    /// Read each input as `path = value` lines, as written by --output-format flat
+    #[structopt(long = "unflat")]
+    pub unflat: bool,
+    /// Evaluate the filter against each `[path, leaf]` event of each input, as produced by
+    /// `tostream`. TOML inputs are read one table at a time and JSON inputs one token at a time,
+    /// so that events are evaluated before the rest of the input is read
+    #[structopt(long = "stream", raw(conflicts_with_all = r#"&["in_place", "diff"]"#))]
+    pub stream: bool,
+    /// Read the TOML front matter of each file, between `+++` lines or in a `---cargo` block, and
+    /// leave the rest of the file untouched when editing it in place
+    #[structopt(long = "front-matter", conflicts_with = "unflat")]
+    pub front_matter: bool,
+    /// Format of the outputs: json, toml, yaml, csv, tsv, markdown, flat, env or sh, defaulting
+    /// to the format of each input
+    #[structopt(long = "output-format", value_name = "FORMAT")]
+    pub output_format: Option<Format>,
+    /// Comma-separated columns of csv, tsv and markdown output, rather than every key of the rows
+    #[structopt(long = "columns", value_name = "NAMES", raw(use_delimiter = "true"))]
+    pub columns: Vec<String>,
+    /// Write nested values in csv, tsv and markdown output as json, toml, or dotted columns
+    #[structopt(long = "flatten", value_name = "STRATEGY", default_value = "json")]
+    pub flatten: Flatten,
+    /// Join the keys making up the names of env and sh variables with this separator
+    #[structopt(long = "env-separator", value_name = "SEP")]
+    pub env_separator: Option<String>,
+    /// Write the names of env and sh variables in upper, lower, or preserve the case of keys
+    #[structopt(long = "env-case", value_name = "CASE", default_value = "upper")]
+    pub env_case: Case,
+    /// Read and write datetimes in JSON as {"$datetime": "..."} objects rather than strings
+    #[structopt(long = "tagged-datetimes")]
+    pub tagged_datetimes: bool,
+}
+
+/// A value read from an input file, along with the document it was parsed from if it is TOML.
+struct Input {
+    value: Value,
+    doc: Option<Document>,
+    /// The format in which outputs for this input are written.
+    format: Format,
+    /// The text around the document, if it was read from the front matter of a file.
+    front_matter: Option<(String, String)>,
+}
+
+impl Input {
+    /// Returns an event of an input read with `--stream`, which is never written back as a
+    /// document.
+    fn event(value: Value, format: Format) -> Input {
+        Input {
+            value,
+            doc: None,
+            format,
+            front_matter: None,
+        }
+    }
+
+    /// Returns the comments of the document this value was parsed from, if any.
+    fn comments(&self) -> Comments {
+        self.doc
+            .as_ref()
+            .map(Document::comments)
+            .unwrap_or_default()
+    }
+
+    /// Returns where the values of the document this value was parsed from were written.
+    fn locations(&self) -> Locations {
+        self.doc
+            .as_ref()
+            .map(|doc| doc.locations().clone())
+            .unwrap_or_default()
+    }
+
+    /// Sorts the keys of the value, and of the document it was parsed from.
+    fn sort_keys(&mut self) {
+        self.value.sort_keys();
+        if let Some(ref mut doc) = self.doc {
+            doc.sort_keys();
+        }
+    }
+}
+
+/// The inputs left to evaluate, which are read by both the main loop and `input`.
+struct Queue {
+    inputs: VecDeque<(String, Result<Input, Failure>)>,
+    /// With `--stream`, the files left to read, or `None` for standard input. Each file is only
+    /// opened once the events of those before it were evaluated.
+    files: VecDeque<Option<PathBuf>>,
+    /// With `--stream`, the events left of the file being read, along with its name.
+    events: Option<(String, Stream)>,
+    opt: Rc<Opt>,
+}
+
+impl Queue {
+    /// Removes the next input, or with `--stream`, the next event of an input.
+    fn pop(&mut self) -> Option<(String, Result<Input, Failure>)> {
+        loop {
+            if let Some((ref name, ref mut events)) = self.events {
+                if let Some(event) = events.next() {
+                    let name = name.clone();
+                    // The rest of a file cannot be read after an error.
+                    if event.is_err() {
+                        self.events = None;
+                    }
+                    return Some((name, event));
+                }
+                self.events = None;
+            }
+
+            if !self.opt.stream {
+                return self.inputs.pop_front();
+            }
+            let path = self.files.pop_front()?;
+            let name = path
+                .as_ref()
+                .map_or_else(|| "<stdin>".to_string(), |p| p.display().to_string());
+            match stream_input(&name, path.as_deref(), &self.opt) {
+                Ok(events) => self.events = Some((name, events)),
+                Err(err) => return Some((name, Err(err))),
+            }
+        }
+    }
 }
 
 fn filter_or_default(s: &str) -> String {
@@ -24,11 +256,691 @@ fn filter_or_default(s: &str) -> String {
 
 fn main() {
     let opt = Opt::from_args();
-    let filter: Filter = opt.filter.parse().unwrap_or_else(|err| {
-        eprintln!("{}", err);
-        process::exit(1);
+    let status = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || run(opt))
+        .and_then(|handle| handle.join().map_err(|_| io::ErrorKind::Other.into()))
+        .unwrap_or(EXIT_ERROR);
+    process::exit(status);
+}
+
+fn run(opt: Opt) -> i32 {
+    let opt = Rc::new(opt);
+    let filter: Filter = match opt.filter.parse() {
+        Ok(filter) => filter,
+        Err(err) => {
+            eprintln!("tq: error: {}", err);
+            return EXIT_COMPILE;
+        }
+    };
+
+    let split: Option<(Filter, Interpreter)> = match opt.split_output.as_ref().map(|s| s.parse()) {
+        Some(Ok(split)) => Some((split, Interpreter::new())),
+        Some(Err(err)) => {
+            eprintln!("tq: error: --split-output: {}", err);
+            return EXIT_COMPILE;
+        }
+        None => None,
+    };
+
+    let color = use_color(&opt);
+    colored::control::set_override(color);
+    let palette = match env::var("TQ_COLORS") {
+        Ok(colors) => colors.parse().unwrap_or_else(|err| {
+            eprintln!("tq: warning: failed to set $TQ_COLORS: {}", err);
+            Palette::default()
+        }),
+        Err(_) => Palette::default(),
+    };
+
+    let mut interpreter = Interpreter::new();
+    let options = output_options(&opt);
+    if opt.in_place || opt.diff {
+        return edit_files(&opt, &filter, &interpreter, &options);
+    }
+
+    let raw = opt.raw_output || opt.join_output || opt.raw_output0;
+    let separator = match (opt.raw_output0, opt.join_output) {
+        (true, _) => "\0",
+        (false, true) => "",
+        (false, false) => "\n",
+    };
+
+    // Every value of every file is queued, so that `input` can read ahead of the current one,
+    // unless the inputs are streamed.
+    let mut queue = Queue {
+        inputs: VecDeque::new(),
+        files: VecDeque::new(),
+        events: None,
+        opt: Rc::clone(&opt),
+    };
+    if opt.stream && opt.files.is_empty() {
+        queue.files.push_back(None);
+    } else if opt.stream {
+        queue.files.extend(opt.files.iter().cloned().map(Some));
+    } else {
+        for (name, text) in read_inputs(&opt.files) {
+            match text.and_then(|text| parse_input(&name, &text, &opt)) {
+                Ok(inputs) => {
+                    for input in inputs {
+                        queue.inputs.push_back((name.clone(), Ok(input)));
+                    }
+                }
+                Err(err) => queue.inputs.push_back((name, Err(err))),
+            }
+        }
+    }
+
+    let queue = Rc::new(RefCell::new(queue));
+    let inputs = Rc::clone(&queue);
+    // Broken inputs skipped by `input` and `inputs` still fail the run.
+    let skipped = Rc::new(Cell::new(0));
+    let skipped_inputs = Rc::clone(&skipped);
+    let continue_on_error = opt.continue_on_error;
+    interpreter.set_inputs(move || loop {
+        let (name, input) = inputs.borrow_mut().pop()?;
+        match input {
+            Ok(input) => return Some(Ok(input.value)),
+            Err((code, err)) if continue_on_error => {
+                eprintln!("tq: error ({}): {}", name, err);
+                skipped_inputs.set(code);
+            }
+            Err((_, err)) => return Some(Err(format!("{} ({})", err, name))),
+        }
     });
 
-    println!("AST: {:?}", filter);
-    println!("Serialized: {}", filter);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut status = 0;
+    let mut yaml_documents = 0;
+    // Set once stdout is closed, as by `| head`, after which nothing else is evaluated or written.
+    let mut closed = false;
+    // Tabular outputs are written as a single table once every output is known.
+    let mut rows = Vec::new();
+
+    loop {
+        let next = queue.borrow_mut().pop();
+        let (name, mut input) = match next {
+            Some((name, Ok(input))) => (name, input),
+            Some((name, Err((code, err)))) => {
+                eprintln!("tq: error ({}): {}", name, err);
+                status = code;
+                if opt.continue_on_error {
+                    continue;
+                }
+                break;
+            }
+            None => break,
+        };
+
+        let value = input.value.clone();
+        if opt.sort_keys {
+            input.sort_keys();
+        }
+        interpreter.set_comments(input.comments());
+        interpreter.set_styles(Styles::default());
+        interpreter.set_locations(&name, input.locations());
+
+        let result = interpreter.run(&filter, value, |mut value| {
+            if opt.sort_keys {
+                value.sort_keys();
+            }
+            if let Some((ref split, ref namer)) = split {
+                let text = render(
+                    &input,
+                    &value,
+                    interpreter.is_update(),
+                    &interpreter.comments(),
+                    &interpreter.styles(),
+                    &options,
+                )?;
+                return split_output(split, namer, &value, &text, opt.force).map_err(Error::from);
+            } else if input.format.is_tabular() {
+                rows.push(value);
+                return Ok(());
+            }
+
+            let mut text = match value {
+                Value::String(s) if raw => {
+                    if opt.raw_output0 && s.contains('\0') {
+                        let msg = "Cannot write a string containing NUL with --raw-output0";
+                        return Err(Error::from(msg.to_string()));
+                    }
+                    if opt.ascii_output {
+                        output::toml::escape_non_ascii(&s)
+                    } else {
+                        s
+                    }
+                }
+                value => {
+                    let mut text = render(
+                        &input,
+                        &value,
+                        interpreter.is_update(),
+                        &interpreter.comments(),
+                        &interpreter.styles(),
+                        &options,
+                    )?;
+                    if text.ends_with('\n') {
+                        text.pop();
+                    }
+                    let document = input.format == Format::Toml
+                        && !options.compact
+                        && matches!(value, Value::Table(_));
+                    match input.format {
+                        // Each output is a separate document of a single YAML stream.
+                        Format::Yaml => {
+                            yaml_documents += 1;
+                            match yaml_documents {
+                                1 => text,
+                                _ => format!("---\n{}", text),
+                            }
+                        }
+                        // The highlighter only knows TOML and JSON syntax.
+                        Format::Toml | Format::Json if color => {
+                            highlight(&text, document, &palette)
+                        }
+                        _ => text,
+                    }
+                }
+            };
+
+            if opt.seq {
+                text.insert(0, '\x1e');
+            }
+            text.push_str(separator);
+            stdout.write_all(text.as_bytes()).map_err(|e| {
+                closed = is_closed(&e);
+                Error::from(e.to_string())
+            })
+        });
+
+        if closed {
+            break;
+        } else if let Err(err) = result {
+            eprintln!("tq: error ({}): {}", name, err);
+            status = EXIT_ERROR;
+        }
+    }
+
+    if skipped.get() != 0 {
+        status = skipped.get();
+    }
+
+    if let Some(format) = opt
+        .output_format
+        .filter(|f| f.is_tabular() && !rows.is_empty() && !closed)
+    {
+        let text = tabular::to_string(&rows, format, &options).and_then(|mut text| {
+            text.push('\n');
+            match stdout.write_all(text.as_bytes()) {
+                Err(ref e) if !is_closed(e) => Err(e.to_string()),
+                _ => Ok(()),
+            }
+        });
+        if let Err(err) = text {
+            eprintln!("tq: error: {}", err);
+            status = EXIT_ERROR;
+        }
+    }
+
+    status
+}
+
+/// Writes `text`, an output of the filter serialized from `value`, to the file named by `split`.
+///
+/// `split` is evaluated with an interpreter of its own, so that comments and styles it sets do not
+/// apply to the outputs. Any missing directories are created, but existing files are only replaced
+/// if `force` is set.
+fn split_output(
+    split: &Filter,
+    interpreter: &Interpreter,
+    value: &Value,
+    text: &str,
+    force: bool,
+) -> Result<(), String> {
+    let mut names = interpreter
+        .eval(split, value.clone())
+        .map_err(|e| e.to_string())?;
+    let path = match (names.len(), names.pop()) {
+        (1, Some(Value::String(name))) => PathBuf::from(name),
+        (1, Some(name)) => {
+            let msg = format!("file names must be strings, not {}", name.type_name());
+            return Err(msg);
+        }
+        (n, _) => return Err(format!("expected exactly 1 file name, found {}", n)),
+    };
+
+    let write = || -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(force)
+            .create_new(!force)
+            .open(&path)?;
+        file.write_all(text.as_bytes())
+    };
+
+    write().map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => {
+            format!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            )
+        }
+        _ => format!("{} ({})", err, path.display()),
+    })
+}
+
+/// Collects the options controlling the layout of outputs.
+fn output_options(opt: &Opt) -> Options {
+    let indent = match (opt.tab, opt.indent) {
+        (true, _) => Some("\t".to_string()),
+        (false, Some(n)) => Some(" ".repeat(n)),
+        (false, None) => None,
+    };
+
+    Options {
+        compact: opt.compact_output,
+        indent,
+        array_wrap: opt.array_wrap,
+        ascii: opt.ascii_output,
+        tagged_datetimes: opt.tagged_datetimes,
+        columns: if opt.columns.is_empty() {
+            None
+        } else {
+            Some(opt.columns.clone())
+        },
+        flatten: opt.flatten,
+        env_separator: opt.env_separator.clone(),
+        env_case: opt.env_case,
+    }
+}
+
+/// Decides whether to colorize output written to the terminal.
+fn use_color(opt: &Opt) -> bool {
+    if opt.color_output || opt.monochrome_output {
+        return opt.color_output;
+    }
+
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && atty::is(atty::Stream::Stdout)
+}
+
+/// Replaces each input file with the single output of the filter, or prints the differences.
+///
+/// Every file is evaluated before any of them is written, so that nothing is modified unless the
+/// filter succeeds for all of them, or with `--continue-on-error`, only the files for which it
+/// fails are left alone. With `--diff`, the exit status is 1 if any file would change.
+fn edit_files(opt: &Opt, filter: &Filter, interpreter: &Interpreter, options: &Options) -> i32 {
+    if opt.files.is_empty() {
+        let flag = if opt.diff { "--diff" } else { "--in-place" };
+        eprintln!("tq: error: {} requires at least one file", flag);
+        return EXIT_IO;
+    }
+
+    let mut edits = Vec::new();
+    let mut status = 0;
+    for (path, (name, text)) in opt.files.iter().zip(read_inputs(&opt.files)) {
+        let edited = text.and_then(|text| {
+            let mut inputs = parse_input(&name, &text, opt)?;
+            if inputs.len() != 1 {
+                let msg = format!("expected exactly 1 input value, found {}", inputs.len());
+                return Err((EXIT_ERROR, msg));
+            }
+
+            let mut input = inputs.remove(0);
+            interpreter.set_comments(input.comments());
+            interpreter.set_styles(Styles::default());
+            interpreter.set_locations(&name, input.locations());
+            let mut outputs = Vec::new();
+            interpreter
+                .run(filter, input.value.clone(), |value| {
+                    let update = interpreter.is_update();
+                    outputs.push((value, update, interpreter.comments(), interpreter.styles()));
+                    Ok(())
+                })
+                .map_err(|e| (EXIT_ERROR, e.to_string()))?;
+            if outputs.len() != 1 {
+                let msg = format!("expected exactly 1 output, found {}", outputs.len());
+                return Err((EXIT_ERROR, msg));
+            }
+
+            let (mut output, update, comments, styles) = outputs.remove(0);
+            if opt.sort_keys {
+                input.sort_keys();
+                output.sort_keys();
+            }
+            match output {
+                Value::Table(_) => {}
+                _ if input.format != Format::Toml => {}
+                _ => {
+                    let msg = format!("cannot write {} as a document", output.type_name());
+                    return Err((EXIT_ERROR, msg));
+                }
+            }
+            // Rewriting the whole document would silently drop its comments and formatting.
+            if input.format == Format::Toml && input.doc.is_some() && !update {
+                let msg = "the output is not an update of the document, so its comments and \
+                           formatting would be lost";
+                return Err((EXIT_ERROR, msg.to_string()));
+            }
+
+            let mut edited = render(&input, &output, update, &comments, &styles, options)
+                .map_err(|e| (EXIT_ERROR, e))?;
+            if let Some((before, after)) = input.front_matter {
+                if input.format != Format::Toml {
+                    let msg = "front matter can only be written as TOML".to_string();
+                    return Err((EXIT_ERROR, msg));
+                }
+                edited = before + &edited + &after;
+            }
+            Ok((text, edited))
+        });
+
+        match edited {
+            Ok((text, edited)) => {
+                if text != edited {
+                    edits.push((path, text, edited));
+                }
+            }
+            Err((code, err)) => {
+                eprintln!("tq: error ({}): {}", name, err);
+                status = code;
+            }
+        }
+    }
+
+    if opt.diff {
+        let color = use_color(opt);
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        for (path, text, edited) in &edits {
+            let diff = unified_diff(&path.display().to_string(), text, edited, color);
+            match stdout.write_all(diff.as_bytes()) {
+                Err(ref err) if is_closed(err) => break,
+                Err(err) => {
+                    eprintln!("tq: error: {}", err);
+                    return EXIT_IO;
+                }
+                Ok(()) => {}
+            }
+        }
+        return match (status, edits.is_empty()) {
+            (0, false) => 1,
+            (status, _) => status,
+        };
+    }
+
+    if status != 0 && !opt.continue_on_error {
+        eprintln!("tq: no files were modified");
+        return status;
+    }
+
+    for (path, _, text) in edits {
+        if let Err(err) = write_atomic(path, &text, opt.backup.as_ref()) {
+            eprintln!("tq: error ({}): {}", path.display(), err);
+            status = EXIT_IO;
+        }
+    }
+
+    status
+}
+
+/// Returns a unified diff between the contents of the file `name` and its edited contents.
+fn unified_diff(name: &str, text: &str, edited: &str, color: bool) -> String {
+    let diff = TextDiff::from_lines(text, edited);
+    let (old, new) = (format!("a/{}", name), format!("b/{}", name));
+    let diff = diff.unified_diff().header(&old, &new).to_string();
+    if !color {
+        return diff;
+    }
+
+    let mut colored = String::with_capacity(diff.len());
+    for line in diff.split_inclusive('\n') {
+        let (content, newline) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+        let content = if content.starts_with("---") || content.starts_with("+++") {
+            content.bold()
+        } else if content.starts_with("@@") {
+            content.cyan()
+        } else if content.starts_with('-') {
+            content.red()
+        } else if content.starts_with('+') {
+            content.green()
+        } else {
+            content.normal()
+        };
+        colored.push_str(&format!("{}{}", content, newline));
+    }
+    colored
+}
+
+/// Serializes one output of the filter, which was evaluated against `input`, and is that input
+/// updated if `is_update` is set.
+///
+/// Updated TOML documents keep their original layout, with the changes to `comments` applied, and
+/// only `--ascii-output` applies to them.
+fn render(
+    input: &Input,
+    value: &Value,
+    is_update: bool,
+    comments: &Comments,
+    styles: &Styles,
+    options: &Options,
+) -> Result<String, String> {
+    match (input.format, value, &input.doc) {
+        (Format::Json, value, _) => Ok(output::json::to_string_with(value, options) + "\n"),
+        (Format::Yaml, value, _) => Ok(output::yaml::to_string_with(value, options) + "\n"),
+        (Format::Flat, value, _) => output::flat::to_string_with(value, options).map(|text| {
+            if text.is_empty() {
+                text
+            } else {
+                text + "\n"
+            }
+        }),
+        (format @ (Format::Env | Format::Sh), value, _) => {
+            output::env::to_string_with(value, format, options).map(|text| {
+                if text.is_empty() {
+                    text
+                } else {
+                    text + "\n"
+                }
+            })
+        }
+        (format @ (Format::Csv | Format::Tsv | Format::Markdown), value, _) => {
+            tabular::to_string(std::slice::from_ref(value), format, options).map(|text| text + "\n")
+        }
+        (Format::Toml, Value::Table(_), Some(doc)) if is_update && !options.compact => {
+            let text = doc.update_with(value, comments, styles)?;
+            if options.ascii {
+                Ok(output::toml::to_ascii(&text))
+            } else {
+                Ok(text)
+            }
+        }
+        (Format::Toml, value, _) => {
+            // Styles are set on the paths of the input, which only match those of updated inputs.
+            let default = Styles::default();
+            let styles = if is_update { styles } else { &default };
+            output::toml::to_string_styled(value, options, styles).map(|mut text| {
+                if let Value::Table(ref table) = *value {
+                    if !table.is_empty() && !options.compact {
+                        text.push('\n');
+                    }
+                }
+                text
+            })
+        }
+    }
+}
+
+/// Replaces the contents of the file at `path` with `text`, keeping its permissions and owner.
+///
+/// The new contents are written to a temporary file in the same directory first, which is then
+/// renamed over the original, so the file is never left partially written. If `path` is a
+/// symlink, the file it points to is replaced rather than the link.
+fn write_atomic(path: &Path, text: &str, backup: Option<&String>) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("/"));
+
+    let metadata = fs::metadata(&path)?;
+    let mut file = NamedTempFile::new_in(dir)?;
+    file.write_all(text.as_bytes())?;
+    file.as_file().sync_all()?;
+    fs::set_permissions(file.path(), metadata.permissions())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{fchown, MetadataExt};
+        // Only the superuser may give a file away, so the new file is left to whoever wrote it
+        // when this fails, as with `sed -i`.
+        let _ = fchown(file.as_file(), Some(metadata.uid()), Some(metadata.gid()));
+    }
+
+    if let Some(suffix) = backup {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(suffix);
+        fs::copy(&path, backup)?;
+    }
+
+    file.persist(&path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Reads each input file, or standard input if there are none, along with its display name.
+fn read_inputs(files: &[PathBuf]) -> Vec<(String, Result<String, Failure>)> {
+    if files.is_empty() {
+        let mut text = String::new();
+        let result = io::stdin().read_to_string(&mut text).map(|_| text);
+        return vec![("<stdin>".to_string(), result.map_err(io_error))];
+    }
+
+    files
+        .iter()
+        .map(|path| {
+            let text = fs::read_to_string(path).map_err(io_error);
+            (path.display().to_string(), text)
+        })
+        .collect()
+}
+
+/// Opens the input file `name` at `path`, or standard input, to read its events with `--stream`.
+///
+/// TOML and JSON inputs are read as their events are needed, while others are parsed as a whole
+/// first.
+fn stream_input(name: &str, path: Option<&Path>, opt: &Opt) -> Result<Stream, Failure> {
+    let mut reader: Box<dyn BufRead> = match path {
+        Some(path) => Box::new(BufReader::new(fs::File::open(path).map_err(io_error)?)),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+    let start = String::from_utf8_lossy(reader.fill_buf().map_err(io_error)?).into_owned();
+    let format = if opt.front_matter || opt.unflat {
+        None
+    } else {
+        Some(opt.input_format.detect_start(Some(Path::new(name)), &start))
+    };
+
+    let (events, format): (Box<dyn Iterator<Item = Result<Value, String>>>, _) = match format {
+        Some(input::Format::Json) => (
+            Box::new(JsonEvents::new(reader, opt.tagged_datetimes)),
+            opt.output_format.unwrap_or(Format::Json),
+        ),
+        Some(input::Format::Toml) => (
+            Box::new(TomlEvents::new(reader, name)),
+            opt.output_format.unwrap_or(Format::Toml),
+        ),
+        _ => {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).map_err(io_error)?;
+            let events = parse_input(name, &text, opt)?
+                .into_iter()
+                .flat_map(|input| {
+                    let format = input.format;
+                    Events::new(input.value).map(move |value| Ok(Input::event(value, format)))
+                });
+            return Ok(Box::new(events));
+        }
+    };
+    Ok(Box::new(events.map(move |event| {
+        event
+            .map(|value| Input::event(value, format))
+            .map_err(|err| (EXIT_ERROR, err))
+    })))
+}
+
+/// Parses the contents of the input file `name`, which may hold several values unless it is TOML.
+fn parse_input(name: &str, text: &str, opt: &Opt) -> Result<Vec<Input>, Failure> {
+    if opt.front_matter {
+        let (before, toml, after) = match input::front_matter(text) {
+            Some(parts) => parts,
+            None => return Err((EXIT_ERROR, "no TOML front matter found".to_string())),
+        };
+        let first_line = before.matches('\n').count() + 1;
+        let doc = Document::parse_file(toml, name, first_line).map_err(|e| (EXIT_ERROR, e))?;
+        return Ok(vec![Input {
+            value: doc.value().clone(),
+            doc: Some(doc),
+            format: opt.output_format.unwrap_or(Format::Toml),
+            front_matter: Some((before.to_string(), after.to_string())),
+        }]);
+    }
+
+    let format = if opt.unflat {
+        input::Format::Flat
+    } else {
+        opt.input_format.detect(Some(Path::new(name)), text)
+    };
+    let inputs = match format {
+        input::Format::Json => input::from_json(text, opt.tagged_datetimes).map(|values| {
+            let format = opt.output_format.unwrap_or(Format::Json);
+            let input = |value| Input {
+                value,
+                doc: None,
+                format,
+                front_matter: None,
+            };
+            values.into_iter().map(input).collect()
+        }),
+        input::Format::Flat => input::from_flat(text).map(|value| {
+            vec![Input {
+                value,
+                doc: None,
+                format: opt.output_format.unwrap_or(Format::Toml),
+                front_matter: None,
+            }]
+        }),
+        input::Format::Yaml => input::from_yaml(text).map(|values| {
+            let format = opt.output_format.unwrap_or(Format::Yaml);
+            let input = |value| Input {
+                value,
+                doc: None,
+                format,
+                front_matter: None,
+            };
+            values.into_iter().map(input).collect()
+        }),
+        _ => Document::parse_file(text, name, 1).map(|doc| {
+            vec![Input {
+                value: doc.value().clone(),
+                doc: Some(doc),
+                format: opt.output_format.unwrap_or(Format::Toml),
+                front_matter: None,
+            }]
+        }),
+    };
+    inputs.map_err(|e| (EXIT_ERROR, e))
+}
+
+/// Returns whether a write failed because stdout was closed, as by `| head`.
+fn is_closed(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::BrokenPipe
+}
+
+fn io_error(err: io::Error) -> Failure {
+    (EXIT_IO, err.to_string())
 }
@@ -0,0 +1,80 @@
+//! Serialization of filter outputs.
+
+use std::str::FromStr;
+
+use self::env::Case;
+use self::tabular::Flatten;
+
+pub mod env;
+pub mod flat;
+pub mod highlight;
+pub mod json;
+pub mod tabular;
+pub mod toml;
+pub mod yaml;
+
+/// A format in which outputs are written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+    Csv,
+    Tsv,
+    Markdown,
+    Flat,
+    Env,
+    Sh,
+}
+
+impl Format {
+    /// Returns whether this format writes tables as the rows of a single table.
+    pub fn is_tabular(self) -> bool {
+        matches!(self, Format::Csv | Format::Tsv | Format::Markdown)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "yaml" => Ok(Format::Yaml),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "markdown" => Ok(Format::Markdown),
+            "flat" => Ok(Format::Flat),
+            "env" => Ok(Format::Env),
+            "sh" => Ok(Format::Sh),
+            s => Err(format!("Unknown output format `{}`", s)),
+        }
+    }
+}
+
+/// Options controlling the layout of serialized values.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Writes every value on a single line, with tables written inline.
+    pub compact: bool,
+    /// Indentation of nested structures.
+    ///
+    /// TOML tables are not indented if this is unset, and JSON and YAML are indented by two
+    /// spaces. YAML is never indented with tabs.
+    pub indent: Option<String>,
+    /// Width beyond which a TOML `key = [...]` line is broken into one element per line.
+    pub array_wrap: Option<usize>,
+    /// Escapes non-ASCII characters in strings and keys, e.g. `"\u00E9"`.
+    pub ascii: bool,
+    /// Writes datetimes in JSON as `{"$datetime": "..."}` rather than as plain strings.
+    pub tagged_datetimes: bool,
+    /// The columns of tabular formats, which are the keys of every row if unset.
+    pub columns: Option<Vec<String>>,
+    /// How tabular formats write arrays and tables nested in rows.
+    pub flatten: Flatten,
+    /// The separator of the keys making up variable names, which is `__` if unset.
+    pub env_separator: Option<String>,
+    /// How the keys making up variable names are transformed.
+    pub env_case: Case,
+}
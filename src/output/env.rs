@@ -0,0 +1,199 @@
+//! Serialization of tables as environment variables, e.g. `DATABASE__HOST=localhost`.
+//!
+//! Nested tables are flattened by joining their keys with a separator, and array elements are
+//! named by their index. Every other character which cannot appear in a variable name is replaced
+//! with `_`. Empty arrays and tables have no leaves, and so produce no variables.
+
+use std::str::FromStr;
+
+use super::{Format, Options};
+use crate::value::{Table, Value};
+
+/// Separator of the keys making up a variable name, when no separator is set.
+const DEFAULT_SEPARATOR: &str = "__";
+
+/// How the keys making up a variable name are transformed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Case {
+    #[default]
+    Upper,
+    Lower,
+    /// Keys are used as they are.
+    Preserve,
+}
+
+impl FromStr for Case {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upper" => Ok(Case::Upper),
+            "lower" => Ok(Case::Lower),
+            "preserve" => Ok(Case::Preserve),
+            s => Err(format!("Unknown case `{}`", s)),
+        }
+    }
+}
+
+/// Writes the leaves of a table as variable assignments, one per line.
+///
+/// With `Format::Env` they are written as in a `.env` file, with values quoted only if needed,
+/// and with `Format::Sh` as `export` statements to be evaluated by a POSIX shell.
+pub fn to_string_with(value: &Value, format: Format, options: &Options) -> Result<String, String> {
+    let table = match *value {
+        Value::Table(ref table) => table,
+        ref value => return Err(format!("Cannot write {} as variables", value.type_name())),
+    };
+
+    let mut variables = Table::new();
+    let mut names = Vec::new();
+    for (key, value) in table {
+        names.push(name(key, options.env_case));
+        leaves(&mut names, value, options, &mut variables)?;
+        names.pop();
+    }
+
+    let lines: Vec<_> = variables
+        .iter()
+        .map(|(name, value)| {
+            let value = match *value {
+                Value::Null => String::new(),
+                Value::String(ref s) => s.clone(),
+                ref value => value.to_string(),
+            };
+            match format {
+                Format::Sh => format!("export {}={}", name, shell_quote(&value)),
+                _ => format!("{}={}", name, dotenv_quote(&value)),
+            }
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+/// Collects a variable for each leaf of `value`, whose name so far is made up of `names`.
+fn leaves(
+    names: &mut Vec<String>,
+    value: &Value,
+    options: &Options,
+    variables: &mut Table,
+) -> Result<(), String> {
+    let children: Vec<_> = match *value {
+        Value::Array(ref array) => array
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v))
+            .collect(),
+        Value::Table(ref table) => table
+            .iter()
+            .map(|(k, v)| (name(k, options.env_case), v))
+            .collect(),
+        ref value => {
+            let separator = options.env_separator.as_deref();
+            let mut name = names.join(separator.unwrap_or(DEFAULT_SEPARATOR));
+            if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+                name.insert(0, '_');
+            }
+            if variables.contains_key(&name) {
+                return Err(format!("More than one value is named {}", name));
+            }
+            variables.insert(name, value.clone());
+            return Ok(());
+        }
+    };
+
+    for (name, child) in children {
+        names.push(name);
+        leaves(names, child, options, variables)?;
+        names.pop();
+    }
+    Ok(())
+}
+
+/// Transforms a key into part of a variable name.
+fn name(key: &str, case: Case) -> String {
+    let name = key.replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "_");
+    match case {
+        Case::Upper => name.to_ascii_uppercase(),
+        Case::Lower => name.to_ascii_lowercase(),
+        Case::Preserve => name,
+    }
+}
+
+/// Quotes a string as a single word for a POSIX shell, as `@sh` does in `jq`.
+///
+/// The string is enclosed in single quotes, within which nothing is special, and each `'` is
+/// written as `'\''`, which ends the quoted string, escapes a quote and starts another one.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Quotes the value of a `.env` variable in double quotes, if it contains any special characters.
+fn dotenv_quote(s: &str) -> String {
+    let is_plain = s
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-.,:/@%+=".contains(c));
+    if is_plain {
+        return s.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '$' => quoted.push_str("\\$"),
+            '`' => quoted.push_str("\\`"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::value;
+
+    #[test]
+    fn env() {
+        let input = value(
+            r#"{ database = { host = 'localhost', port = 5432, "read-only" = true }, hosts = ['a b', "it's"], empty = [], "9" = { x = null } }"#,
+        );
+        let env = to_string_with(&input, Format::Env, &Options::default()).unwrap();
+        let expected = "DATABASE__HOST=localhost
+DATABASE__PORT=5432
+DATABASE__READ_ONLY=true
+HOSTS__0=\"a b\"
+HOSTS__1=\"it's\"
+_9__X=";
+        assert_eq!(env, expected);
+
+        let options = Options {
+            env_separator: Some("_".to_string()),
+            env_case: Case::Preserve,
+            ..Options::default()
+        };
+        let env = to_string_with(&input, Format::Env, &options).unwrap();
+        assert!(env.starts_with("database_host=localhost\n"));
+        assert!(env.contains("\nhosts_0=\"a b\"\n"));
+    }
+
+    #[test]
+    fn sh() {
+        let input = value(r#"{ a = { b = "it's $HOME\n" }, c = 1979-05-27 }"#);
+        let sh = to_string_with(&input, Format::Sh, &Options::default()).unwrap();
+        assert_eq!(sh, "export A__B='it'\\''s $HOME\n'\nexport C='1979-05-27'");
+    }
+
+    #[test]
+    fn errors() {
+        let input = value("{ a-b = 1, a_b = 2 }");
+        let err = to_string_with(&input, Format::Env, &Options::default()).unwrap_err();
+        assert_eq!(err, "More than one value is named A_B");
+        assert!(to_string_with(&value("[1]"), Format::Env, &Options::default()).is_err());
+    }
+}
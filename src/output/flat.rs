@@ -0,0 +1,86 @@
+//! Serialization of values as one line per leaf, e.g. `bin[0].name = "tq"`.
+//!
+//! Each line holds the full path of a leaf, with keys joined by dots and quoted as TOML keys are,
+//! and array indices in brackets. Leaves are written as inline TOML values, and empty arrays and
+//! tables are written as leaves so that they survive being read back with
+//! [`from_flat`](../../input/fn.from_flat.html).
+
+use std::fmt::Write;
+
+use super::Options;
+use crate::value::{Key, Value};
+
+/// Serializes a table or array as one line per leaf, laid out according to `options`.
+pub fn to_string_with(value: &Value, options: &Options) -> Result<String, String> {
+    match *value {
+        Value::Array(_) | Value::Table(_) => {}
+        ref value => return Err(format!("Cannot flatten {}", value.type_name())),
+    }
+
+    let mut lines = Vec::new();
+    leaves(&mut String::new(), value, &mut lines);
+    let text = lines.join("\n");
+    if options.ascii {
+        Ok(super::toml::to_ascii(&text))
+    } else {
+        Ok(text)
+    }
+}
+
+/// Collects a line for each leaf of `value`, whose path so far is `path`.
+fn leaves(path: &mut String, value: &Value, lines: &mut Vec<String>) {
+    let len = path.len();
+    match *value {
+        Value::Array(ref array) if !array.is_empty() => {
+            for (i, elem) in array.iter().enumerate() {
+                write!(path, "[{}]", i).unwrap();
+                leaves(path, elem, lines);
+                path.truncate(len);
+            }
+        }
+        Value::Table(ref table) if !table.is_empty() => {
+            for (key, value) in table {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                write!(path, "{}", Key(key)).unwrap();
+                leaves(path, value, lines);
+                path.truncate(len);
+            }
+        }
+        ref value => lines.push(format!("{} = {}", path, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::value;
+    use crate::input::from_flat;
+
+    #[test]
+    fn leaves() {
+        let input = value(
+            r#"{ package = { metadata = { "docs.rs" = { all-features = true } } }, bin = [{ name = 'tq', "" = [[1.5], []] }], features = {} }"#,
+        );
+        let flat = to_string_with(&input, &Options::default()).unwrap();
+        let expected = r#"package.metadata."docs.rs".all-features = true
+bin[0].name = "tq"
+bin[0].""[0][0] = 1.5
+bin[0].""[1] = []
+features = {}"#;
+        assert_eq!(flat, expected);
+        assert_eq!(from_flat(&flat).unwrap(), input);
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = value(
+            r#"[{ "a\"b\n" = null, 'é' = 1979-05-27T07:32:00Z, x = ["line\nbreak\t", -0.0, nan, -inf, 1e100] }]"#,
+        );
+        let flat = to_string_with(&input, &Options::default()).unwrap();
+        assert!(flat.starts_with(r#"[0]."a\"b\n" = null"#));
+        assert_eq!(from_flat(&flat).unwrap().to_string(), input.to_string());
+        assert!(to_string_with(&Value::Integer(1), &Options::default()).is_err());
+    }
+}
@@ -0,0 +1,430 @@
+//! Syntax highlighting of TOML output for terminals.
+//!
+//! Highlighting works on serialized text rather than on values, so that documents written back
+//! with their formatting intact are highlighted the same way as freshly serialized ones, comments
+//! included.
+
+use std::str::FromStr;
+
+use colored::{Color, ColoredString, Colorize};
+
+/// The style of one kind of token, e.g. `1;31` for bold red.
+///
+/// Styles are written as ANSI SGR parameters separated by `;`, as in `JQ_COLORS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    dimmed: bool,
+    italic: bool,
+    underline: bool,
+    blink: bool,
+    reversed: bool,
+    hidden: bool,
+    strikethrough: bool,
+}
+
+impl Style {
+    /// Applies this style to `text`.
+    pub fn paint(&self, text: &str) -> ColoredString {
+        let mut painted = ColoredString::from(text);
+        if let Some(fg) = self.fg {
+            painted = painted.color(fg);
+        }
+        if let Some(bg) = self.bg {
+            painted = painted.on_color(bg);
+        }
+        let styles = [
+            (
+                self.bold,
+                Colorize::bold as fn(ColoredString) -> ColoredString,
+            ),
+            (self.dimmed, Colorize::dimmed),
+            (self.italic, Colorize::italic),
+            (self.underline, Colorize::underline),
+            (self.blink, Colorize::blink),
+            (self.reversed, Colorize::reversed),
+            (self.hidden, Colorize::hidden),
+            (self.strikethrough, Colorize::strikethrough),
+        ];
+        for &(enabled, apply) in &styles {
+            if enabled {
+                painted = apply(painted);
+            }
+        }
+        painted
+    }
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const COLORS: [Color; 8] = [
+            Color::Black,
+            Color::Red,
+            Color::Green,
+            Color::Yellow,
+            Color::Blue,
+            Color::Magenta,
+            Color::Cyan,
+            Color::White,
+        ];
+        const BRIGHT_COLORS: [Color; 8] = [
+            Color::BrightBlack,
+            Color::BrightRed,
+            Color::BrightGreen,
+            Color::BrightYellow,
+            Color::BrightBlue,
+            Color::BrightMagenta,
+            Color::BrightCyan,
+            Color::BrightWhite,
+        ];
+
+        let mut style = Style::default();
+        for param in s.split(';').filter(|p| !p.is_empty()) {
+            let code: usize = param
+                .parse()
+                .map_err(|_| format!("Invalid color {:?}", s))?;
+            match code {
+                0 => style = Style::default(),
+                1 => style.bold = true,
+                2 => style.dimmed = true,
+                3 => style.italic = true,
+                4 => style.underline = true,
+                5 => style.blink = true,
+                7 => style.reversed = true,
+                8 => style.hidden = true,
+                9 => style.strikethrough = true,
+                30..=37 => style.fg = Some(COLORS[code - 30]),
+                39 => style.fg = None,
+                40..=47 => style.bg = Some(COLORS[code - 40]),
+                49 => style.bg = None,
+                90..=97 => style.fg = Some(BRIGHT_COLORS[code - 90]),
+                100..=107 => style.bg = Some(BRIGHT_COLORS[code - 100]),
+                _ => return Err(format!("Invalid color {:?}", s)),
+            }
+        }
+        Ok(style)
+    }
+}
+
+/// The styles of each kind of token.
+///
+/// A palette is written as a `:`-separated list of styles in the order `null:false:true:numbers:
+/// strings:arrays:tables:keys:headers:datetimes:comments`, like jq's `JQ_COLORS`. Styles left out
+/// at the end of the list keep their defaults.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub null: Style,
+    pub false_: Style,
+    pub true_: Style,
+    pub numbers: Style,
+    pub strings: Style,
+    pub arrays: Style,
+    pub tables: Style,
+    pub keys: Style,
+    pub headers: Style,
+    pub datetimes: Style,
+    pub comments: Style,
+}
+
+impl Palette {
+    /// Returns the styles in the order they are listed in.
+    fn styles_mut(&mut self) -> [&mut Style; 11] {
+        [
+            &mut self.null,
+            &mut self.false_,
+            &mut self.true_,
+            &mut self.numbers,
+            &mut self.strings,
+            &mut self.arrays,
+            &mut self.tables,
+            &mut self.keys,
+            &mut self.headers,
+            &mut self.datetimes,
+            &mut self.comments,
+        ]
+    }
+
+    /// Overrides the leading styles of this palette with those listed in `s`.
+    fn set(&mut self, s: &str) -> Result<(), String> {
+        let specs: Vec<_> = s.split(':').collect();
+        let mut styles = self.styles_mut();
+        if specs.len() > styles.len() {
+            return Err(format!("Too many colors in {:?}", s));
+        }
+        for (style, spec) in styles.iter_mut().zip(specs) {
+            **style = spec.parse()?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        let plain = Style::default();
+        let mut palette = Palette {
+            null: plain,
+            false_: plain,
+            true_: plain,
+            numbers: plain,
+            strings: plain,
+            arrays: plain,
+            tables: plain,
+            keys: plain,
+            headers: plain,
+            datetimes: plain,
+            comments: plain,
+        };
+        palette
+            .set("1;30:0;39:0;39:0;36:0;32:1;39:1;39:34;1:1;35:0;33:2;37")
+            .expect("default palette is invalid");
+        palette
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut palette = Palette::default();
+        palette.set(s)?;
+        Ok(palette)
+    }
+}
+
+/// Highlights serialized TOML or JSON.
+///
+/// If `document` is `true`, `text` is a whole TOML document, with keys and table headers.
+/// Otherwise, it is a single inline value, which may also be JSON.
+pub fn highlight(text: &str, document: bool, palette: &Palette) -> String {
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut nesting = Vec::new();
+    let mut expect_key = document;
+    let mut line_start = true;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let style = match bytes[i] {
+            b'\n' => {
+                out.push('\n');
+                i += 1;
+                if document && nesting.is_empty() {
+                    expect_key = true;
+                    line_start = true;
+                }
+                continue;
+            }
+            b' ' | b'\t' | b'\r' => {
+                out.push(bytes[i] as char);
+                i += 1;
+                continue;
+            }
+            b'#' => {
+                i = find(bytes, i, |b| b == b'\n');
+                Some(palette.comments)
+            }
+            b'[' if line_start && expect_key && nesting.is_empty() => {
+                i = header_end(bytes, i);
+                expect_key = false;
+                Some(palette.headers)
+            }
+            b'"' | b'\'' => {
+                i = string_end(bytes, i);
+                Some(if expect_key {
+                    palette.keys
+                } else {
+                    palette.strings
+                })
+            }
+            b'=' | b':' | b'.' if expect_key => {
+                expect_key = bytes[i] == b'.';
+                i += 1;
+                None
+            }
+            b'[' | b'{' => {
+                nesting.push(bytes[i]);
+                expect_key = bytes[i] == b'{';
+                i += 1;
+                Some(if bytes[start] == b'[' {
+                    palette.arrays
+                } else {
+                    palette.tables
+                })
+            }
+            b']' | b'}' => {
+                nesting.pop();
+                i += 1;
+                Some(if bytes[start] == b']' {
+                    palette.arrays
+                } else {
+                    palette.tables
+                })
+            }
+            b',' => {
+                expect_key = nesting.last() == Some(&b'{');
+                i += 1;
+                None
+            }
+            _ if expect_key => {
+                i = find(bytes, i, |b| {
+                    !(b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+                });
+                i = i.max(start + 1);
+                Some(palette.keys)
+            }
+            _ => {
+                i = word_end(bytes, i);
+                Some(match &text[start..i] {
+                    "null" => palette.null,
+                    "false" => palette.false_,
+                    "true" => palette.true_,
+                    word if is_datetime(word) => palette.datetimes,
+                    _ => palette.numbers,
+                })
+            }
+        };
+
+        line_start = false;
+        match style {
+            Some(style) => out.push_str(&style.paint(&text[start..i]).to_string()),
+            None => out.push_str(&text[start..i]),
+        }
+    }
+
+    out
+}
+
+/// Returns the index of the first byte from `i` onwards matching `pred`, or the end of `bytes`.
+fn find(bytes: &[u8], i: usize, pred: impl Fn(u8) -> bool) -> usize {
+    bytes[i..]
+        .iter()
+        .position(|&b| pred(b))
+        .map_or(bytes.len(), |n| i + n)
+}
+
+/// Returns the end of the `[table]` or `[[array.of.tables]]` header starting at `i`.
+fn header_end(bytes: &[u8], mut i: usize) -> usize {
+    let double = bytes.get(i + 1) == Some(&b'[');
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => i = string_end(bytes, i),
+            b'\n' => return i,
+            b']' if double && bytes.get(i + 1) == Some(&b']') => return i + 2,
+            b']' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// Returns the end of the string starting at `i`, which may be a multi-line or literal string.
+fn string_end(bytes: &[u8], i: usize) -> usize {
+    let quote = bytes[i];
+    let escapes = quote == b'"';
+    let triple = bytes[i..].starts_with(&[quote; 3]);
+    let mut j = i + if triple { 3 } else { 1 };
+
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' if escapes => j += 2,
+            b'\n' if !triple => return j,
+            b if b == quote && !triple => return j + 1,
+            b if b == quote && bytes[j..].starts_with(&[quote; 3]) => {
+                // Up to two quotes directly before the closing delimiter belong to the string.
+                let run = bytes[j..].iter().take_while(|&&b| b == quote).count();
+                return j + run.min(5);
+            }
+            _ => j += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the end of the number, boolean or datetime starting at `i`.
+fn word_end(bytes: &[u8], i: usize) -> usize {
+    let end = find(bytes, i, |b| b" \t\r\n,]}=#".contains(&b)).max(i + 1);
+    // The date and time of a datetime may be separated by a space instead of `T`.
+    let is_date = end - i == 10 && bytes[i + 4] == b'-' && bytes[i + 7] == b'-';
+    let time = bytes.get(end + 1..end + 4).unwrap_or_default();
+    if is_date && bytes[end..].starts_with(b" ") && time.len() == 3 && time[2] == b':' {
+        word_end(bytes, end + 1)
+    } else {
+        end
+    }
+}
+
+fn is_datetime(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    let is_date = bytes.len() >= 10 && bytes[4] == b'-' && bytes[7] == b'-';
+    let is_time = bytes.len() >= 8 && bytes[2] == b':' && bytes[5] == b':';
+    bytes
+        .iter()
+        .all(|b| b.is_ascii_digit() || b"-:+.TtZz ".contains(b))
+        && (is_date || is_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str, document: bool) -> String {
+        // Replace each style with a marker, to make the expected output readable.
+        let markers = [
+            "30", "31", "32", "33", "34", "35", "36", "37", "90", "91", "92",
+        ];
+        let palette: Palette = markers.join(":").parse().unwrap();
+
+        colored::control::set_override(true);
+        let names = [
+            "null", "false", "true", "num", "str", "arr", "tab", "key", "hdr", "dt", "cmt",
+        ];
+        let mut out = highlight(text, document, &palette);
+        for (marker, name) in markers.iter().zip(names.iter()) {
+            out = out.replace(&format!("\x1b[{}m", marker), &format!("<{}>", name));
+        }
+        out.replace("\x1b[0m", "</>")
+    }
+
+    #[test]
+    fn documents() {
+        let text = "# top\na = \"x\" # note\n\"b.c\".d = [1, 0x10]\n\n[[t.'u v']]\ne = { f = true, g = 1979-05-27 07:32:00Z }";
+        let expected = "<cmt># top</>\n<key>a</> = <str>\"x\"</> <cmt># note</>\n<key>\"b.c\"</>.<key>d</> = <arr>[</><num>1</>, <num>0x10</><arr>]</>\n\n<hdr>[[t.'u v']]</>\n<key>e</> = <tab>{</> <key>f</> = <true>true</>, <key>g</> = <dt>1979-05-27 07:32:00Z</> <tab>}</>";
+        assert_eq!(tokens(text, true), expected);
+    }
+
+    #[test]
+    fn multiline_strings() {
+        let text = "s = \"\"\"\none \"two\"\"\"\"\nt = '''\n[not a header]'''";
+        let expected = "<key>s</> = <str>\"\"\"\none \"two\"\"\"\"</>\n<key>t</> = <str>'''\n[not a header]'''</>";
+        assert_eq!(tokens(text, true), expected);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(tokens("\"tq\"", false), "<str>\"tq\"</>");
+        assert_eq!(
+            tokens("[null, -1.5e3, false]", false),
+            "<arr>[</><null>null</>, <num>-1.5e3</>, <false>false</><arr>]</>"
+        );
+        assert_eq!(
+            tokens("{\n  \"a\": [\"b\"],\n  \"c\": 1\n}", false),
+            "<tab>{</>\n  <key>\"a\"</>: <arr>[</><str>\"b\"</><arr>]</>,\n  <key>\"c\"</>: <num>1</>\n<tab>}</>"
+        );
+    }
+
+    #[test]
+    fn palettes() {
+        let palette: Palette = "0;31:1".parse().unwrap();
+        assert_eq!(palette.null, "31".parse().unwrap());
+        assert_eq!(palette.false_, "1".parse().unwrap());
+        assert_eq!(palette.comments, Palette::default().comments);
+        assert!("1;31:foo".parse::<Palette>().is_err());
+        assert!("38;5;1".parse::<Style>().is_err());
+        assert!(":::::::::::".parse::<Palette>().is_err());
+    }
+}
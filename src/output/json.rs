@@ -0,0 +1,205 @@
+//! Serialization of values as JSON, laid out the way `jq` writes it.
+//!
+//! Arrays and objects are written with one element per line, unless they are empty or compact
+//! output was requested. Since JSON has no datetimes, they are written as RFC 3339 strings, or as
+//! `{"$datetime": "..."}` objects which are read back as datetimes.
+
+use std::fmt::Write;
+
+use super::Options;
+use crate::value::{Table, Value};
+
+/// The key of the object which a datetime is written as, when tagging datetimes.
+pub const DATETIME_TAG: &str = "$datetime";
+
+/// Indentation of nested arrays and objects, when no indentation is set.
+const DEFAULT_INDENT: &str = "  ";
+
+/// Serializes a value as JSON, laid out according to `options`.
+///
+/// `NaN` is written as `null`, and infinities as the largest finite numbers, as `jq` does.
+pub fn to_string_with(value: &Value, options: &Options) -> String {
+    let indent = options
+        .indent
+        .as_ref()
+        .map_or(DEFAULT_INDENT, String::as_str);
+    let mut writer = Writer {
+        out: String::new(),
+        options,
+        indent: if options.compact { "" } else { indent },
+    };
+    writer.value(0, value);
+    writer.out
+}
+
+struct Writer<'a> {
+    out: String,
+    options: &'a Options,
+    /// The indentation of nested structures, which are written on a single line if empty.
+    indent: &'a str,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes a value nested within `level` arrays and objects.
+    fn value(&mut self, level: usize, value: &Value) {
+        match *value {
+            Value::Null => self.out.push_str("null"),
+            Value::Boolean(b) => write!(self.out, "{}", b).unwrap(),
+            Value::Integer(i) => write!(self.out, "{}", i).unwrap(),
+            Value::Float(f) if f.is_nan() => self.out.push_str("null"),
+            Value::Float(f) if f.is_infinite() => {
+                let max = if f > 0.0 { f64::MAX } else { f64::MIN };
+                write!(self.out, "{:e}", max).unwrap()
+            }
+            Value::Float(f) => write!(self.out, "{:?}", f).unwrap(),
+            Value::String(ref s) => self.string(s),
+            Value::Datetime(ref dt) if self.options.tagged_datetimes => {
+                let mut table = Table::new();
+                table.insert(DATETIME_TAG.to_string(), Value::String(dt.to_string()));
+                self.value(level, &Value::Table(table));
+            }
+            Value::Datetime(ref dt) => self.string(&dt.to_string()),
+            Value::Array(ref array) if array.is_empty() => self.out.push_str("[]"),
+            Value::Array(ref array) => {
+                self.out.push('[');
+                for (i, elem) in array.iter().enumerate() {
+                    self.separator(level + 1, i > 0);
+                    self.value(level + 1, elem);
+                }
+                self.separator(level, false);
+                self.out.push(']');
+            }
+            Value::Table(ref table) if table.is_empty() => self.out.push_str("{}"),
+            Value::Table(ref table) => {
+                self.out.push('{');
+                for (i, (key, value)) in table.iter().enumerate() {
+                    self.separator(level + 1, i > 0);
+                    self.string(key);
+                    self.out.push(':');
+                    if !self.indent.is_empty() {
+                        self.out.push(' ');
+                    }
+                    self.value(level + 1, value);
+                }
+                self.separator(level, false);
+                self.out.push('}');
+            }
+        }
+    }
+
+    /// Writes the comma and line break before an element indented `level` times.
+    fn separator(&mut self, level: usize, comma: bool) {
+        if comma {
+            self.out.push(',');
+        }
+        if !self.indent.is_empty() {
+            self.out.push('\n');
+            for _ in 0..level {
+                self.out.push_str(self.indent);
+            }
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                '\x08' => self.out.push_str("\\b"),
+                '\x0C' => self.out.push_str("\\f"),
+                c if c.is_control() || (self.options.ascii && !c.is_ascii()) => {
+                    let mut units = [0; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        write!(self.out, "\\u{:04x}", unit).unwrap();
+                    }
+                }
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::value;
+
+    #[test]
+    fn pretty() {
+        let input = value("{ b = [1, 2.5, { c = null }], a = {}, d = [], e = 'x' }");
+        let json = to_string_with(&input, &Options::default());
+        let expected = r#"{
+  "b": [
+    1,
+    2.5,
+    {
+      "c": null
+    }
+  ],
+  "a": {},
+  "d": [],
+  "e": "x"
+}"#;
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn compact() {
+        let options = Options {
+            compact: true,
+            ..Options::default()
+        };
+        let input = value("{ a = [1, { b = true }], c = 'x' }");
+        let json = to_string_with(&input, &options);
+        assert_eq!(json, r#"{"a":[1,{"b":true}],"c":"x"}"#);
+
+        let options = Options {
+            indent: Some(String::new()),
+            ..Options::default()
+        };
+        assert_eq!(to_string_with(&input, &options), json);
+    }
+
+    #[test]
+    fn datetimes() {
+        let input = value("[1979-05-27T07:32:00Z, 1979-05-27]");
+        let options = Options {
+            compact: true,
+            ..Options::default()
+        };
+        let json = to_string_with(&input, &options);
+        assert_eq!(json, r#"["1979-05-27T07:32:00Z","1979-05-27"]"#);
+
+        let options = Options {
+            compact: true,
+            tagged_datetimes: true,
+            ..Options::default()
+        };
+        let json = to_string_with(&input, &options);
+        let expected = r#"[{"$datetime":"1979-05-27T07:32:00Z"},{"$datetime":"1979-05-27"}]"#;
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn escapes() {
+        let options = Options {
+            ascii: true,
+            ..Options::default()
+        };
+        let input = value(r#""\"é🦀\"\n""#);
+        assert_eq!(
+            to_string_with(&input, &Options::default()),
+            r#""\"é🦀\"\n""#
+        );
+        assert_eq!(
+            to_string_with(&input, &options),
+            r#""\"\u00e9\ud83e\udd80\"\n""#
+        );
+        assert_eq!(to_string_with(&value("nan"), &options), "null");
+    }
+}
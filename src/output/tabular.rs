@@ -0,0 +1,262 @@
+//! Serialization of tables as the rows of CSV, TSV or Markdown tables.
+//!
+//! Unless they are given explicitly, the columns are the keys of every row, in the order they are
+//! first seen. Cells missing from a row are left empty, as are `null` cells, and nested arrays and
+//! tables are flattened according to a [`Flatten`](./enum.Flatten.html) strategy.
+
+use std::str::FromStr;
+
+use super::{json, Format, Options};
+use crate::value::{Table, Value};
+
+/// How arrays and tables nested in a row are written.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Flatten {
+    /// Written as compact JSON in a single cell, e.g. `["a","b"]`.
+    #[default]
+    Json,
+    /// Written as inline TOML in a single cell, e.g. `["a", "b"]`.
+    Toml,
+    /// Spread over one column per leaf, e.g. `features.0` and `features.1`.
+    Dotted,
+}
+
+impl FromStr for Flatten {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Flatten::Json),
+            "toml" => Ok(Flatten::Toml),
+            "dotted" => Ok(Flatten::Dotted),
+            s => Err(format!("Unknown flattening strategy `{}`", s)),
+        }
+    }
+}
+
+/// Writes `rows` as a table in `format`, which must be CSV, TSV or Markdown.
+///
+/// Each of `rows` is either a table, which is written as one row, or an array of tables.
+pub fn to_string(rows: &[Value], format: Format, options: &Options) -> Result<String, String> {
+    let mut tables = Vec::new();
+    for row in rows {
+        match *row {
+            Value::Table(ref table) => tables.push(flatten_row(table, options.flatten)),
+            Value::Array(ref array) => {
+                for row in array {
+                    match *row {
+                        Value::Table(ref table) => tables.push(flatten_row(table, options.flatten)),
+                        ref row => return Err(not_a_row(row)),
+                    }
+                }
+            }
+            ref row => return Err(not_a_row(row)),
+        }
+    }
+
+    let columns = match options.columns {
+        Some(ref columns) => columns.clone(),
+        None => {
+            let mut columns = Table::new();
+            for table in &tables {
+                columns.extend(table.keys().map(|k| (k.clone(), Value::Null)));
+            }
+            columns.into_keys().collect()
+        }
+    };
+
+    let cells = tables.iter().map(|table| {
+        columns
+            .iter()
+            .map(|column| {
+                table
+                    .get(column)
+                    .map_or(String::new(), |v| cell(v, options))
+            })
+            .collect()
+    });
+    let rows: Vec<Vec<String>> = Some(columns.clone()).into_iter().chain(cells).collect();
+
+    let lines: Vec<String> = match format {
+        Format::Csv => rows.iter().map(|row| join(row, ",", escape_csv)).collect(),
+        Format::Tsv => rows.iter().map(|row| join(row, "\t", escape_tsv)).collect(),
+        Format::Markdown => markdown(&rows),
+        format => return Err(format!("{:?} is not a tabular format", format)),
+    };
+    Ok(lines.join("\n"))
+}
+
+fn not_a_row(value: &Value) -> String {
+    format!("Cannot write {} as a table row", value.type_name())
+}
+
+/// Spreads the nested arrays and tables of a row over dotted columns, if `flatten` is `Dotted`.
+fn flatten_row(table: &Table, flatten: Flatten) -> Table {
+    fn spread(prefix: String, value: &Value, row: &mut Table) {
+        let entries: Vec<(String, &Value)> = match *value {
+            Value::Array(ref array) if !array.is_empty() => array
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (i.to_string(), v))
+                .collect(),
+            Value::Table(ref table) if !table.is_empty() => {
+                table.iter().map(|(k, v)| (k.clone(), v)).collect()
+            }
+            ref value => {
+                row.insert(prefix, value.clone());
+                return;
+            }
+        };
+
+        for (key, value) in entries {
+            spread(format!("{}.{}", prefix, key), value, row);
+        }
+    }
+
+    if flatten != Flatten::Dotted {
+        return table.clone();
+    }
+
+    let mut row = Table::new();
+    for (key, value) in table {
+        spread(key.clone(), value, &mut row);
+    }
+    row
+}
+
+/// Returns the text of a cell, before it is escaped.
+fn cell(value: &Value, options: &Options) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref s) => s.clone(),
+        Value::Array(_) | Value::Table(_) if options.flatten == Flatten::Json => {
+            let options = Options {
+                compact: true,
+                ..Options::default()
+            };
+            json::to_string_with(value, &options)
+        }
+        ref value => value.to_string(),
+    }
+}
+
+fn join(row: &[String], separator: &str, escape: fn(&str) -> String) -> String {
+    let cells: Vec<_> = row.iter().map(|cell| escape(cell)).collect();
+    cells.join(separator)
+}
+
+/// Quotes a CSV cell if it contains a separator, quote or line break, as described in RFC 4180.
+fn escape_csv(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Escapes the tabs, line breaks and backslashes of a TSV cell, as `@tsv` does.
+fn escape_tsv(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Escapes the pipes and line breaks of a Markdown cell, which must fit on a single line.
+fn escape_markdown(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\n', '\r'], "<br>")
+}
+
+/// Lays out the header and rows of a Markdown table, padding each column to the same width.
+fn markdown(rows: &[Vec<String>]) -> Vec<String> {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| escape_markdown(cell)).collect())
+        .collect();
+
+    let mut widths = vec![3; rows[0].len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let pad = |row: &Vec<String>| {
+        let cells = row.iter().zip(&widths);
+        line(
+            cells
+                .map(|(c, &w)| format!("{}{}", c, " ".repeat(w - c.chars().count())))
+                .collect(),
+        )
+    };
+
+    let mut lines = vec![pad(&rows[0])];
+    lines.push(line(widths.iter().map(|&w| "-".repeat(w)).collect()));
+    lines.extend(rows[1..].iter().map(pad));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+
+    fn rows(literal: &str) -> Vec<Value> {
+        let filter = literal.parse().unwrap();
+        Interpreter::new().eval(&filter, Value::Null).unwrap()
+    }
+
+    #[test]
+    fn csv_and_tsv() {
+        let rows = rows("[{ name = 'tq', version = '0.1.0' }, { name = 'a,\"b\"', features = ['x', 'y'] }], { name = \"tab\\tline\\n\" }");
+        let options = Options::default();
+        let csv = to_string(&rows, Format::Csv, &options).unwrap();
+        let expected = "name,version,features\ntq,0.1.0,\n\"a,\"\"b\"\"\",,\"[\"\"x\"\",\"\"y\"\"]\"\n\"tab\tline\n\",,";
+        assert_eq!(csv, expected);
+
+        let tsv = to_string(&rows, Format::Tsv, &options).unwrap();
+        let expected =
+            "name\tversion\tfeatures\ntq\t0.1.0\t\na,\"b\"\t\t[\"x\",\"y\"]\ntab\\tline\\n\t\t";
+        assert_eq!(tsv, expected);
+    }
+
+    #[test]
+    fn markdown_table() {
+        let rows = rows("[{ name = 'tq', deps = { log = '0.4' } }, { name = 'a|b', version = 2 }]");
+        let options = Options {
+            columns: Some(vec!["name".to_string(), "version".to_string()]),
+            ..Options::default()
+        };
+        let md = to_string(&rows, Format::Markdown, &options).unwrap();
+        let expected =
+            "| name | version |\n| ---- | ------- |\n| tq   |         |\n| a\\|b | 2       |";
+        assert_eq!(md, expected);
+    }
+
+    #[test]
+    fn flattening() {
+        let rows = rows("{ name = 'tq', deps = { log = '0.4' }, features = ['x'], empty = [] }");
+        let options = Options {
+            flatten: Flatten::Dotted,
+            ..Options::default()
+        };
+        let csv = to_string(&rows, Format::Csv, &options).unwrap();
+        assert_eq!(csv, "name,deps.log,features.0,empty\ntq,0.4,x,[]");
+
+        let options = Options {
+            flatten: Flatten::Toml,
+            ..Options::default()
+        };
+        let csv = to_string(&rows, Format::Csv, &options).unwrap();
+        assert_eq!(
+            csv,
+            "name,deps,features,empty\ntq,\"{ log = \"\"0.4\"\" }\",\"[\"\"x\"\"]\",[]"
+        );
+        assert!(to_string(&rows[..], Format::Json, &options).is_err());
+        assert!(to_string(&[Value::Integer(1)], Format::Csv, &options).is_err());
+    }
+}
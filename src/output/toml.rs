@@ -0,0 +1,684 @@
+//! Serialization of values as TOML, laid out the way a person would write it by hand.
+//!
+//! Tables are written as documents: plain values first, followed by `[table]` sections and
+//! `[[array.of.tables]]`, each in the order their keys appear in the table. Nested tables are only
+//! written inline when they are small and sit among plain values, e.g. a dependency specification
+//! in `[dependencies]`. Strings containing newlines are written as multi-line strings, and keys are
+//! only quoted when required.
+//!
+//! Any other value is written inline, as it would appear on the right side of `key = value`.
+//!
+//! Filters can override these choices for particular tables and arrays with [`Styles`], set by
+//! builtins such as `inline` and `standard`.
+//!
+//! [`Styles`]: ./struct.Styles.html
+
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+
+use super::Options;
+use crate::ast::tokens::Literal;
+use crate::value::{Key, Table, Value};
+
+/// The widest `key = { ... }` line which may be written as an inline table.
+const MAX_INLINE_WIDTH: usize = 80;
+
+/// Indentation of the elements of wrapped arrays, when no indentation is set.
+const DEFAULT_INDENT: &str = "    ";
+
+/// Serializes a value as TOML.
+///
+/// Tables are serialized as documents, and always produce valid TOML unless they contain `null`,
+/// which has no TOML representation. Other values are serialized inline.
+pub fn to_string(value: &Value) -> Result<String, String> {
+    to_string_with(value, &Options::default())
+}
+
+/// Serializes a value as TOML, laid out according to `options`.
+pub fn to_string_with(value: &Value, options: &Options) -> Result<String, String> {
+    to_string_styled(value, options, &Styles::default())
+}
+
+/// Like [`to_string_with`](./fn.to_string_with.html), but writes the tables and arrays found at
+/// the paths of `styles` in the given style, wherever TOML allows it.
+pub fn to_string_styled(
+    value: &Value,
+    options: &Options,
+    styles: &Styles,
+) -> Result<String, String> {
+    let mut writer = Writer {
+        out: String::new(),
+        options,
+        styles,
+        path: Vec::new(),
+        document: false,
+    };
+
+    match *value {
+        ref value if options.compact => writer.inline(value)?,
+        Value::Table(ref table) => {
+            writer.document = true;
+            writer.table(&mut Vec::new(), table, false)?;
+        }
+        ref value => writer.value(0, value)?,
+    }
+
+    if options.ascii {
+        Ok(to_ascii(&writer.out))
+    } else {
+        Ok(writer.out)
+    }
+}
+
+/// Escapes the non-ASCII characters of serialized TOML without changing its meaning.
+///
+/// Characters in basic strings and quoted keys are escaped as `\uXXXX` or `\UXXXXXXXX`, and literal
+/// strings containing them are rewritten as basic strings, since literal strings have no escapes.
+/// Comments are left as they are.
+pub fn to_ascii(toml: &str) -> String {
+    let mut out = String::with_capacity(toml.len());
+    let mut rest = toml;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '#' => rest.find('\n').unwrap_or(rest.len()),
+            '"' | '\'' => string_len(rest),
+            c => c.len_utf8(),
+        };
+
+        let token = &rest[..len];
+        match c {
+            '"' => out.push_str(&escape_non_ascii(token)),
+            '\'' if !token.is_ascii() => out.push_str(&literal_to_basic(token)),
+            _ => out.push_str(token),
+        }
+        rest = &rest[len..];
+    }
+    out
+}
+
+/// Escapes every non-ASCII character of `s`, e.g. `é` as `\u00E9`.
+pub fn escape_non_ascii(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c as u32 {
+            _ if c.is_ascii() => escaped.push(c),
+            code if code <= 0xFFFF => write!(escaped, "\\u{:04X}", code).unwrap(),
+            code => write!(escaped, "\\U{:08X}", code).unwrap(),
+        }
+    }
+    escaped
+}
+
+/// Returns the length of the string at the start of `s`, including its delimiters.
+fn string_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let quote = bytes[0];
+    let multiline = bytes.len() >= 3 && bytes[1] == quote && bytes[2] == quote;
+    let mut i = if multiline { 3 } else { 1 };
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if quote == b'"' => i += 2,
+            b'\n' if !multiline => return i,
+            b if b == quote && !multiline => return i + 1,
+            b if b == quote => {
+                let run = bytes[i..].iter().take_while(|&&b| b == quote).count();
+                if run >= 3 {
+                    return i + run;
+                }
+                i += run;
+            }
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Rewrites a literal string as the equivalent basic string, escaping non-ASCII characters.
+fn literal_to_basic(literal: &str) -> String {
+    let (open, close) = if literal.starts_with("'''") {
+        ("'''", "\"\"\"")
+    } else {
+        ("'", "\"")
+    };
+    let len = open.len();
+    if literal.len() < 2 * len || !literal.ends_with(open) {
+        return literal.to_string();
+    }
+
+    let mut basic = close.to_string();
+    for c in literal[len..literal.len() - len].chars() {
+        match c {
+            '\\' => basic.push_str("\\\\"),
+            '"' => basic.push_str("\\\""),
+            c => basic.push(c),
+        }
+    }
+    basic.push_str(close);
+    escape_non_ascii(&basic)
+}
+
+/// How a table or array of tables is written, as requested by a filter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Style {
+    /// `key = { ... }` or `key = [{ ... }]`
+    Inline,
+    /// `[path.to.key]`
+    Standard,
+    /// `key.member = value`, for each member
+    Dotted,
+    /// `[[path.to.key]]`
+    ArrayOfTables,
+}
+
+impl Style {
+    /// Returns whether a value can be written in this style.
+    pub fn fits(self, value: &Value) -> bool {
+        match self {
+            Style::Inline => matches!(*value, Value::Array(_) | Value::Table(_)),
+            Style::Standard => is_table(value),
+            Style::Dotted => matches!(*value, Value::Table(ref table) if !table.is_empty()),
+            Style::ArrayOfTables => is_array_of_tables(value),
+        }
+    }
+
+    /// Returns the name of the builtin which sets this style.
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Inline => "inline",
+            Style::Standard => "standard",
+            Style::Dotted => "dotted",
+            Style::ArrayOfTables => "aot",
+        }
+    }
+}
+
+/// The styles in which the tables and arrays of a value are written, keyed by their paths.
+///
+/// Styles which do not fit the value found at their path are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct Styles {
+    /// The paths written as arrays, with the paths themselves, in the order they were styled.
+    styles: IndexMap<String, (Vec<Value>, Style)>,
+}
+
+impl Styles {
+    /// Returns the style of the value at `path`, if it has one.
+    pub fn get(&self, path: &[Value]) -> Option<Style> {
+        let key = Value::Array(path.to_vec()).to_string();
+        self.styles.get(&key).map(|&(_, style)| style)
+    }
+
+    /// Sets the style of the value at `path`, replacing any style it had.
+    pub fn set(&mut self, path: &[Value], style: Style) {
+        let key = Value::Array(path.to_vec()).to_string();
+        self.styles.shift_remove(&key);
+        self.styles.insert(key, (path.to_vec(), style));
+    }
+
+    /// Returns the styled paths, outermost first.
+    pub fn iter(&self) -> impl Iterator<Item = (&[Value], Style)> {
+        let mut styles: Vec<_> = self
+            .styles
+            .values()
+            .map(|&(ref path, style)| (path.as_slice(), style))
+            .collect();
+        styles.sort_by_key(|&(path, _)| path.len());
+        styles.into_iter()
+    }
+}
+
+/// How a member of a table is laid out within a document.
+enum Layout {
+    /// `key = value`
+    Value,
+    /// `key.member = value`, for each member
+    Dotted,
+    /// `[path.to.key]`
+    Table,
+    /// `[[path.to.key]]`
+    ArrayOfTables,
+}
+
+struct Writer<'a> {
+    out: String,
+    options: &'a Options,
+    styles: &'a Styles,
+    /// The path of the value being written, including array indices.
+    path: Vec<Value>,
+    /// Whether a document is being written, in which `null` cannot appear.
+    document: bool,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes the members of `table`, preceded by a header if `keys` is not the document root.
+    fn table<'t>(
+        &mut self,
+        keys: &mut Vec<&'t str>,
+        table: &'t Table,
+        in_array: bool,
+    ) -> Result<(), String> {
+        let styles: Vec<_> = table.iter().map(|(k, v)| self.style(k, v)).collect();
+        let has_values = table.values().zip(&styles).any(|(v, style)| match *style {
+            Some(Style::Inline) | Some(Style::Dotted) => true,
+            Some(Style::Standard) | Some(Style::ArrayOfTables) => false,
+            None => !is_table(v) && !is_array_of_tables(v),
+        });
+        let layouts: Vec<_> = table
+            .iter()
+            .zip(styles)
+            .map(|((k, v), style)| (k, v, layout(keys.len(), has_values, k, v, style)))
+            .collect();
+
+        // Sections are indented by their depth, so `[a]` is not indented but `[a.b]` is.
+        let level = keys.len().saturating_sub(1);
+        let needs_header = has_values || table.is_empty();
+        if in_array || (!keys.is_empty() && needs_header) {
+            if !self.out.is_empty() {
+                self.out.push_str("\n\n");
+            }
+            self.indent(level);
+            let path: Vec<_> = keys.iter().map(|k| Key(k).to_string()).collect();
+            if in_array {
+                write!(self.out, "[[{}]]", path.join(".")).unwrap();
+            } else {
+                write!(self.out, "[{}]", path.join(".")).unwrap();
+            }
+        }
+
+        for &(key, value, ref layout) in &layouts {
+            match (layout, value) {
+                (Layout::Value, value) => {
+                    if !self.out.is_empty() {
+                        self.out.push('\n');
+                    }
+                    self.indent(level);
+                    write!(self.out, "{} = ", Key(key)).unwrap();
+                    self.value(level, value)?;
+                }
+                (Layout::Dotted, Value::Table(ref table)) => {
+                    self.path.push(Value::from(key.as_str()));
+                    self.dotted(level, &Key(key).to_string(), table)?;
+                    self.path.pop();
+                }
+                _ => {}
+            }
+        }
+
+        for (key, value, layout) in layouts {
+            keys.push(key);
+            self.path.push(Value::from(key.as_str()));
+            match (layout, value) {
+                (Layout::Table, Value::Table(ref table)) => self.table(keys, table, false)?,
+                (Layout::ArrayOfTables, Value::Array(ref array)) => {
+                    for (i, elem) in array.iter().enumerate() {
+                        if let Value::Table(ref table) = *elem {
+                            self.path.push(Value::Integer(i as i64));
+                            self.table(keys, table, true)?;
+                            self.path.pop();
+                        }
+                    }
+                }
+                _ => {}
+            }
+            self.path.pop();
+            keys.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Writes the members of a dotted table as `prefix.member = value` lines, indented `level`
+    /// times, with nested tables dotted as well unless they are styled as inline tables.
+    fn dotted(&mut self, level: usize, prefix: &str, table: &Table) -> Result<(), String> {
+        for (key, value) in table {
+            let prefix = format!("{}.{}", prefix, Key(key));
+            let is_inline = self.style(key, value) == Some(Style::Inline);
+            self.path.push(Value::from(key.as_str()));
+            match *value {
+                Value::Table(ref table) if !table.is_empty() && !is_inline => {
+                    self.dotted(level, &prefix, table)?;
+                }
+                ref value => {
+                    if !self.out.is_empty() {
+                        self.out.push('\n');
+                    }
+                    self.indent(level);
+                    write!(self.out, "{} = ", prefix).unwrap();
+                    self.value(level, value)?;
+                }
+            }
+            self.path.pop();
+        }
+        Ok(())
+    }
+
+    /// Returns the style of the member `key = value` of the table being written, if it fits.
+    fn style(&self, key: &str, value: &Value) -> Option<Style> {
+        let mut path = self.path.clone();
+        path.push(Value::from(key));
+        self.styles.get(&path).filter(|style| style.fits(value))
+    }
+
+    /// Writes the right side of `key = value`, on a line indented `level` times.
+    ///
+    /// Strings containing newlines are written as multi-line strings within documents, and arrays
+    /// are broken into one element per line if the line would be wider than `array_wrap`.
+    fn value(&mut self, level: usize, value: &Value) -> Result<(), String> {
+        match *value {
+            Value::String(ref s) if self.document && s.contains('\n') => {
+                self.multiline(s);
+                Ok(())
+            }
+            Value::Array(ref array) if !array.is_empty() && self.options.array_wrap.is_some() => {
+                let start = self.out.len();
+                self.inline(value)?;
+                let line_start = self.out[..start].rfind('\n').map_or(0, |i| i + 1);
+                let width = self.out[line_start..].chars().count();
+                if self.options.array_wrap.is_some_and(|max| width <= max) {
+                    return Ok(());
+                }
+
+                self.out.truncate(start);
+                self.out.push_str("[\n");
+                for elem in array {
+                    match self.options.indent {
+                        Some(ref unit) => self.out.push_str(&unit.repeat(level + 1)),
+                        None => self.out.push_str(DEFAULT_INDENT),
+                    }
+                    self.inline(elem)?;
+                    self.out.push_str(",\n");
+                }
+                self.indent(level);
+                self.out.push(']');
+                Ok(())
+            }
+            ref value => self.inline(value),
+        }
+    }
+
+    /// Writes a value on a single line, e.g. `{ a = 1, b = [true] }`.
+    fn inline(&mut self, value: &Value) -> Result<(), String> {
+        match *value {
+            Value::Null if self.document => {
+                return Err("null cannot be represented in TOML".to_string());
+            }
+            Value::Array(ref array) => {
+                self.out.push('[');
+                for (i, elem) in array.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.inline(elem)?;
+                }
+                self.out.push(']');
+            }
+            Value::Table(ref table) if table.is_empty() => self.out.push_str("{}"),
+            Value::Table(ref table) => {
+                self.out.push_str("{ ");
+                for (i, (key, value)) in table.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    write!(self.out, "{} = ", Key(key)).unwrap();
+                    self.inline(value)?;
+                }
+                self.out.push_str(" }");
+            }
+            Value::String(ref s) => write!(self.out, "{}", Literal::from(s.as_str())).unwrap(),
+            ref value => write!(self.out, "{}", value).unwrap(),
+        }
+        Ok(())
+    }
+
+    /// Writes a string as a multi-line basic string, keeping its newlines as they are.
+    fn multiline(&mut self, s: &str) {
+        let out = &mut self.out;
+        out.push_str("\"\"\"\n");
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' | '\t' => out.push(c),
+                '\\' => out.push_str("\\\\"),
+                '\r' => out.push_str("\\r"),
+                // Quotes are only escaped where they could form a closing `"""` delimiter.
+                '"' if matches!(chars.peek(), None | Some('"')) => out.push_str("\\\""),
+                c if c.is_control() => write!(out, "\\u{:04X}", c as u32).unwrap(),
+                c => out.push(c),
+            }
+        }
+        out.push_str("\"\"\"");
+    }
+
+    fn indent(&mut self, level: usize) {
+        if let Some(ref unit) = self.options.indent {
+            for _ in 0..level {
+                self.out.push_str(unit);
+            }
+        }
+    }
+}
+
+/// Decides how to lay out the member `key = value` of a table nested `depth` tables deep, unless
+/// a `style` was requested for it.
+fn layout(
+    depth: usize,
+    has_values: bool,
+    key: &str,
+    value: &Value,
+    style: Option<Style>,
+) -> Layout {
+    match style {
+        Some(Style::Inline) => return Layout::Value,
+        Some(Style::Standard) => return Layout::Table,
+        Some(Style::Dotted) => return Layout::Dotted,
+        Some(Style::ArrayOfTables) => return Layout::ArrayOfTables,
+        None => {}
+    }
+
+    match *value {
+        Value::Table(_) if depth == 0 || !has_values => Layout::Table,
+        Value::Table(ref table) => {
+            let options = Options::default();
+            let styles = Styles::default();
+            let mut inline = Writer {
+                out: String::new(),
+                options: &options,
+                styles: &styles,
+                path: Vec::new(),
+                document: true,
+            };
+            let nested = table.values().any(|v| is_table(v) || contains_table(v));
+            let fits = inline.inline(value).is_ok()
+                && Key(key).to_string().len() + 3 + inline.out.chars().count() <= MAX_INLINE_WIDTH;
+            if nested || !fits {
+                Layout::Table
+            } else {
+                Layout::Value
+            }
+        }
+        ref value if is_array_of_tables(value) => Layout::ArrayOfTables,
+        _ => Layout::Value,
+    }
+}
+
+fn is_table(value: &Value) -> bool {
+    matches!(*value, Value::Table(_))
+}
+
+fn is_array_of_tables(value: &Value) -> bool {
+    match *value {
+        Value::Array(ref array) => !array.is_empty() && array.iter().all(is_table),
+        _ => false,
+    }
+}
+
+fn contains_table(value: &Value) -> bool {
+    match *value {
+        Value::Array(ref array) => array.iter().any(|v| is_table(v) || contains_table(v)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::value;
+
+    fn reparse(toml: &str) -> Value {
+        Value::from(toml.parse::<toml::Value>().unwrap())
+    }
+
+    #[test]
+    fn values_before_tables() {
+        let input = value("{ a = { b = 1 }, c = 2, d = [{ e = 3 }, { e = 4 }] }");
+        let toml = to_string(&input).unwrap();
+        assert_eq!(toml, "c = 2\n\n[a]\nb = 1\n\n[[d]]\ne = 3\n\n[[d]]\ne = 4");
+        assert_eq!(reparse(&toml), input);
+    }
+
+    #[test]
+    fn nested_tables() {
+        let input = value(
+            "{
+                package = { name = 'tq', metadata = { docs = { rs = { all-features = true } } } },
+                dependencies = { log = '0.4', serde = { version = '1.0', features = ['derive'] } },
+                profile = { release = { lto = true } }
+            }",
+        );
+        let toml = to_string(&input).unwrap();
+        let expected = r#"[package]
+name = "tq"
+
+[package.metadata.docs.rs]
+all-features = true
+
+[dependencies]
+log = "0.4"
+serde = { version = "1.0", features = ["derive"] }
+
+[profile.release]
+lto = true"#;
+        assert_eq!(toml, expected);
+        assert_eq!(reparse(&toml), input);
+    }
+
+    #[test]
+    fn key_order() {
+        let input = value("{ package = { version = '0.1.0', name = 'tq' }, bin = [{ name = 'tq' }], b = 1, a = 2 }");
+        let toml = to_string(&input).unwrap();
+        let expected = "b = 1\na = 2\n\n[package]\nversion = \"0.1.0\"\nname = \"tq\"\n\n[[bin]]\nname = \"tq\"";
+        assert_eq!(toml, expected);
+    }
+
+    #[test]
+    fn large_tables_are_not_inline() {
+        let input = value("{ a = { x = 1, b = { c = 'a long string value', d = 'which does not fit on a single line', e = 'on one line' } } }");
+        let toml = to_string(&input).unwrap();
+        assert!(toml.contains("[a.b]"));
+        assert_eq!(reparse(&toml), input);
+    }
+
+    #[test]
+    fn keys_and_strings() {
+        let input =
+            value(r#"{ "a b" = 'x', "docs.rs" = { "x.y" = "\"quoted\"" }, c = "one\ntwo\"" }"#);
+        let toml = to_string(&input).unwrap();
+        let expected = "\"a b\" = \"x\"\nc = \"\"\"\none\ntwo\\\"\"\"\"\n\n[\"docs.rs\"]\n\"x.y\" = \"\\\"quoted\\\"\"";
+        assert_eq!(toml, expected);
+        assert_eq!(reparse(&toml), input);
+    }
+
+    #[test]
+    fn mixed_and_nested_arrays() {
+        let input = value("{ a = [1, 'two', { three = 3 }], b = [[{ c = 1 }]], e = {}, f = [] }");
+        let toml = to_string(&input).unwrap();
+        assert_eq!(
+            toml,
+            "a = [1, \"two\", { three = 3 }]\nb = [[{ c = 1 }]]\nf = []\n\n[e]"
+        );
+    }
+
+    #[test]
+    fn compact() {
+        let options = Options {
+            compact: true,
+            ..Options::default()
+        };
+        let input = value("{ a = { b = [1, 2] }, c = [{ d = 'x' }] }");
+        let toml = to_string_with(&input, &options).unwrap();
+        assert_eq!(toml, "{ a = { b = [1, 2] }, c = [{ d = \"x\" }] }");
+    }
+
+    #[test]
+    fn indented_tables_and_wrapped_arrays() {
+        let options = Options {
+            indent: Some("  ".to_string()),
+            array_wrap: Some(20),
+            ..Options::default()
+        };
+        let input =
+            value("{ a = { b = { c = [1, 2], d = ['one', 'two', 'three'] } }, e = [true] }");
+        let toml = to_string_with(&input, &options).unwrap();
+        let expected = r#"e = [true]
+
+  [a.b]
+  c = [1, 2]
+  d = [
+    "one",
+    "two",
+    "three",
+  ]"#;
+        assert_eq!(toml, expected);
+        assert_eq!(reparse(&toml), input);
+    }
+
+    #[test]
+    fn ascii_only() {
+        let options = Options {
+            ascii: true,
+            ..Options::default()
+        };
+        let input = value(r#"{ "clé" = "café 🦀" }"#);
+        let toml = to_string_with(&input, &options).unwrap();
+        assert_eq!(toml, r#""cl\u00E9" = "caf\u00E9 \U0001F980""#);
+        assert_eq!(reparse(&toml), input);
+
+        let toml = "a = 'C:\\é \"x\"' # é\nb = '''\nü'''";
+        let expected = "a = \"C:\\\\\\u00E9 \\\"x\\\"\" # é\nb = \"\"\"\n\\u00FC\"\"\"";
+        assert_eq!(to_ascii(toml), expected);
+        assert_eq!(reparse(&to_ascii(toml)), reparse(toml));
+    }
+
+    #[test]
+    fn styles() {
+        let input =
+            value("{ a = { b = { c = 1 } }, d = { e = 'x', f = { g = [1] } }, h = [{ i = 2 }] }");
+        let path = |keys: &[&str]| keys.iter().map(|&k| Value::from(k)).collect::<Vec<_>>();
+        let mut styles = Styles::default();
+        styles.set(&path(&["a", "b"]), Style::Standard);
+        styles.set(&path(&["d"]), Style::Dotted);
+        styles.set(&path(&["d", "f"]), Style::Inline);
+        styles.set(&path(&["h"]), Style::Inline);
+        let toml = to_string_styled(&input, &Options::default(), &styles).unwrap();
+        let expected = "d.e = \"x\"\nd.f = { g = [1] }\nh = [{ i = 2 }]\n\n[a.b]\nc = 1";
+        assert_eq!(toml, expected);
+        assert_eq!(reparse(&toml), input);
+
+        // Styles which do not fit are ignored.
+        let mut styles = Styles::default();
+        styles.set(&path(&["d", "e"]), Style::ArrayOfTables);
+        styles.set(&path(&["a"]), Style::Inline);
+        styles.set(&path(&["a"]), Style::Standard);
+        let toml = to_string_styled(&input, &Options::default(), &styles).unwrap();
+        assert_eq!(toml, to_string(&input).unwrap());
+    }
+
+    #[test]
+    fn null_is_an_error() {
+        assert!(to_string(&value("{ a = [null] }")).is_err());
+        assert_eq!(to_string(&value("[1, null]")), Ok("[1, null]".to_string()));
+        assert_eq!(to_string(&value("'x'")), Ok("\"x\"".to_string()));
+    }
+}
@@ -0,0 +1,250 @@
+//! Serialization of values as YAML.
+//!
+//! Tables and arrays are written in block style, with nested structures indented, and strings
+//! are only quoted if they would otherwise be read as another type. Datetimes are written as YAML
+//! timestamps. With compact output, values are written in flow style on a single line instead.
+
+use std::fmt::Write;
+
+use super::Options;
+use crate::input::yaml::resolve;
+use crate::value::Value;
+
+/// Indentation of nested structures, when no indentation is set.
+const DEFAULT_INDENT: &str = "  ";
+
+/// Serializes a value as YAML, laid out according to `options`.
+pub fn to_string_with(value: &Value, options: &Options) -> String {
+    // Tabs cannot be used for indentation in YAML.
+    let indent = match options.indent {
+        Some(ref indent) if !indent.is_empty() && !indent.contains('\t') => indent.as_str(),
+        _ => DEFAULT_INDENT,
+    };
+
+    let mut writer = Writer {
+        out: String::new(),
+        options,
+        indent,
+    };
+    if options.compact {
+        writer.flow(value)
+    } else {
+        writer.block(String::new(), value)
+    }
+    writer.out
+}
+
+struct Writer<'a> {
+    out: String,
+    options: &'a Options,
+    indent: &'a str,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes a value, whose nested lines are prefixed by `prefix`.
+    ///
+    /// Non-empty tables and arrays start on the current line, e.g. after `- `.
+    fn block(&mut self, prefix: String, value: &Value) {
+        match *value {
+            Value::Array(ref array) if !array.is_empty() => {
+                for (i, elem) in array.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push('\n');
+                        self.out.push_str(&prefix);
+                    }
+                    self.out.push_str("- ");
+                    self.block(format!("{}  ", prefix), elem);
+                }
+            }
+            Value::Table(ref table) if !table.is_empty() => {
+                for (i, (key, value)) in table.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push('\n');
+                        self.out.push_str(&prefix);
+                    }
+                    self.string(key);
+                    self.out.push(':');
+
+                    let nested = format!("{}{}", prefix, self.indent);
+                    match *value {
+                        Value::Array(ref a) if !a.is_empty() => {
+                            write!(self.out, "\n{}", nested).unwrap();
+                            self.block(nested, value);
+                        }
+                        Value::Table(ref t) if !t.is_empty() => {
+                            write!(self.out, "\n{}", nested).unwrap();
+                            self.block(nested, value);
+                        }
+                        Value::String(ref s) if is_literal(s) => {
+                            self.out.push(' ');
+                            self.literal(&nested, s);
+                        }
+                        ref value => {
+                            self.out.push(' ');
+                            self.flow(value);
+                        }
+                    }
+                }
+            }
+            Value::String(ref s) if is_literal(s) => self.literal(&prefix, s),
+            ref value => self.flow(value),
+        }
+    }
+
+    /// Writes a value on a single line, e.g. `{a: 1, b: [true]}`.
+    fn flow(&mut self, value: &Value) {
+        match *value {
+            Value::Null => self.out.push_str("null"),
+            Value::Boolean(b) => write!(self.out, "{}", b).unwrap(),
+            Value::Integer(i) => write!(self.out, "{}", i).unwrap(),
+            Value::Float(f) if f.is_nan() => self.out.push_str(".nan"),
+            Value::Float(f) if f.is_infinite() && f > 0.0 => self.out.push_str(".inf"),
+            Value::Float(f) if f.is_infinite() => self.out.push_str("-.inf"),
+            Value::Float(f) => write!(self.out, "{:?}", f).unwrap(),
+            Value::String(ref s) => self.string(s),
+            Value::Datetime(ref dt) => write!(self.out, "{}", dt).unwrap(),
+            Value::Array(ref array) => {
+                self.out.push('[');
+                for (i, elem) in array.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.flow(elem);
+                }
+                self.out.push(']');
+            }
+            Value::Table(ref table) => {
+                self.out.push('{');
+                for (i, (key, value)) in table.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.string(key);
+                    self.out.push_str(": ");
+                    self.flow(value);
+                }
+                self.out.push('}');
+            }
+        }
+    }
+
+    /// Writes a string as a plain scalar if possible, and as a double-quoted scalar otherwise.
+    fn string(&mut self, s: &str) {
+        if is_plain(s) && (s.is_ascii() || !self.options.ascii) {
+            self.out.push_str(s);
+            return;
+        }
+
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if c.is_control() || (self.options.ascii && !c.is_ascii()) => match c as u32 {
+                    code if code <= 0xFFFF => write!(self.out, "\\u{:04X}", code).unwrap(),
+                    code => write!(self.out, "\\U{:08X}", code).unwrap(),
+                },
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    /// Writes a multi-line string as a literal block scalar, whose lines are indented by `prefix`.
+    fn literal(&mut self, prefix: &str, s: &str) {
+        let content = s.strip_suffix('\n');
+        self.out
+            .push_str(if content.is_some() { "|" } else { "|-" });
+        for line in content.unwrap_or(s).split('\n') {
+            self.out.push('\n');
+            if !line.is_empty() {
+                write!(self.out, "{}{}", prefix, line).unwrap();
+            }
+        }
+    }
+}
+
+/// Returns whether `s` can be written as a plain scalar, which is read back as the same string.
+fn is_plain(s: &str) -> bool {
+    let first = match s.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+
+    resolve(s).is_none()
+        && !"-?:,[]{}#&*!|>'\"%@`".contains(first)
+        && !s.starts_with(char::is_whitespace)
+        && !s.ends_with(char::is_whitespace)
+        && !s.ends_with(':')
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.contains([',', '[', ']', '{', '}'])
+        && !s.contains(char::is_control)
+}
+
+/// Returns whether `s` is best written as a literal block scalar, keeping its line breaks.
+fn is_literal(s: &str) -> bool {
+    let content = s.strip_suffix('\n').unwrap_or(s);
+    content.contains('\n')
+        && !s.starts_with([' ', '\n'])
+        && !content.ends_with('\n')
+        && !s.contains(|c: char| c.is_control() && c != '\n')
+        && !content.split('\n').any(|line| line.ends_with(' '))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::value;
+    use crate::input::from_yaml;
+
+    #[test]
+    fn block_style() {
+        let input = value(
+            "{ name = 'tq', jobs = [{ run = ['cargo build', 'cargo test'], os = 'linux' }, []], env = {}, on = 1979-05-27 }",
+        );
+        let yaml = to_string_with(&input, &Options::default());
+        let expected = "name: tq
+jobs:
+  - run:
+      - cargo build
+      - cargo test
+    os: linux
+  - []
+env: {}
+on: 1979-05-27";
+        assert_eq!(yaml, expected);
+        assert_eq!(from_yaml(&yaml).unwrap(), vec![input]);
+    }
+
+    #[test]
+    fn quoting() {
+        let input = value(
+            r#"{ "a b" = ['true', '1.5', '', '- x', 'a: b', 'x #y', '2001-12-14', "é", 'null', "it's"] }"#,
+        );
+        let yaml = to_string_with(&input, &Options::default());
+        assert_eq!(from_yaml(&yaml).unwrap(), vec![input.clone()]);
+
+        let options = Options {
+            compact: true,
+            ascii: true,
+            ..Options::default()
+        };
+        let yaml = to_string_with(&input, &options);
+        let expected = r#"{a b: ["true", "1.5", "", "- x", "a: b", "x #y", "2001-12-14", "\u00E9", "null", it's]}"#;
+        assert_eq!(yaml, expected);
+        assert_eq!(from_yaml(&yaml).unwrap(), vec![input]);
+    }
+
+    #[test]
+    fn literal_strings() {
+        let input = value(r#"{ script = "cargo build\n\ncargo test\n", note = "a\nb" }"#);
+        let yaml = to_string_with(&input, &Options::default());
+        let expected = "script: |\n  cargo build\n\n  cargo test\nnote: |-\n  a\n  b";
+        assert_eq!(yaml, expected);
+        assert_eq!(from_yaml(&yaml).unwrap(), vec![input]);
+    }
+}
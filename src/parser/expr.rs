@@ -5,7 +5,7 @@ pub use self::function::function_decl;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::char;
-use nom::combinator::{map, opt};
+use nom::combinator::{map, not, opt};
 use nom::multi::many0;
 use nom::sequence::{delimited, pair, preceded, terminated, tuple};
 use nom::IResult;
@@ -32,7 +32,10 @@ pub fn expr(input: &str) -> IResult<&str, Expr> {
 
 fn pipe(input: &str) -> IResult<&str, Expr> {
     let pipe = pair(char('|'), tokens::space);
-    let expr = pair(chain, many0(preceded(pipe, chain)));
+    let expr = pair(
+        alt((scoped, chain)),
+        many0(preceded(pipe, alt((scoped, chain)))),
+    );
     map(expr, |(first, rest)| {
         rest.into_iter().fold(first, |lhs, rhs| {
             Expr::Binary(BinaryOp::Pipe, Box::new(lhs), Box::new(rhs))
@@ -40,9 +43,35 @@ fn pipe(input: &str) -> IResult<&str, Expr> {
     })(input)
 }
 
+/// Function declarations, variable bindings and labels, whose scope extends over the rest of the
+/// pipeline to their right.
+fn scoped(input: &str) -> IResult<&str, Expr> {
+    let fn_decl = pair(terminated(function_decl, tokens::space), pipe);
+    let fn_decl = map(fn_decl, |(decl, expr)| {
+        Expr::FnDecl(Box::new(decl), Box::new(expr))
+    });
+
+    let bind_pipe = tuple((tokens::space, char('|'), tokens::space));
+    let binding = pair(terminated(pattern::binding, bind_pipe), pipe);
+    let binding = map(binding, |(binding, expr)| {
+        Expr::Binding(Box::new(binding), Box::new(expr))
+    });
+
+    let label_pipe = pair(char('|'), tokens::space);
+    let label = pair(terminated(map(label_decl, Expr::Label), label_pipe), pipe);
+    let label = map(label, |(label, expr)| {
+        Expr::Binary(BinaryOp::Pipe, Box::new(label), Box::new(expr))
+    });
+
+    alt((fn_decl, binding, label))(input)
+}
+
 fn chain(input: &str) -> IResult<&str, Expr> {
     let comma = pair(char(','), tokens::space);
-    let expr = pair(fn_decl, many0(preceded(comma, fn_decl)));
+    let expr = pair(
+        alternative,
+        many0(preceded(comma, alt((scoped, alternative)))),
+    );
     map(expr, |(first, rest)| {
         rest.into_iter().fold(first, |lhs, rhs| {
             Expr::Binary(BinaryOp::Comma, Box::new(lhs), Box::new(rhs))
@@ -50,33 +79,12 @@ fn chain(input: &str) -> IResult<&str, Expr> {
     })(input)
 }
 
-fn fn_decl(input: &str) -> IResult<&str, Expr> {
-    let expr = pair(many0(terminated(function_decl, tokens::space)), binding);
-    map(expr, |(decls, expr)| {
-        decls.into_iter().rev().fold(expr, |expr, decl| {
-            Expr::FnDecl(Box::new(decl), Box::new(expr))
-        })
-    })(input)
-}
-
-fn binding(input: &str) -> IResult<&str, Expr> {
-    let pipe = tuple((tokens::space, char('|'), tokens::space));
-    let expr = pair(many0(terminated(pattern::binding, pipe)), label);
-    map(expr, |(bindings, expr)| {
-        bindings.into_iter().rev().fold(expr, |expr, binding| {
-            Expr::Binding(Box::new(binding), Box::new(expr))
-        })
-    })(input)
-}
-
-fn label(input: &str) -> IResult<&str, Expr> {
-    let label = map(label_decl, Expr::Label);
-    let label = terminated(label, pair(char('|'), tokens::space));
-    let expr = pair(many0(label), assign);
-    map(expr, |(decls, expr)| {
-        decls.into_iter().rev().fold(expr, |expr, label| {
-            Expr::Binary(BinaryOp::Pipe, Box::new(label), Box::new(expr))
-        })
+fn alternative(input: &str) -> IResult<&str, Expr> {
+    let op = terminated(tag("//"), not(char('=')));
+    let expr = pair(assign, opt(preceded(pair(op, tokens::space), alternative)));
+    map(expr, |(lhs, rhs)| match rhs {
+        Some(rhs) => Expr::Binary(BinaryOp::Alt, Box::new(lhs), Box::new(rhs)),
+        None => lhs,
     })(input)
 }
 
@@ -117,8 +125,8 @@ fn compare(input: &str) -> IResult<&str, Expr> {
 
     let lte = map(tag("<="), |_| BinaryOp::LessThanEq);
     let lt = map(char('<'), |_| BinaryOp::LessThan);
-    let gte = map(tag(">="), |_| BinaryOp::LessThanEq);
-    let gt = map(char('>'), |_| BinaryOp::LessThan);
+    let gte = map(tag(">="), |_| BinaryOp::GreaterThanEq);
+    let gt = map(char('>'), |_| BinaryOp::GreaterThan);
     let comparison = alt((lte, lt, gte, gt));
 
     let op = terminated(alt((equality, comparison)), tokens::space);
@@ -142,11 +150,10 @@ fn sum(input: &str) -> IResult<&str, Expr> {
 }
 
 fn product(input: &str) -> IResult<&str, Expr> {
-    let alt_ = map(tag("//"), |_| BinaryOp::Alt);
     let mul = map(char('*'), |_| BinaryOp::Mul);
-    let div = map(char('/'), |_| BinaryOp::Div);
+    let div = map(terminated(char('/'), not(char('/'))), |_| BinaryOp::Div);
     let rem = map(char('%'), |_| BinaryOp::Mod);
-    let op = terminated(alt((alt_, mul, div, rem)), tokens::space);
+    let op = terminated(alt((mul, div, rem)), tokens::space);
     let expr = pair(try_postfix, many0(pair(op, try_postfix)));
     map(expr, |(first, rest)| {
         rest.into_iter().fold(first, |lhs, (op, rhs)| {
@@ -204,3 +211,38 @@ fn term(input: &str) -> IResult<&str, Expr> {
         paren, literal, brk, empty, filter, construct, variable, fn_call,
     ))(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use nom::combinator::all_consuming;
+
+    use super::*;
+    use crate::tq_expr_and_str;
+
+    #[test]
+    fn alternative() {
+        let (expected, expr_str) = tq_expr_and_str!(.package.edition / / "2015");
+        let (_, actual) = all_consuming(expr)(&expr_str).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), r#".package.edition // "2015""#);
+
+        let (expected, expr_str) = tq_expr_and_str!(.a / / .b * 2 / / 1, 3);
+        let (_, actual) = all_consuming(expr)(&expr_str).unwrap();
+        assert_eq!(expected, actual);
+
+        let (expected, expr_str) = tq_expr_and_str!(.a = 1 / / .b |= 2);
+        let (_, actual) = all_consuming(expr)(&expr_str).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn alternative_update() {
+        let (expected, expr_str) = tq_expr_and_str!(.package.edition / /= "2015");
+        let (_, actual) = all_consuming(expr)(&expr_str).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), r#".package.edition //= "2015""#);
+
+        let (_, actual) = all_consuming(expr)(".a / 2").unwrap();
+        assert_eq!(actual.to_string(), ".a / 2");
+    }
+}
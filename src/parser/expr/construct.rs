@@ -22,7 +22,7 @@ fn array(input: &str) -> IResult<&str, Expr> {
 fn table(input: &str) -> IResult<&str, Expr> {
     let key = terminated(table_key, tokens::space);
     let value = terminated(table_value, tokens::space);
-    let member = pair(key, preceded(pair(char('='), tokens::space), value));
+    let member = pair(key, opt(preceded(pair(char('='), tokens::space), value)));
 
     let members = separated_list(pair(char(','), tokens::space), member);
     let table = delimited(pair(char('{'), tokens::space), members, char('}'));
@@ -85,4 +85,17 @@ mod tests {
         let (_, actual) = all_consuming(construct)(&string).unwrap();
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn table_shorthand() {
+        let (expected, string) = tq_expr_and_str!({ name, version });
+        let (_, actual) = all_consuming(construct)(&string).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), "{name, version}");
+
+        let (expected, string) = tq_expr_and_str!({ $x, "a b", (.k), one = 1 });
+        let (_, actual) = all_consuming(construct)(&string).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), "{$x, \"a b\", (.k), one = 1}");
+    }
 }
@@ -7,7 +7,8 @@ use nom::sequence::{pair, preceded};
 use nom::IResult;
 
 use super::index::index;
-use super::tokens::{identifier, variable};
+use super::tokens::{identifier, string, variable};
+use crate::ast::tokens::Ident;
 use crate::ast::{Expr, ExprFilter};
 
 pub fn filter(input: &str) -> IResult<&str, Expr> {
@@ -47,7 +48,8 @@ fn first_segment(input: &str) -> IResult<&str, ExprFilter> {
 }
 
 fn field(input: &str) -> IResult<&str, ExprFilter> {
-    map(preceded(char('.'), identifier), ExprFilter::Field)(input)
+    let key = alt((identifier, map(string, Ident::from)));
+    map(preceded(char('.'), key), ExprFilter::Field)(input)
 }
 
 #[cfg(test)]
@@ -111,4 +113,43 @@ mod tests {
         let (_, actual) = all_consuming(filter)(&path).unwrap();
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn quoted_path() {
+        let (expected, path) = tq_expr_and_str!(."docs.rs");
+        let (_, actual) = all_consuming(filter)(&path).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), path);
+
+        let (expected, path) = tq_expr_and_str!(.package.metadata."docs.rs".features[0]);
+        let (_, actual) = all_consuming(filter)(&path).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), path);
+
+        let (expected, path) = tq_expr_and_str!($x."a b");
+        let (_, actual) = all_consuming(filter)(&path).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), path);
+
+        let (_, literal) = all_consuming(filter)(".'serde-json'.'a \\ b'").unwrap();
+        assert_eq!(literal.to_string(), ".serde-json.\"a \\\\ b\"");
+    }
+
+    #[test]
+    fn slice_path() {
+        let (expected, path) = tq_expr_and_str!(.authors[1:]);
+        let (_, actual) = all_consuming(filter)(&path).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), ".authors[1:]");
+
+        let (expected, path) = tq_expr_and_str!(.authors[:3]);
+        let (_, actual) = all_consuming(filter)(&path).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), ".authors[:3]");
+
+        let (expected, path) = tq_expr_and_str!(.features.default[0:2]);
+        let (_, actual) = all_consuming(filter)(&path).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(actual.to_string(), ".features.default[0:2]");
+    }
 }
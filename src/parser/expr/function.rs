@@ -49,7 +49,7 @@ mod tests {
             let (expr, string) = $crate::tq_expr_and_str!($($expr)+);
             match expr {
                 $crate::ast::Expr::FnCall(call) => (call, string.trim().to_string()),
-                e => panic!(format!("tq_expr_and_str!() did not produce an `ExprFnCall`: {:?}", e)),
+                e => panic!("tq_expr_and_str!() did not produce an `ExprFnCall`: {:?}", e),
             }
         }};
     }
@@ -60,7 +60,7 @@ mod tests {
             let trimmed = string.trim().replace(" :", ":").trim_end_matches(" .").to_string();
             match expr {
                 $crate::ast::Expr::FnDecl(decl, _) => (*decl, trimmed),
-                e => panic!(format!("tq_expr_and_str!() did not produce an `ExprFnDecl`: {:?}", e)),
+                e => panic!("tq_expr_and_str!() did not produce an `ExprFnDecl`: {:?}", e),
             }
         }};
     }
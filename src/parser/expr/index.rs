@@ -15,17 +15,17 @@ pub fn index(input: &str) -> IResult<&str, ExprIndex> {
 }
 
 pub fn index_slice(input: &str) -> IResult<&str, ExprSlice> {
-    let empty_upper = tuple((left_brace, char(':'), tokens::space));
-    let lower = map(delimited(empty_upper, expr, char(']')), ExprSlice::Lower);
+    let empty_lower = tuple((left_brace, char(':'), tokens::space));
+    let upper = map(delimited(empty_lower, expr, char(']')), ExprSlice::Upper);
 
-    let opt_lower = delimited(tokens::space, opt(expr), char(']'));
-    let range = pair(preceded(left_brace, expr), preceded(char(':'), opt_lower));
-    let range_or_upper = map(range, |(upper, lower)| match lower {
-        Some(lower) => ExprSlice::Range(upper, lower),
-        None => ExprSlice::Upper(upper),
+    let opt_upper = delimited(tokens::space, opt(expr), char(']'));
+    let range = pair(preceded(left_brace, expr), preceded(char(':'), opt_upper));
+    let range_or_lower = map(range, |(lower, upper)| match upper {
+        Some(upper) => ExprSlice::Range(lower, upper),
+        None => ExprSlice::Lower(lower),
     });
 
-    alt((lower, range_or_upper))(input)
+    alt((upper, range_or_lower))(input)
 }
 
 fn left_brace(input: &str) -> IResult<&str, &str> {
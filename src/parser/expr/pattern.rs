@@ -58,7 +58,7 @@ mod tests {
             let (expr, string) = $crate::tq_expr_and_str!($($expr)+ | .);
             match expr {
                 $crate::ast::Expr::Binding(bind, _) => (*bind, string.replace(" | .", "")),
-                e => panic!(format!("tq_expr_and_str!() did not produce an `Expr::Binding`: {:?}", e)),
+                e => panic!("tq_expr_and_str!() did not produce an `Expr::Binding`: {:?}", e),
             }
         }};
     }
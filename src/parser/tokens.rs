@@ -23,7 +23,7 @@ pub fn space(input: &str) -> IResult<&str, ()> {
 }
 
 pub fn identifier(input: &str) -> IResult<&str, Ident> {
-    let first = count(alt((alpha1, is_a("_-"))), 1);
+    let first = count(alt((alpha1, is_a("_"))), 1);
     let rest = many0(alt((alphanumeric1, is_a("_-"))));
     map(recognize(pair(first, rest)), Ident::from)(input)
 }
@@ -43,10 +43,7 @@ mod tests {
             let (expr, string) = $crate::tq_expr_and_str!($literal);
             match expr {
                 $crate::ast::Expr::Literal(lit) => (lit, string),
-                e => panic!(format!(
-                    "tq_expr_and_str!() did not produce a `Literal`: {:?}",
-                    e
-                )),
+                e => panic!("tq_expr_and_str!() did not produce a `Literal`: {:?}", e),
             }
         }};
     }
@@ -80,8 +80,6 @@ fn digit_sequence(input: &str) -> IResult<&str, &str> {
 
 #[cfg(test)]
 mod tests {
-    use std::f64::{INFINITY, NAN, NEG_INFINITY};
-
     use float_cmp::{approx_eq, ApproxEq};
     use nom::combinator::all_consuming;
 
@@ -141,21 +139,21 @@ mod tests {
     #[test]
     fn float_special_literals() {
         let (_, bare_inf_literal) = all_consuming(float)("inf").expect("bare inf failed");
-        approx_eq!(f64, bare_inf_literal, INFINITY);
+        approx_eq!(f64, bare_inf_literal, f64::INFINITY);
 
         let (_, neg_inf_literal) = all_consuming(float)("-inf").expect("negative inf failed");
-        approx_eq!(f64, neg_inf_literal, NEG_INFINITY);
+        approx_eq!(f64, neg_inf_literal, f64::NEG_INFINITY);
 
         let (_, pos_inf_literal) = all_consuming(float)("+inf").expect("positive inf failed");
-        approx_eq!(f64, pos_inf_literal, INFINITY);
+        approx_eq!(f64, pos_inf_literal, f64::INFINITY);
 
         let (_, bare_nan_literal) = all_consuming(float)("nan").expect("bare nan failed");
-        approx_eq!(f64, bare_nan_literal, NAN);
+        approx_eq!(f64, bare_nan_literal, f64::NAN);
 
         let (_, neg_nan_literal) = all_consuming(float)("-nan").expect("negative nan failed");
-        approx_eq!(f64, neg_nan_literal, -NAN);
+        approx_eq!(f64, neg_nan_literal, -f64::NAN);
 
         let (_, pos_nan_literal) = all_consuming(float)("+nan").expect("positive nan failed");
-        approx_eq!(f64, pos_nan_literal, NAN);
+        approx_eq!(f64, pos_nan_literal, f64::NAN);
     }
 }
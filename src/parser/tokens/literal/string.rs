@@ -63,7 +63,7 @@ fn utf16_string(input: &str) -> IResult<&str, String> {
 
 fn utf32_string(input: &str) -> IResult<&str, String> {
     let code = preceded(tag("\\U"), verify(take_n(8), all_chars_hexdigit));
-    let utf32_char = map_res(code, |digits| u32::from_str_radix(&digits, 16));
+    let utf32_char = map_res(code, |digits| u32::from_str_radix(digits, 16));
     let decode = |c| char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER);
     map(many1(map(utf32_char, decode)), String::from_iter)(input)
 }
@@ -0,0 +1,224 @@
+//! Values produced and consumed by `tq` filters.
+
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use indexmap::IndexMap;
+use toml::value::Datetime;
+
+use crate::ast::tokens::Literal;
+
+pub mod ops;
+pub mod path;
+pub mod stream;
+
+/// A table of key/value pairs, which keeps its keys in insertion order.
+pub type Table = IndexMap<String, Value>;
+
+/// A value flowing through a `tq` filter.
+///
+/// This is a superset of `toml::Value` with an additional `Null` variant, which is produced by
+/// missing keys and out-of-bounds indices and cannot be represented in a TOML document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Datetime(Datetime),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    /// Returns the name of this value's type, as reported by the `type` builtin.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Null => "null",
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) | Value::Float(_) => "number",
+            Value::String(_) => "string",
+            Value::Datetime(_) => "datetime",
+            Value::Array(_) => "array",
+            Value::Table(_) => "object",
+        }
+    }
+
+    /// Returns `false` if this value is `null` or `false`, and `true` otherwise.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(*self, Value::Null | Value::Boolean(false))
+    }
+
+    /// Sorts the keys of this value's tables, and of the tables nested within them.
+    pub fn sort_keys(&mut self) {
+        match *self {
+            Value::Array(ref mut array) => array.iter_mut().for_each(Value::sort_keys),
+            Value::Table(ref mut table) => {
+                table.sort_keys();
+                table.values_mut().for_each(Value::sort_keys);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns this value as a 64-bit float, if it is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Integer(i) => Some(i as f64),
+            Value::Float(f) => Some(f),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(boolean: bool) -> Self {
+        Value::Boolean(boolean)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(int: i64) -> Self {
+        Value::Integer(int)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(float: f64) -> Self {
+        Value::Float(float)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Self {
+        Value::String(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<Datetime> for Value {
+    fn from(datetime: Datetime) -> Self {
+        Value::Datetime(datetime)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(array: Vec<Value>) -> Self {
+        Value::Array(array)
+    }
+}
+
+impl From<Table> for Value {
+    fn from(table: Table) -> Self {
+        Value::Table(table)
+    }
+}
+
+impl From<Literal> for Value {
+    fn from(literal: Literal) -> Self {
+        match literal {
+            Literal::Boolean(b) => Value::Boolean(b),
+            Literal::Datetime(dt) => Value::Datetime(dt),
+            Literal::Float(f) => Value::Float(f),
+            Literal::Integer(i) => Value::Integer(i),
+            Literal::String(s) => Value::String(s),
+        }
+    }
+}
+
+impl From<toml::Value> for Value {
+    fn from(value: toml::Value) -> Self {
+        match value {
+            toml::Value::Boolean(b) => Value::Boolean(b),
+            toml::Value::Datetime(dt) => Value::Datetime(dt),
+            toml::Value::Float(f) => Value::Float(f),
+            toml::Value::Integer(i) => Value::Integer(i),
+            toml::Value::String(s) => Value::String(s),
+            toml::Value::Array(a) => Value::Array(a.into_iter().map(Value::from).collect()),
+            toml::Value::Table(t) => {
+                Value::Table(t.into_iter().map(|(k, v)| (k, Value::from(v))).collect())
+            }
+        }
+    }
+}
+
+impl TryFrom<Value> for toml::Value {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Null => Err("null cannot be represented in TOML".to_string()),
+            Value::Boolean(b) => Ok(toml::Value::Boolean(b)),
+            Value::Datetime(dt) => Ok(toml::Value::Datetime(dt)),
+            Value::Float(f) => Ok(toml::Value::Float(f)),
+            Value::Integer(i) => Ok(toml::Value::Integer(i)),
+            Value::String(s) => Ok(toml::Value::String(s)),
+            Value::Array(a) => a
+                .into_iter()
+                .map(TryFrom::try_from)
+                .collect::<Result<_, _>>()
+                .map(toml::Value::Array),
+            Value::Table(t) => t
+                .into_iter()
+                .map(|(k, v)| TryFrom::try_from(v).map(|v| (k, v)))
+                .collect::<Result<_, _>>()
+                .map(toml::Value::Table),
+        }
+    }
+}
+
+/// Formats the value as an inline TOML value, e.g. `{ a = 1, b = [true] }`.
+///
+/// Since `null` has no TOML representation, it is written as the bare word `null`.
+impl Display for Value {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            Value::Null => fmt.write_str("null"),
+            Value::Boolean(b) => write!(fmt, "{}", b),
+            Value::Integer(i) => write!(fmt, "{}", i),
+            Value::Float(f) if f.is_nan() => fmt.write_str("nan"),
+            Value::Float(f) if f.is_infinite() && f > 0.0 => fmt.write_str("inf"),
+            Value::Float(f) if f.is_infinite() => fmt.write_str("-inf"),
+            Value::Float(f) => write!(fmt, "{:?}", f),
+            Value::String(ref s) => write!(fmt, "{}", Literal::from(s.as_str())),
+            Value::Datetime(ref dt) => write!(fmt, "{}", dt),
+            Value::Array(ref array) => {
+                let elems: Vec<_> = array.iter().map(ToString::to_string).collect();
+                write!(fmt, "[{}]", elems.join(", "))
+            }
+            Value::Table(ref table) if table.is_empty() => fmt.write_str("{}"),
+            Value::Table(ref table) => {
+                let members: Vec<_> = table
+                    .iter()
+                    .map(|(k, v)| format!("{} = {}", Key(k), v))
+                    .collect();
+                write!(fmt, "{{ {} }}", members.join(", "))
+            }
+        }
+    }
+}
+
+/// A table key, which is quoted if it cannot be written as a bare key.
+pub(crate) struct Key<'a>(pub &'a str);
+
+impl<'a> Display for Key<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let is_bare = !self.0.is_empty()
+            && self
+                .0
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+        if is_bare {
+            fmt.write_str(self.0)
+        } else {
+            write!(fmt, "{}", Literal::from(self.0))
+        }
+    }
+}
@@ -0,0 +1,637 @@
+//! Semantics of the unary and binary operators over `tq` values.
+//!
+//! These rules are shared by everything that needs to apply an operator to concrete values, e.g.
+//! the evaluator and constant folding. Operators which control the flow of evaluation rather than
+//! combine two values (`,` and `|`) are not handled here.
+//!
+//! | Operator | Left operand | Right operand | Result                                           |
+//! |----------|--------------|---------------|--------------------------------------------------|
+//! | `+`      | `null`       | any           | the right operand                                |
+//! | `+`      | any          | `null`        | the left operand                                 |
+//! | `+`      | number       | number        | sum                                              |
+//! | `+`      | string       | string        | concatenation                                    |
+//! | `+`      | array        | array         | concatenation                                    |
+//! | `+`      | table        | table         | shallow merge, keys on the right win             |
+//! | `-`      | number       | number        | difference                                       |
+//! | `-`      | array        | array         | left elements not present in the right           |
+//! | `-`      | datetime     | datetime      | duration in seconds, if both are the same kind   |
+//! | `*`      | number       | number        | product                                          |
+//! | `*`      | string       | number        | string repeated `n` times, `null` if `n < 1`     |
+//! | `*`      | number       | string        | string repeated `n` times, `null` if `n < 1`     |
+//! | `*`      | table        | table         | recursive merge, keys on the right win           |
+//! | `/`      | number       | number        | quotient, error if the divisor is zero           |
+//! | `/`      | string       | string        | left split on every occurrence of the right      |
+//! | `%`      | number       | number        | remainder after truncating both to integers      |
+//! | `==` ... | any          | any           | comparison according to [`compare`]              |
+//! | `and`    | any          | any           | `true` if both operands are truthy               |
+//! | `or`     | any          | any           | `true` if either operand is truthy               |
+//! | `//`     | any          | any           | the left operand if truthy, else the right       |
+//!
+//! Arithmetic on two integers produces an integer as long as the result is representable as one,
+//! and falls back to a float otherwise. Arithmetic involving a float always produces a float.
+//! Every other combination of operands is an error.
+//!
+//! [`compare`]: ./fn.compare.html
+
+use std::cmp::Ordering;
+
+use super::{Table, Value};
+use crate::ast::{BinaryOp, UnaryOp};
+
+/// The longest string, in bytes, which repeating a string may produce.
+const MAX_REPEAT_LEN: usize = 1 << 30;
+
+/// Applies a unary operator to a value.
+///
+/// `-` negates a number and `!` returns whether the value is falsy.
+pub fn unary(op: &UnaryOp, value: Value) -> Result<Value, String> {
+    match (op, value) {
+        (UnaryOp::Neg, Value::Integer(i)) => Ok(i
+            .checked_neg()
+            .map(Value::Integer)
+            .unwrap_or_else(|| Value::Float(-(i as f64)))),
+        (UnaryOp::Neg, Value::Float(f)) => Ok(Value::Float(-f)),
+        (UnaryOp::Neg, value) => Err(format!("{} cannot be negated", describe(&value))),
+        (UnaryOp::Not, value) => Ok(Value::Boolean(!value.is_truthy())),
+    }
+}
+
+/// Applies a binary operator to two values, following the rules in the module documentation.
+pub fn binary(op: &BinaryOp, lhs: Value, rhs: Value) -> Result<Value, String> {
+    match *op {
+        BinaryOp::Add => add(lhs, rhs),
+        BinaryOp::Sub => sub(lhs, rhs),
+        BinaryOp::Mul => mul(lhs, rhs),
+        BinaryOp::Div => div(lhs, rhs),
+        BinaryOp::Mod => rem(lhs, rhs),
+        BinaryOp::Eq => Ok(Value::Boolean(equals(&lhs, &rhs))),
+        BinaryOp::NotEq => Ok(Value::Boolean(!equals(&lhs, &rhs))),
+        BinaryOp::LessThan => Ok(Value::Boolean(compare(&lhs, &rhs) == Ordering::Less)),
+        BinaryOp::LessThanEq => Ok(Value::Boolean(compare(&lhs, &rhs) != Ordering::Greater)),
+        BinaryOp::GreaterThan => Ok(Value::Boolean(compare(&lhs, &rhs) == Ordering::Greater)),
+        BinaryOp::GreaterThanEq => Ok(Value::Boolean(compare(&lhs, &rhs) != Ordering::Less)),
+        BinaryOp::And => Ok(Value::Boolean(lhs.is_truthy() && rhs.is_truthy())),
+        BinaryOp::Or => Ok(Value::Boolean(lhs.is_truthy() || rhs.is_truthy())),
+        BinaryOp::Alt if lhs.is_truthy() => Ok(lhs),
+        BinaryOp::Alt => Ok(rhs),
+        BinaryOp::Comma | BinaryOp::Pipe => Err(format!("`{}` is not a value operator", op)),
+    }
+}
+
+/// Returns whether two values are equal.
+///
+/// Integers and floats are equal if they have the same numeric value. `nan` is never equal to
+/// anything, including itself.
+pub fn equals(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (Value::Float(f), _) | (_, Value::Float(f)) if f.is_nan() => false,
+        (lhs, rhs) => compare(lhs, rhs) == Ordering::Equal,
+    }
+}
+
+/// Defines a total order over all values.
+///
+/// Values of different types are ordered as follows:
+///
+/// `null` < `false` < `true` < numbers < datetimes < strings < arrays < tables
+///
+/// Numbers are compared numerically, with `nan` ordered before all other numbers. Datetimes are
+/// compared chronologically. Strings are compared by codepoint and arrays lexicographically.
+/// Tables are compared first by their sorted sets of keys, then by their values key by key.
+pub fn compare(lhs: &Value, rhs: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match *value {
+            Value::Null => 0,
+            Value::Boolean(false) => 1,
+            Value::Boolean(true) => 2,
+            Value::Integer(_) | Value::Float(_) => 3,
+            Value::Datetime(_) => 4,
+            Value::String(_) => 5,
+            Value::Array(_) => 6,
+            Value::Table(_) => 7,
+        }
+    }
+
+    match (lhs, rhs) {
+        (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+        (Value::Integer(_), Value::Float(_))
+        | (Value::Float(_), Value::Integer(_))
+        | (Value::Float(_), Value::Float(_)) => {
+            let (a, b) = (lhs.as_f64().unwrap(), rhs.as_f64().unwrap());
+            match (a.is_nan(), b.is_nan()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            }
+        }
+        (Value::Datetime(a), Value::Datetime(b)) => {
+            let (a, b) = (a.to_string(), b.to_string());
+            match (Moment::parse(&a), Moment::parse(&b)) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                _ => a.cmp(&b),
+            }
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => {
+            let elems = a.iter().zip(b.iter()).map(|(x, y)| compare(x, y));
+            let first_unequal = elems.into_iter().find(|o| *o != Ordering::Equal);
+            first_unequal.unwrap_or_else(|| a.len().cmp(&b.len()))
+        }
+        (Value::Table(a), Value::Table(b)) => {
+            let mut keys_a: Vec<_> = a.keys().collect();
+            let mut keys_b: Vec<_> = b.keys().collect();
+            keys_a.sort();
+            keys_b.sort();
+            keys_a.cmp(&keys_b).then_with(|| {
+                let values = keys_a.iter().map(|k| compare(&a[*k], &b[*k]));
+                let first_unequal = values.into_iter().find(|o| *o != Ordering::Equal);
+                first_unequal.unwrap_or(Ordering::Equal)
+            })
+        }
+        (lhs, rhs) => rank(lhs).cmp(&rank(rhs)),
+    }
+}
+
+fn add(lhs: Value, rhs: Value) -> Result<Value, String> {
+    match (lhs, rhs) {
+        (Value::Null, value) | (value, Value::Null) => Ok(value),
+        (Value::Integer(a), Value::Integer(b)) => {
+            Ok(integer_or_float(a.checked_add(b), || a as f64 + b as f64))
+        }
+        (Value::String(mut a), Value::String(b)) => {
+            a.push_str(&b);
+            Ok(Value::String(a))
+        }
+        (Value::Array(mut a), Value::Array(b)) => {
+            a.extend(b);
+            Ok(Value::Array(a))
+        }
+        (Value::Table(mut a), Value::Table(b)) => {
+            a.extend(b);
+            Ok(Value::Table(a))
+        }
+        (lhs, rhs) => match (lhs.as_f64(), rhs.as_f64()) {
+            (Some(a), Some(b)) => Ok(Value::Float(a + b)),
+            _ => Err(mismatch(&lhs, &rhs, "added")),
+        },
+    }
+}
+
+fn sub(lhs: Value, rhs: Value) -> Result<Value, String> {
+    match (lhs, rhs) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            Ok(integer_or_float(a.checked_sub(b), || a as f64 - b as f64))
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            let diff = a.into_iter().filter(|x| !b.iter().any(|y| equals(x, y)));
+            Ok(Value::Array(diff.collect()))
+        }
+        (Value::Datetime(a), Value::Datetime(b)) => {
+            let (a_str, b_str) = (a.to_string(), b.to_string());
+            match (Moment::parse(&a_str), Moment::parse(&b_str)) {
+                (Some(x), Some(y)) if x.kind == y.kind => Ok(duration(x.nanos - y.nanos)),
+                (Some(_), Some(_)) => Err(format!(
+                    "{} and {} cannot be subtracted because they are not the same kind of datetime",
+                    describe(&Value::Datetime(a)),
+                    describe(&Value::Datetime(b))
+                )),
+                _ => Err(mismatch(
+                    &Value::Datetime(a),
+                    &Value::Datetime(b),
+                    "subtracted",
+                )),
+            }
+        }
+        (lhs, rhs) => match (lhs.as_f64(), rhs.as_f64()) {
+            (Some(a), Some(b)) => Ok(Value::Float(a - b)),
+            _ => Err(mismatch(&lhs, &rhs, "subtracted")),
+        },
+    }
+}
+
+fn mul(lhs: Value, rhs: Value) -> Result<Value, String> {
+    match (lhs, rhs) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            Ok(integer_or_float(a.checked_mul(b), || a as f64 * b as f64))
+        }
+        (Value::String(s), n @ Value::Integer(_))
+        | (Value::String(s), n @ Value::Float(_))
+        | (n @ Value::Integer(_), Value::String(s))
+        | (n @ Value::Float(_), Value::String(s)) => repeat(s, &n),
+        (Value::Table(mut a), Value::Table(b)) => {
+            merge_deep(&mut a, b);
+            Ok(Value::Table(a))
+        }
+        (lhs, rhs) => match (lhs.as_f64(), rhs.as_f64()) {
+            (Some(a), Some(b)) => Ok(Value::Float(a * b)),
+            _ => Err(mismatch(&lhs, &rhs, "multiplied")),
+        },
+    }
+}
+
+fn div(lhs: Value, rhs: Value) -> Result<Value, String> {
+    match (lhs, rhs) {
+        (Value::String(a), Value::String(b)) => {
+            let parts: Vec<_> = if a.is_empty() {
+                Vec::new()
+            } else if b.is_empty() {
+                a.chars().map(|c| Value::String(c.to_string())).collect()
+            } else {
+                a.split(b.as_str()).map(Value::from).collect()
+            };
+            Ok(Value::Array(parts))
+        }
+        (ref lhs, ref rhs) if rhs.as_f64() == Some(0.0) && lhs.as_f64().is_some() => Err(format!(
+            "{} and {} cannot be divided because the divisor is zero",
+            describe(lhs),
+            describe(rhs)
+        )),
+        (Value::Integer(a), Value::Integer(b)) if a.checked_rem(b) == Some(0) => {
+            Ok(Value::Integer(a / b))
+        }
+        (lhs, rhs) => match (lhs.as_f64(), rhs.as_f64()) {
+            (Some(a), Some(b)) => Ok(Value::Float(a / b)),
+            _ => Err(mismatch(&lhs, &rhs, "divided")),
+        },
+    }
+}
+
+fn rem(lhs: Value, rhs: Value) -> Result<Value, String> {
+    match (lhs.as_f64(), rhs.as_f64()) {
+        (Some(_), Some(_)) => {
+            let truncate = |v: &Value| match *v {
+                Value::Integer(i) => i,
+                Value::Float(f) => f as i64,
+                _ => unreachable!(),
+            };
+
+            match (truncate(&lhs), truncate(&rhs)) {
+                (_, 0) => Err(format!(
+                    "{} and {} cannot be divided because the divisor is zero",
+                    describe(&lhs),
+                    describe(&rhs)
+                )),
+                (a, b) => Ok(Value::Integer(a.wrapping_rem(b))),
+            }
+        }
+        _ => Err(mismatch(&lhs, &rhs, "divided")),
+    }
+}
+
+fn integer_or_float<F: FnOnce() -> f64>(int: Option<i64>, float: F) -> Value {
+    int.map(Value::Integer)
+        .unwrap_or_else(|| Value::Float(float()))
+}
+
+fn repeat(s: String, count: &Value) -> Result<Value, String> {
+    let n = match *count {
+        Value::Integer(i) => i,
+        Value::Float(f) if f.is_nan() => 0,
+        Value::Float(f) => f as i64,
+        _ => unreachable!(),
+    };
+
+    if n < 1 {
+        return Ok(Value::Null);
+    }
+
+    match s.len().checked_mul(n as usize) {
+        Some(len) if len <= MAX_REPEAT_LEN => Ok(Value::String(s.repeat(n as usize))),
+        _ => Err(format!(
+            "{} cannot be repeated {} times",
+            describe(&Value::String(s)),
+            n
+        )),
+    }
+}
+
+fn merge_deep(lhs: &mut Table, rhs: Table) {
+    for (key, value) in rhs {
+        match value {
+            Value::Table(src) => match lhs.get_mut(&key) {
+                Some(Value::Table(dst)) => merge_deep(dst, src),
+                _ => {
+                    lhs.insert(key, Value::Table(src));
+                }
+            },
+            value => {
+                lhs.insert(key, value);
+            }
+        }
+    }
+}
+
+fn duration(nanos: i128) -> Value {
+    if nanos % 1_000_000_000 == 0 {
+        Value::Integer((nanos / 1_000_000_000) as i64)
+    } else {
+        Value::Float(nanos as f64 / 1e9)
+    }
+}
+
+fn mismatch(lhs: &Value, rhs: &Value, verb: &str) -> String {
+    format!("{} and {} cannot be {}", describe(lhs), describe(rhs), verb)
+}
+
+/// Describes a value for use in an error message, e.g. `string ("hello")`.
+pub(crate) fn describe(value: &Value) -> String {
+    const MAX_LEN: usize = 11;
+
+    let mut text = value.to_string();
+    if text.chars().count() > MAX_LEN {
+        text = text.chars().take(MAX_LEN - 1).collect();
+        text.push_str("...");
+    }
+
+    format!("{} ({})", value.type_name(), text)
+}
+
+/// A datetime reduced to a comparable point in time.
+///
+/// Since TOML has four distinct kinds of datetime (offset datetimes, local datetimes, local dates
+/// and local times), only moments of the same kind can be meaningfully subtracted. Offset
+/// datetimes are normalized to UTC.
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct Moment {
+    kind: u8,
+    nanos: i128,
+}
+
+impl Moment {
+    const OFFSET_DATETIME: u8 = 0;
+    const LOCAL_DATETIME: u8 = 1;
+    const LOCAL_DATE: u8 = 2;
+    const LOCAL_TIME: u8 = 3;
+
+    fn parse(s: &str) -> Option<Self> {
+        fn number(s: &str, range: std::ops::Range<usize>) -> Option<i128> {
+            s.get(range)
+                .filter(|d| d.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|d| d.parse().ok())
+        }
+
+        let (date, rest) = if s.len() >= 10 && s.as_bytes()[4] == b'-' {
+            let (y, m, d) = (number(s, 0..4)?, number(s, 5..7)?, number(s, 8..10)?);
+            (
+                Some(days_from_civil(y, m, d)),
+                s[10..].trim_start_matches(|c| "Tt ".contains(c)),
+            )
+        } else {
+            (None, s)
+        };
+
+        if rest.is_empty() {
+            let days = date?;
+            let nanos = days * 86_400 * 1_000_000_000;
+            return Some(Moment {
+                kind: Moment::LOCAL_DATE,
+                nanos,
+            });
+        }
+
+        let (h, m, sec) = (
+            number(rest, 0..2)?,
+            number(rest, 3..5)?,
+            number(rest, 6..8)?,
+        );
+        let rest = &rest[8..];
+        let frac_len = match rest.strip_prefix('.') {
+            Some(frac) => frac.bytes().take_while(u8::is_ascii_digit).count(),
+            None => 0,
+        };
+        let frac = if frac_len > 0 {
+            let digits = &rest[1..=frac_len.min(9)];
+            number(digits, 0..digits.len())? * 10i128.pow(9 - digits.len() as u32)
+        } else {
+            0
+        };
+        let offset = &rest[if frac_len > 0 { frac_len + 1 } else { 0 }..];
+
+        let time = ((h * 60 + m) * 60 + sec) * 1_000_000_000 + frac;
+        let date = match date {
+            Some(days) => days * 86_400 * 1_000_000_000,
+            None => {
+                return Some(Moment {
+                    kind: Moment::LOCAL_TIME,
+                    nanos: time,
+                })
+            }
+        };
+
+        if offset.is_empty() {
+            let nanos = date + time;
+            Some(Moment {
+                kind: Moment::LOCAL_DATETIME,
+                nanos,
+            })
+        } else if offset == "Z" || offset == "z" {
+            let nanos = date + time;
+            Some(Moment {
+                kind: Moment::OFFSET_DATETIME,
+                nanos,
+            })
+        } else {
+            let sign = match offset.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let minutes = number(offset, 1..3)? * 60 + number(offset, 4..6)?;
+            let nanos = date + time - sign * minutes * 60 * 1_000_000_000;
+            Some(Moment {
+                kind: Moment::OFFSET_DATETIME,
+                nanos,
+            })
+        }
+    }
+}
+
+/// Returns the number of days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i128, month: i128, day: i128) -> i128 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(members: &[(&str, Value)]) -> Value {
+        let table = members.iter().map(|(k, v)| (k.to_string(), v.clone()));
+        Value::Table(table.collect())
+    }
+
+    fn datetime(s: &str) -> Value {
+        Value::Datetime(s.parse().unwrap())
+    }
+
+    #[test]
+    fn add() {
+        let sum = binary(&BinaryOp::Add, 1.into(), 2.into());
+        assert_eq!(sum, Ok(Value::Integer(3)));
+
+        let sum = binary(&BinaryOp::Add, 1.into(), 2.5.into());
+        assert_eq!(sum, Ok(Value::Float(3.5)));
+
+        let overflow = binary(&BinaryOp::Add, i64::MAX.into(), 1.into());
+        assert_eq!(overflow, Ok(Value::Float(i64::MAX as f64 + 1.0)));
+
+        let concat = binary(&BinaryOp::Add, "foo".into(), "bar".into());
+        assert_eq!(concat, Ok(Value::from("foobar")));
+
+        let identity = binary(&BinaryOp::Add, Value::Null, "foo".into());
+        assert_eq!(identity, Ok(Value::from("foo")));
+        let identity = binary(&BinaryOp::Add, "foo".into(), Value::Null);
+        assert_eq!(identity, Ok(Value::from("foo")));
+
+        let lhs = table(&[("a", 1.into()), ("b", table(&[("c", 2.into())]))]);
+        let rhs = table(&[("b", table(&[("d", 3.into())]))]);
+        let merged = table(&[("a", 1.into()), ("b", table(&[("d", 3.into())]))]);
+        assert_eq!(binary(&BinaryOp::Add, lhs, rhs), Ok(merged));
+
+        let error = binary(&BinaryOp::Add, "foo".into(), 1.into()).unwrap_err();
+        assert_eq!(error, "string (\"foo\") and number (1) cannot be added");
+    }
+
+    #[test]
+    fn sub() {
+        let diff = binary(&BinaryOp::Sub, 1.into(), 2.into());
+        assert_eq!(diff, Ok(Value::Integer(-1)));
+
+        let lhs = Value::Array(vec![1.into(), 2.into(), 1.0.into(), 3.into()]);
+        let rhs = Value::Array(vec![1.into()]);
+        let diff = binary(&BinaryOp::Sub, lhs, rhs);
+        assert_eq!(diff, Ok(Value::Array(vec![2.into(), 3.into()])));
+
+        let lhs = datetime("1979-05-28T00:32:00-07:00");
+        let rhs = datetime("1979-05-27T07:32:00Z");
+        assert_eq!(binary(&BinaryOp::Sub, lhs, rhs), Ok(Value::Integer(86_400)));
+
+        let lhs = datetime("07:32:00.5");
+        let rhs = datetime("07:32:00");
+        assert_eq!(binary(&BinaryOp::Sub, lhs, rhs), Ok(Value::Float(0.5)));
+
+        let lhs = datetime("2000-03-01");
+        let rhs = datetime("2000-02-28");
+        assert_eq!(
+            binary(&BinaryOp::Sub, lhs, rhs),
+            Ok(Value::Integer(2 * 86_400))
+        );
+
+        let lhs = datetime("2000-03-01");
+        let rhs = datetime("07:32:00");
+        assert!(binary(&BinaryOp::Sub, lhs, rhs).is_err());
+
+        assert!(binary(&BinaryOp::Sub, "foo".into(), "o".into()).is_err());
+    }
+
+    #[test]
+    fn mul() {
+        let product = binary(&BinaryOp::Mul, 3.into(), 4.into());
+        assert_eq!(product, Ok(Value::Integer(12)));
+
+        let repeated = binary(&BinaryOp::Mul, "ab".into(), 3.into());
+        assert_eq!(repeated, Ok(Value::from("ababab")));
+        let repeated = binary(&BinaryOp::Mul, 2.into(), "ab".into());
+        assert_eq!(repeated, Ok(Value::from("abab")));
+        let repeated = binary(&BinaryOp::Mul, "ab".into(), 0.into());
+        assert_eq!(repeated, Ok(Value::Null));
+        assert!(binary(&BinaryOp::Mul, "a".into(), 1e18.into()).is_err());
+        assert!(binary(&BinaryOp::Mul, "ab".into(), i64::MAX.into()).is_err());
+
+        let lhs = table(&[("a", 1.into()), ("b", table(&[("c", 2.into())]))]);
+        let rhs = table(&[("b", table(&[("d", 3.into())]))]);
+        let nested = table(&[("c", 2.into()), ("d", 3.into())]);
+        let merged = table(&[("a", 1.into()), ("b", nested)]);
+        assert_eq!(binary(&BinaryOp::Mul, lhs, rhs), Ok(merged));
+
+        assert!(binary(&BinaryOp::Mul, "ab".into(), "cd".into()).is_err());
+    }
+
+    #[test]
+    fn div() {
+        let quotient = binary(&BinaryOp::Div, 12.into(), 4.into());
+        assert_eq!(quotient, Ok(Value::Integer(3)));
+        let quotient = binary(&BinaryOp::Div, 7.into(), 2.into());
+        assert_eq!(quotient, Ok(Value::Float(3.5)));
+
+        let error = binary(&BinaryOp::Div, 1.into(), 0.into()).unwrap_err();
+        assert_eq!(
+            error,
+            "number (1) and number (0) cannot be divided because the divisor is zero"
+        );
+
+        let split = binary(&BinaryOp::Div, "a, b, c".into(), ", ".into());
+        let parts = vec!["a".into(), "b".into(), "c".into()];
+        assert_eq!(split, Ok(Value::Array(parts)));
+        let split = binary(&BinaryOp::Div, "".into(), ",".into());
+        assert_eq!(split, Ok(Value::Array(Vec::new())));
+    }
+
+    #[test]
+    fn rem() {
+        assert_eq!(
+            binary(&BinaryOp::Mod, 7.into(), 3.into()),
+            Ok(Value::Integer(1))
+        );
+        assert_eq!(
+            binary(&BinaryOp::Mod, (-7).into(), 3.into()),
+            Ok(Value::Integer(-1))
+        );
+        assert_eq!(
+            binary(&BinaryOp::Mod, 7.5.into(), 2.into()),
+            Ok(Value::Integer(1))
+        );
+        assert!(binary(&BinaryOp::Mod, 7.into(), 0.5.into()).is_err());
+    }
+
+    #[test]
+    fn ordering() {
+        let ascending = vec![
+            Value::Null,
+            false.into(),
+            true.into(),
+            f64::NAN.into(),
+            (-1).into(),
+            1.5.into(),
+            2.into(),
+            datetime("1979-05-27T07:32:00Z"),
+            datetime("1979-05-27T00:33:00-07:00"),
+            "a".into(),
+            "b".into(),
+            Value::Array(vec![]),
+            Value::Array(vec![1.into()]),
+            table(&[("a", 2.into())]),
+            table(&[("a", 1.into()), ("b", 1.into())]),
+        ];
+
+        for pair in ascending.windows(2) {
+            assert_eq!(compare(&pair[0], &pair[1]), Ordering::Less, "{:?}", pair);
+            assert_eq!(compare(&pair[1], &pair[0]), Ordering::Greater, "{:?}", pair);
+        }
+
+        assert!(equals(&1.into(), &1.0.into()));
+        assert!(!equals(&f64::NAN.into(), &f64::NAN.into()));
+
+        let less = binary(&BinaryOp::LessThan, "a".into(), 1.into());
+        assert_eq!(less, Ok(Value::Boolean(false)));
+    }
+
+    #[test]
+    fn logical() {
+        assert_eq!(unary(&UnaryOp::Not, Value::Null), Ok(Value::Boolean(true)));
+        assert_eq!(unary(&UnaryOp::Neg, 1.into()), Ok(Value::Integer(-1)));
+        assert!(unary(&UnaryOp::Neg, "a".into()).is_err());
+
+        let alt = binary(&BinaryOp::Alt, false.into(), "fallback".into());
+        assert_eq!(alt, Ok(Value::from("fallback")));
+        let and = binary(&BinaryOp::And, 1.into(), Value::Null);
+        assert_eq!(and, Ok(Value::Boolean(false)));
+        assert!(binary(&BinaryOp::Pipe, 1.into(), 2.into()).is_err());
+    }
+}
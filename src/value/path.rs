@@ -0,0 +1,340 @@
+//! Indexing, slicing and path manipulation over `tq` values.
+//!
+//! A path is a sequence of components, each of which is either a string key into a table, an
+//! integer index into an array, or a slice table of the form `{ start = n, end = m }` where either
+//! bound may be `null`. These are the same paths produced by `path(f)` and consumed by `getpath`,
+//! `setpath` and `delpaths`.
+
+use std::cmp::Ordering;
+
+use super::ops::{self, describe};
+use super::{Table, Value};
+
+/// Indexes into a value with a key, as in `.[key]`.
+///
+/// Missing keys and out-of-bounds indices produce `null`, as does indexing into `null` itself.
+pub fn index(value: &Value, key: &Value) -> Result<Value, String> {
+    match (value, key) {
+        (Value::Table(ref table), Value::String(ref k)) => {
+            Ok(table.get(k).cloned().unwrap_or(Value::Null))
+        }
+        (Value::Array(ref array), Value::Integer(_))
+        | (Value::Array(ref array), Value::Float(_)) => {
+            let i = key.as_f64().unwrap().floor();
+            let i = if i < 0.0 { i + array.len() as f64 } else { i };
+            if i < 0.0 || i >= array.len() as f64 {
+                Ok(Value::Null)
+            } else {
+                Ok(array[i as usize].clone())
+            }
+        }
+        (Value::Array(ref array), Value::Array(ref needle)) => Ok(indices(array, needle)),
+        (Value::Null, Value::String(_))
+        | (Value::Null, Value::Integer(_))
+        | (Value::Null, Value::Float(_))
+        | (Value::Null, Value::Null) => Ok(Value::Null),
+        (_, Value::Table(ref bounds)) => {
+            let (start, end) = slice_component(bounds);
+            slice(value, &start, &end)
+        }
+        (value, key) => Err(cannot_index(value, key)),
+    }
+}
+
+/// Slices an array or string, as in `.[start:end]`.
+///
+/// Strings are sliced by Unicode codepoint. A `null` bound stands for the beginning or end of the
+/// value, negative bounds count from the end, and fractional bounds are widened to the nearest
+/// integers, so the start is rounded down and the end rounded up. Slicing `null` produces `null`.
+pub fn slice(value: &Value, start: &Value, end: &Value) -> Result<Value, String> {
+    match *value {
+        Value::Null => Ok(Value::Null),
+        Value::Array(ref array) => {
+            let (start, end) = slice_bounds(array.len(), start, end)?;
+            Ok(Value::Array(array[start..end].to_vec()))
+        }
+        Value::String(ref s) => {
+            let (start, end) = slice_bounds(s.chars().count(), start, end)?;
+            Ok(Value::String(
+                s.chars().skip(start).take(end - start).collect(),
+            ))
+        }
+        ref value => Err(format!("Cannot index {} with object", value.type_name())),
+    }
+}
+
+/// Returns the keys and children of an array or table, as iterated over by `.[]`.
+pub fn entries(value: &Value) -> Result<Vec<(Value, Value)>, String> {
+    match *value {
+        Value::Array(ref array) => Ok(array
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (Value::Integer(i as i64), v.clone()))
+            .collect()),
+        Value::Table(ref table) => Ok(table
+            .iter()
+            .map(|(k, v)| (Value::String(k.clone()), v.clone()))
+            .collect()),
+        ref value => Err(format!("Cannot iterate over {}", describe(value))),
+    }
+}
+
+/// Returns the value at `path`, or `null` if it does not exist.
+pub fn get(value: &Value, path: &[Value]) -> Result<Value, String> {
+    match path.split_first() {
+        None => Ok(value.clone()),
+        Some(_) if *value == Value::Null => Ok(Value::Null),
+        Some((key, rest)) => get(&index(value, key)?, rest),
+    }
+}
+
+/// Replaces the value at `path` with `new`, creating any missing tables and arrays along the way.
+pub fn set(value: Value, path: &[Value], new: Value) -> Result<Value, String> {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(new),
+    };
+
+    match (value, key) {
+        (Value::Table(mut table), Value::String(ref k)) => {
+            let child = table.entry(k.clone()).or_insert(Value::Null);
+            *child = set(std::mem::replace(child, Value::Null), rest, new)?;
+            Ok(Value::Table(table))
+        }
+        (Value::Null, Value::String(ref k)) => {
+            let mut table = Table::new();
+            table.insert(k.clone(), set(Value::Null, rest, new)?);
+            Ok(Value::Table(table))
+        }
+        (Value::Array(mut array), Value::Integer(_))
+        | (Value::Array(mut array), Value::Float(_)) => {
+            let i = array_index(array.len(), key)?;
+            if i >= array.len() {
+                array.resize(i + 1, Value::Null);
+            }
+            let child = std::mem::replace(&mut array[i], Value::Null);
+            array[i] = set(child, rest, new)?;
+            Ok(Value::Array(array))
+        }
+        (Value::Null, Value::Integer(_)) | (Value::Null, Value::Float(_)) => {
+            set(Value::Array(Vec::new()), path, new)
+        }
+        (Value::Array(mut array), Value::Table(ref bounds)) => {
+            let (start, end) = slice_component(bounds);
+            let (start, end) = slice_bounds(array.len(), &start, &end)?;
+            let current = Value::Array(array[start..end].to_vec());
+            match set(current, rest, new)? {
+                Value::Array(replacement) => {
+                    array.splice(start..end, replacement);
+                    Ok(Value::Array(array))
+                }
+                _ => Err("A slice of an array can only be assigned another array".to_string()),
+            }
+        }
+        (Value::Null, Value::Table(_)) => set(Value::Array(Vec::new()), path, new),
+        (value, key) => Err(cannot_index(&value, key)),
+    }
+}
+
+/// Deletes the values at each of `paths`.
+///
+/// Paths are deleted from last to first, so that removing an array element does not shift the
+/// indices of elements which are yet to be deleted.
+pub fn delete(mut value: Value, mut paths: Vec<Vec<Value>>) -> Result<Value, String> {
+    paths.sort_by(|a, b| compare_paths(b, a));
+    paths.dedup();
+    for path in paths {
+        value = delete_one(value, &path)?;
+    }
+    Ok(value)
+}
+
+fn delete_one(value: Value, path: &[Value]) -> Result<Value, String> {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(Value::Null),
+    };
+
+    match (value, key) {
+        (Value::Null, _) => Ok(Value::Null),
+        (Value::Table(mut table), Value::String(ref k)) => {
+            if rest.is_empty() {
+                table.shift_remove(k);
+            } else if let Some(child) = table.get_mut(k) {
+                *child = delete_one(std::mem::replace(child, Value::Null), rest)?;
+            }
+            Ok(Value::Table(table))
+        }
+        (Value::Array(mut array), Value::Integer(_))
+        | (Value::Array(mut array), Value::Float(_)) => {
+            let i = match array_index(array.len(), key) {
+                Ok(i) if i < array.len() => i,
+                _ => return Ok(Value::Array(array)),
+            };
+            if rest.is_empty() {
+                array.remove(i);
+            } else {
+                let child = std::mem::replace(&mut array[i], Value::Null);
+                array[i] = delete_one(child, rest)?;
+            }
+            Ok(Value::Array(array))
+        }
+        (Value::Array(mut array), Value::Table(ref bounds)) => {
+            let (start, end) = slice_component(bounds);
+            let (start, end) = slice_bounds(array.len(), &start, &end)?;
+            if rest.is_empty() {
+                array.drain(start..end);
+            } else {
+                let current = Value::Array(array[start..end].to_vec());
+                if let Value::Array(replacement) = delete_one(current, rest)? {
+                    array.splice(start..end, replacement);
+                }
+            }
+            Ok(Value::Array(array))
+        }
+        (value, key) => Err(format!(
+            "Cannot delete field at {} index of {}",
+            key.type_name(),
+            value.type_name()
+        )),
+    }
+}
+
+fn compare_paths(lhs: &[Value], rhs: &[Value]) -> Ordering {
+    for (l, r) in lhs.iter().zip(rhs) {
+        match ops::compare(l, r) {
+            Ordering::Equal => continue,
+            ordering => return ordering,
+        }
+    }
+    lhs.len().cmp(&rhs.len())
+}
+
+fn indices(haystack: &[Value], needle: &[Value]) -> Value {
+    if needle.is_empty() {
+        return Value::Null;
+    }
+
+    let found = haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| window.iter().zip(needle).all(|(a, b)| ops::equals(a, b)))
+        .map(|(i, _)| Value::Integer(i as i64))
+        .collect();
+
+    Value::Array(found)
+}
+
+fn array_index(len: usize, key: &Value) -> Result<usize, String> {
+    let i = key.as_f64().unwrap_or(0.0).floor();
+    let i = if i < 0.0 { i + len as f64 } else { i };
+    if i < 0.0 {
+        Err("Out of bounds negative array index".to_string())
+    } else {
+        Ok(i as usize)
+    }
+}
+
+fn slice_component(bounds: &Table) -> (Value, Value) {
+    let start = bounds.get("start").cloned().unwrap_or(Value::Null);
+    let end = bounds.get("end").cloned().unwrap_or(Value::Null);
+    (start, end)
+}
+
+fn slice_bounds(len: usize, start: &Value, end: &Value) -> Result<(usize, usize), String> {
+    let bound = |b: &Value, default: usize| match *b {
+        Value::Null => Ok(default as f64),
+        ref b => b
+            .as_f64()
+            .ok_or_else(|| "Start and end indices of an array slice must be numbers".to_string()),
+    };
+
+    let len_f = len as f64;
+    let clamp = |b: f64| {
+        let b = if b < 0.0 { b + len_f } else { b };
+        b.max(0.0).min(len_f)
+    };
+
+    let start = clamp(bound(start, 0)?).floor();
+    let end = clamp(bound(end, len)?).ceil().max(start);
+    Ok((start as usize, end as usize))
+}
+
+fn cannot_index(value: &Value, key: &Value) -> String {
+    match *key {
+        Value::String(ref k) => format!("Cannot index {} with \"{}\"", value.type_name(), k),
+        ref key => format!(
+            "Cannot index {} with {}",
+            value.type_name(),
+            key.type_name()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn array(elems: &[i64]) -> Value {
+        Value::Array(elems.iter().map(|&i| Value::Integer(i)).collect())
+    }
+
+    fn bounds(start: Value, end: Value) -> Value {
+        let mut table = Table::new();
+        table.insert("start".to_string(), start);
+        table.insert("end".to_string(), end);
+        Value::Table(table)
+    }
+
+    #[test]
+    fn slices() {
+        let value = array(&[0, 1, 2, 3, 4]);
+        let slice = |s, e| slice(&value, &s, &e).unwrap();
+        assert_eq!(slice(Value::Integer(2), Value::Null), array(&[2, 3, 4]));
+        assert_eq!(slice(Value::Null, Value::Integer(3)), array(&[0, 1, 2]));
+        assert_eq!(
+            slice(Value::Integer(1), Value::Integer(4)),
+            array(&[1, 2, 3])
+        );
+        assert_eq!(slice(Value::Integer(-2), Value::Null), array(&[3, 4]));
+        assert_eq!(slice(Value::Float(1.5), Value::Float(2.5)), array(&[1, 2]));
+        assert_eq!(slice(Value::Integer(4), Value::Integer(1)), array(&[]));
+        assert_eq!(
+            slice(Value::Integer(-10), Value::Integer(10)),
+            value.clone()
+        );
+
+        let s = Value::from("häßlich");
+        let sliced = super::slice(&s, &Value::Integer(1), &Value::Integer(-3));
+        assert_eq!(sliced, Ok(Value::from("äßl")));
+        assert!(super::slice(&Value::Integer(1), &Value::Null, &Value::Null).is_err());
+    }
+
+    #[test]
+    fn set_and_delete_slices() {
+        let value = array(&[0, 1, 2, 3, 4]);
+        let path = [bounds(Value::Integer(1), Value::Null)];
+        assert_eq!(set(value.clone(), &path, array(&[])), Ok(array(&[0])));
+
+        let path = [bounds(Value::Integer(1), Value::Integer(3))];
+        let replaced = set(value.clone(), &path, array(&[9]));
+        assert_eq!(replaced, Ok(array(&[0, 9, 3, 4])));
+        assert!(set(value.clone(), &path, Value::Integer(9)).is_err());
+
+        let paths = vec![
+            vec![Value::Integer(0)],
+            vec![bounds(Value::Integer(3), Value::Null)],
+        ];
+        assert_eq!(delete(value, paths), Ok(array(&[1, 2])));
+    }
+
+    #[test]
+    fn set_creates_containers() {
+        let path = [Value::from("a"), Value::Integer(2)];
+        let value = set(Value::Null, &path, Value::Boolean(true)).unwrap();
+        let inner = Value::Array(vec![Value::Null, Value::Null, Value::Boolean(true)]);
+        assert_eq!(get(&value, &path), Ok(Value::Boolean(true)));
+        assert_eq!(get(&value, &path[..1]), Ok(inner));
+        assert_eq!(get(&Value::Null, &path), Ok(Value::Null));
+    }
+}
@@ -0,0 +1,204 @@
+//! Streaming of values as `[path, leaf]` events, as done by `tostream` and `--stream`.
+//!
+//! Each leaf is an event `[path, leaf]`, where empty arrays and tables are leaves too, and the
+//! end of each non-empty array or table is an event `[path]` holding the path of its last member.
+//! A scalar or empty value at the top level is a single `[[], value]` event.
+
+use super::Value;
+
+/// The events of a value, in the order `tostream` produces them.
+///
+/// The value is taken apart as its events are produced, so members which were already streamed
+/// are not kept in memory.
+pub struct Events {
+    /// A value to be streamed as a single leaf, when the top-level value is not a container.
+    leaf: Option<Value>,
+    /// The members left to stream of each array or table being streamed, innermost last.
+    stack: Vec<Frame>,
+    /// The path of the innermost array or table being streamed.
+    path: Vec<Value>,
+}
+
+struct Frame {
+    members: Box<dyn Iterator<Item = (Value, Value)>>,
+    /// The key of the last member streamed so far.
+    last: Option<Value>,
+}
+
+impl Events {
+    pub fn new(value: Value) -> Self {
+        match members(value) {
+            Ok(members) => Events {
+                leaf: None,
+                stack: vec![Frame {
+                    members,
+                    last: None,
+                }],
+                path: Vec::new(),
+            },
+            Err(leaf) => Events {
+                leaf: Some(leaf),
+                stack: Vec::new(),
+                path: Vec::new(),
+            },
+        }
+    }
+}
+
+impl Iterator for Events {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        if let Some(leaf) = self.leaf.take() {
+            return Some(Value::Array(vec![Value::Array(Vec::new()), leaf]));
+        }
+
+        loop {
+            let frame = self.stack.last_mut()?;
+            match frame.members.next() {
+                Some((key, value)) => {
+                    frame.last = Some(key.clone());
+                    match members(value) {
+                        Ok(members) => {
+                            self.path.push(key);
+                            self.stack.push(Frame {
+                                members,
+                                last: None,
+                            });
+                        }
+                        Err(leaf) => {
+                            let mut path = self.path.clone();
+                            path.push(key);
+                            return Some(Value::Array(vec![Value::Array(path), leaf]));
+                        }
+                    }
+                }
+                None => {
+                    let last = frame.last.take();
+                    self.stack.pop();
+                    let mut path = self.path.clone();
+                    path.extend(last);
+                    self.path.pop();
+                    return Some(Value::Array(vec![Value::Array(path)]));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the members of a non-empty array or table, or gives the value back if it is a leaf.
+fn members(value: Value) -> Result<Box<dyn Iterator<Item = (Value, Value)>>, Value> {
+    match value {
+        Value::Array(array) if !array.is_empty() => Ok(Box::new(
+            array
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| (Value::Integer(i as i64), v)),
+        )),
+        Value::Table(table) if !table.is_empty() => Ok(Box::new(
+            table.into_iter().map(|(k, v)| (Value::String(k), v)),
+        )),
+        value => Err(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{value, Interpreter};
+
+    /// The definitions of `tostream` and `fromstream` which `builtin.tq` used to have.
+    const PRELUDE: &str = r#"
+        def tostream_tq:
+          path(def r: (.[]?|r), .; r) as $p |
+          getpath($p) |
+          reduce path(.[]?) as $q ([$p, .]; [$p+$q]);
+        def fromstream_tq(i): {x = null, e = false} as $init |
+          foreach i as $i ($init
+          ; if .e then $init else . end
+          | if $i|length == 2
+            then setpath(["e"]; $i[0]|length==0) | setpath(["x"]+$i[0]; $i[1])
+            else setpath(["e"]; $i[0]|length==1) end
+          ; if .e then .x else empty end);
+    "#;
+
+    fn eval(filter: &str, input: &str) -> Result<Vec<Value>, String> {
+        let filter = format!("{} {}", PRELUDE, filter).parse().unwrap();
+        Interpreter::new()
+            .eval(&filter, value(input))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn events() {
+        let input = value("{ a = 1, b = [true, [], { c = {} }], d = {} }");
+        let events: Vec<_> = Events::new(input).map(|e| e.to_string()).collect();
+        let expected = [
+            r#"[["a"], 1]"#,
+            r#"[["b", 0], true]"#,
+            r#"[["b", 1], []]"#,
+            r#"[["b", 2, "c"], {}]"#,
+            r#"[["b", 2, "c"]]"#,
+            r#"[["b", 2]]"#,
+            r#"[["d"], {}]"#,
+            r#"[["d"]]"#,
+        ];
+        assert_eq!(events, expected);
+
+        let events: Vec<_> = Events::new(Value::Integer(1)).collect();
+        assert_eq!(events, vec![value("[[], 1]")]);
+    }
+
+    #[test]
+    fn same_as_prelude() {
+        let inputs = [
+            "{ a = 1, b = [true, [], { c = {}, d = [[1]] }], e = 'x' }",
+            "[]",
+            "[[], {}, null]",
+            "'x'",
+        ];
+        for input in &inputs {
+            assert_eq!(eval("tostream", input), eval("tostream_tq", input));
+            assert_eq!(
+                eval("fromstream(tostream)", input),
+                eval("fromstream_tq(tostream_tq)", input)
+            );
+            assert_eq!(
+                eval("[. as $v | 1 | truncate_stream($v | tostream)]", input),
+                eval("[. as $v | 1 | truncate_stream($v | tostream_tq)]", input)
+            );
+        }
+
+        let events = [
+            "[[[], 1], [['a'], 2], [['a', 'b']], [[0], 3], [[0]], [null, 4]]",
+            "[[['a', 0], 1], [['a', 0]], [['b'], 2], [['b']]]",
+            "[[[]], [[1, 2], 'x'], [[1, 2]], [[1]]]",
+            "[['ab'], [[], 1]]",
+            "[[['a'], 1, 2]]",
+            "[[1, 2]]",
+            "[true]",
+            "[[['a'], 1], [[0], 2]]",
+            "[[null, 4]]",
+            "[[['a'], 1], [null, 2], [['a']]]",
+            "[[null]]",
+        ];
+        for events in &events {
+            // Only the error messages may differ.
+            match eval("fromstream_tq(.[])", events) {
+                Ok(expected) => assert_eq!(eval("fromstream(.[])", events), Ok(expected)),
+                Err(_) => assert!(eval("fromstream(.[])", events).is_err(), "{}", events),
+            }
+        }
+        // A `null` path is the top-level path, for both of them.
+        assert_eq!(
+            eval("fromstream(.[])", "[[null, 4]]"),
+            Ok(vec![Value::Integer(4)])
+        );
+    }
+
+    #[test]
+    fn not_paths() {
+        assert!(eval("path(fromstream(tostream))", "{ a = 1 }").is_err());
+        assert!(eval("path(tostream)", "{ a = 1 }").is_err());
+    }
+}
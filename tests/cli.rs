@@ -0,0 +1,341 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use tempfile::TempDir;
+
+const MANIFEST: &str = r#"# The package itself.
+[package]
+name = "tq"
+version = "0.1.0"
+
+[limits]
+mask = 0xff_ff
+"#;
+
+/// Runs `tq` with `args`, writing `stdin` to its standard input.
+fn tq(args: &[&str], stdin: &str) -> Output {
+    run(Command::new(env!("CARGO_BIN_EXE_tq")).args(args), stdin)
+}
+
+/// Like `tq`, but runs it in the directory `dir`.
+fn tq_in(dir: &TempDir, args: &[&str], stdin: &str) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_tq"));
+    run(command.args(args).current_dir(dir.path()), stdin)
+}
+
+fn run(command: &mut Command, stdin: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Returns a temporary directory holding `Cargo.toml`, with the contents of `MANIFEST`.
+fn manifest() -> (TempDir, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("Cargo.toml");
+    fs::write(&path, MANIFEST).unwrap();
+    (dir, path.to_str().unwrap().to_string())
+}
+
+fn read(path: impl AsRef<Path>) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+fn files(dir: &TempDir) -> Vec<String> {
+    let mut files: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    files
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+fn stderr(output: &Output) -> &str {
+    std::str::from_utf8(&output.stderr).unwrap()
+}
+
+#[test]
+fn in_place() {
+    let (dir, path) = manifest();
+    let filter = "if true then .package.version = '0.2.0' else . end";
+    let output = tq(&["-i", filter, &path], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(read(&path), MANIFEST.replace("0.1.0", "0.2.0"));
+    // The temporary file the new contents were written to was renamed over the original.
+    assert_eq!(files(&dir), ["Cargo.toml"]);
+}
+
+#[test]
+fn in_place_backup() {
+    let (dir, path) = manifest();
+    let output = tq(&["-i", "--backup", ".orig", ".limits.mask = 1", &path], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(read(&path), MANIFEST.replace("0xff_ff", "1"));
+    assert_eq!(read(dir.path().join("Cargo.toml.orig")), MANIFEST);
+    assert_eq!(files(&dir), ["Cargo.toml", "Cargo.toml.orig"]);
+}
+
+#[test]
+fn raw_output() {
+    let input = "a = \"x\"\nb = [\"y\", 1]\n";
+    let filter = ".a, .b[], { c = .a }";
+    assert_eq!(stdout(&tq(&["-r", filter], input)), "x\ny\n1\nc = \"x\"\n");
+    assert_eq!(stdout(&tq(&["-j", filter], input)), "xy1c = \"x\"");
+    let output = tq(&["--raw-output0", filter], input);
+    assert_eq!(stdout(&output), concat!("x\0y\0", "1\0c = \"x\"\0"));
+
+    let output = tq(&["--seq", ".a, .b"], input);
+    assert_eq!(stdout(&output), "\x1e\"x\"\n\x1e[\"y\", 1]\n");
+    let output = tq(&["--seq", "-r", ".a"], input);
+    assert_eq!(stdout(&output), "\x1ex\n");
+}
+
+#[test]
+fn raw_output0_nul() {
+    let output = tq(&["--raw-output0", ".a, \"b\\u0000c\", .a"], "a = \"x\"");
+    assert_eq!(stdout(&output), "x\0");
+    assert_eq!(output.status.code(), Some(5));
+    let message = "Cannot write a string containing NUL with --raw-output0";
+    assert!(stderr(&output).contains(message), "{}", stderr(&output));
+
+    // Other modes write such strings as they would any other.
+    let output = tq(&["-r", "\"b\\u0000c\""], "");
+    assert_eq!(stdout(&output), "b\0c\n");
+    let output = tq(&["\"b\\u0000c\""], "");
+    assert_eq!(stdout(&output), "\"b\\u0000c\"\n");
+}
+
+#[test]
+fn color_output() {
+    let input = "a = \"x\"\n";
+    assert!(stdout(&tq(&["-C", "."], input)).contains('\x1b'));
+    assert!(stdout(&tq(&["-C", "--output-format", "json", "."], input)).contains('\x1b'));
+    for &format in &["env", "sh", "flat"] {
+        let output = tq(&["-C", "--output-format", format, "."], input);
+        assert!(!stdout(&output).contains('\x1b'), "{}", format);
+    }
+}
+
+#[test]
+fn diff() {
+    let (dir, path) = manifest();
+    let output = tq_in(
+        &dir,
+        &["--diff", ".package.version = '0.2.0'", "Cargo.toml"],
+        "",
+    );
+    let expected = r#"--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -1,7 +1,7 @@
+ # The package itself.
+ [package]
+ name = "tq"
+-version = "0.1.0"
++version = "0.2.0"
+ 
+ [limits]
+ mask = 0xff_ff
+"#;
+    assert_eq!(stdout(&output), expected);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(read(&path), MANIFEST);
+
+    let output = tq_in(
+        &dir,
+        &["--diff", ".package.version = '0.1.0'", "Cargo.toml"],
+        "",
+    );
+    assert_eq!(stdout(&output), "");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = tq_in(&dir, &["--diff", "empty", "Cargo.toml"], "");
+    assert_eq!(stdout(&output), "");
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn in_place_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (_dir, path) = manifest();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    let output = tq(&["-i", ".limits.mask = 1", &path], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+}
+
+#[cfg(unix)]
+#[test]
+fn in_place_symlink() {
+    let (dir, path) = manifest();
+    let link = dir.path().join("link.toml");
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+    let link = link.to_str().unwrap();
+    let output = tq(&["-i", "--backup", ".orig", ".limits.mask = 1", link], "");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    // The file the link points to is edited, and the link is kept.
+    assert_eq!(read(&path), MANIFEST.replace("0xff_ff", "1"));
+    assert!(fs::symlink_metadata(link).unwrap().file_type().is_symlink());
+    assert_eq!(read(dir.path().join("Cargo.toml.orig")), MANIFEST);
+    assert_eq!(files(&dir), ["Cargo.toml", "Cargo.toml.orig", "link.toml"]);
+}
+
+#[test]
+fn in_place_refusals() {
+    let cases = [
+        ("empty", "expected exactly 1 output, found 0"),
+        (".limits.mask = 1, .", "expected exactly 1 output, found 2"),
+        (".package", "the output is not an update of the document"),
+        (
+            "{ limits = .limits }",
+            "the output is not an update of the document",
+        ),
+        (".package.name", "cannot write string as a document"),
+    ];
+    for &(filter, message) in &cases {
+        let (dir, path) = manifest();
+        let output = tq(&["-i", filter, &path], "");
+        assert_eq!(output.status.code(), Some(5), "{}", filter);
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+        assert!(stderr(&output).ends_with("tq: no files were modified\n"));
+        assert_eq!(read(&path), MANIFEST);
+        assert_eq!(files(&dir), ["Cargo.toml"]);
+    }
+
+    // No file is modified if any of them cannot be.
+    let (dir, path) = manifest();
+    let other = dir.path().join("other.toml");
+    fs::write(&other, "limits = 1\n").unwrap();
+    let output = tq(
+        &["-i", ".limits.mask = 1", &path, other.to_str().unwrap()],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(read(&path), MANIFEST);
+}
+
+#[test]
+fn split_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = "[[member]]\nname = \"a\"\n\n[[member]]\nname = \"b\"\n";
+    let split = "\"out/\" + .name + \".toml\"";
+    let run = |args: &[&str]| tq_in(&dir, args, input);
+
+    // Missing directories are created.
+    let output = run(&["--split-output", split, ".member[]"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(read(dir.path().join("out/a.toml")), "name = \"a\"\n");
+    assert_eq!(read(dir.path().join("out/b.toml")), "name = \"b\"\n");
+
+    // Existing files are only replaced with --force.
+    let output = run(&["--split-output", split, ".member[] | .n = 1"]);
+    assert_eq!(output.status.code(), Some(5));
+    let message = "out/a.toml already exists, use --force to overwrite it";
+    assert!(stderr(&output).contains(message), "{}", stderr(&output));
+    assert_eq!(read(dir.path().join("out/a.toml")), "name = \"a\"\n");
+
+    let output = run(&["--split-output", split, "--force", ".member[] | .n = 1"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(read(dir.path().join("out/a.toml")), "name = \"a\"\nn = 1\n");
+    assert_eq!(read(dir.path().join("out/b.toml")), "name = \"b\"\nn = 1\n");
+}
+
+#[test]
+fn split_output_side_effects() {
+    let dir = tempfile::tempdir().unwrap();
+    let split = "(.a | setcomment(\"named\")) as $_ | \"out\" + (.b | tostring) + \".toml\"";
+    let args = ["--split-output", split, "(.b = 1), (.b = 2)"];
+    let output = tq_in(&dir, &args, "a = 0\n");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    // The comment set by the file name filter is not written to either output.
+    assert_eq!(read(dir.path().join("out1.toml")), "a = 0\nb = 1\n");
+    assert_eq!(read(dir.path().join("out2.toml")), "a = 0\nb = 2\n");
+}
+
+#[test]
+fn closed_stdout() {
+    let input: String = (0..10_000).map(|i| format!("k{} = {}\n", i, i)).collect();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tq"))
+        .args(["-c", "tostream"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let mut line = [0; 16];
+    child.stdout.take().unwrap().read_exact(&mut line).unwrap();
+    let mut stderr = String::new();
+    child
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+
+    assert_eq!(stderr, "");
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn stream_incrementally() {
+    let cases = [
+        ("[1, 2,", "[[0],1]\n", " 3]", "[[1],2]\n[[2],3]\n[[2]]\n"),
+        (
+            "[a]\nx = 1\n[b]\n",
+            "[[\"a\", \"x\"], 1]\n",
+            "y = 2\n",
+            "[[\"a\", \"x\"]]\n[[\"b\", \"y\"], 2]\n[[\"b\", \"y\"]]\n[[\"b\"]]\n",
+        ),
+    ];
+    for &(start, first, rest, events) in &cases {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tq"))
+            .args(["-c", "--stream", "."])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+        // The first event is written before the rest of the input is.
+        stdin.write_all(start.as_bytes()).unwrap();
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        assert_eq!(line, first);
+
+        stdin.write_all(rest.as_bytes()).unwrap();
+        drop(stdin);
+        let mut lines = String::new();
+        stdout.read_to_string(&mut lines).unwrap();
+        assert_eq!(lines, events);
+        assert_eq!(child.wait().unwrap().code(), Some(0));
+    }
+}